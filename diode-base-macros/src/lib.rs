@@ -1,6 +1,12 @@
 use proc_macro::TokenStream;
+use proc_macro2::Span;
 use quote::quote;
-use syn::{ItemStruct, LitStr, parse_macro_input};
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned as _;
+use syn::{Error, Expr, ExprLit, ImplItem, ItemImpl, ItemStruct, Lit, LitStr, Meta, Token};
+use syn::{FnArg, parse_macro_input};
+
+const ARGS_ATTR: &str = "args";
 
 #[proc_macro_attribute]
 pub fn config_section(args: TokenStream, input: TokenStream) -> TokenStream {
@@ -18,7 +24,188 @@ pub fn config_section(args: TokenStream, input: TokenStream) -> TokenStream {
                 #key
             }
         }
+
+        impl #struct_name {
+            /// Reads this section from the `Config` component, like
+            /// `Config::extract`.
+            #[allow(dead_code)]
+            pub fn from_app(
+                ctx: &::diode::AppContext,
+            ) -> ::std::result::Result<Self, ::diode::AppError> {
+                <diode_base::Config as ::diode::Extract<Self>>::extract(ctx)
+            }
+        }
     };
 
     TokenStream::from(expanded)
 }
+
+/// Attribute macro for impl blocks implementing a command as a service
+#[proc_macro_attribute]
+pub fn command(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let command_attr = match parse_command_attribute(attr) {
+        Ok(v) => v,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    match syn::parse::<ItemImpl>(item) {
+        Ok(item_impl) => match handle_command_impl(item_impl, command_attr) {
+            Ok(v) => v.into(),
+            Err(err) => err.to_compile_error().into(),
+        },
+        Err(_) => Error::new(
+            Span::call_site(),
+            "#[command] can only be applied to impl blocks",
+        )
+        .to_compile_error()
+        .into(),
+    }
+}
+
+struct CommandAttribute {
+    name: LitStr,
+    about: Option<LitStr>,
+}
+
+fn parse_command_attribute(attr: TokenStream) -> Result<CommandAttribute, Error> {
+    let meta_items: Punctuated<Meta, Token![,]> =
+        syn::parse::Parser::parse2(Punctuated::parse_terminated, attr.into())?;
+
+    let mut name = None;
+    let mut about = None;
+
+    for meta in meta_items {
+        match meta {
+            Meta::NameValue(nv) if nv.path.is_ident("name") => {
+                name = Some(parse_lit_str(&nv.value)?);
+            }
+            Meta::NameValue(nv) if nv.path.is_ident("about") => {
+                about = Some(parse_lit_str(&nv.value)?);
+            }
+            _ => {
+                return Err(Error::new_spanned(
+                    meta,
+                    "Unsupported attribute format in #[command]",
+                ));
+            }
+        }
+    }
+
+    let name = name.ok_or_else(|| {
+        Error::new(
+            Span::call_site(),
+            "#[command] requires a `name = \"...\"` argument",
+        )
+    })?;
+
+    Ok(CommandAttribute { name, about })
+}
+
+fn parse_lit_str(expr: &Expr) -> Result<LitStr, Error> {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Str(lit), ..
+        }) => Ok(lit.clone()),
+        _ => Err(Error::new_spanned(expr, "Expected a string literal")),
+    }
+}
+
+fn handle_command_impl(
+    mut item_impl: ItemImpl,
+    attr: CommandAttribute,
+) -> Result<proc_macro2::TokenStream, Error> {
+    if item_impl.trait_.is_some() {
+        return Err(Error::new(
+            item_impl.span(),
+            "Trait impls are not supported",
+        ));
+    }
+
+    let mut args_method = None;
+    let mut run_method = None;
+
+    for item in &mut item_impl.items {
+        if let ImplItem::Fn(method) = item {
+            let attrs_len = method.attrs.len();
+            method.attrs.retain(|attr| !attr.path().is_ident(ARGS_ATTR));
+            if method.attrs.len() != attrs_len {
+                if args_method.is_some() {
+                    return Err(Error::new(
+                        method.sig.span(),
+                        "Only one #[args] method allowed",
+                    ));
+                }
+                args_method = Some(method.sig.ident.clone());
+            }
+            if method.sig.ident == "run" {
+                run_method = Some(method.sig.clone());
+            }
+        }
+    }
+
+    let run_method =
+        run_method.ok_or_else(|| Error::new(item_impl.span(), "No run method found"))?;
+    if run_method.asyncness.is_none() {
+        return Err(Error::new(
+            run_method.span(),
+            "Command run method must be async",
+        ));
+    }
+    if !matches!(run_method.inputs.first(), Some(FnArg::Receiver(_))) {
+        return Err(Error::new(
+            run_method.span(),
+            "Command run method must take &self",
+        ));
+    }
+    let run_call = match run_method.inputs.len() {
+        1 => quote! {
+            let _ = matches;
+            command.run().await?;
+        },
+        2 => quote! {
+            command.run(matches).await?;
+        },
+        _ => {
+            return Err(Error::new(
+                run_method.inputs.span(),
+                "Command run method takes &self and optionally ArgMatches",
+            ));
+        }
+    };
+
+    let self_ty = &item_impl.self_ty;
+    let (impl_generics, _, where_clause) = item_impl.generics.split_for_impl();
+    let name = &attr.name;
+    let about = attr.about.map(|about| quote! { .about(#about) });
+    let args = args_method.map(|method| quote! { let command = Self::#method(command); });
+
+    Ok(quote! {
+        #item_impl
+
+        impl #impl_generics ::diode_base::Command for #self_ty #where_clause {
+            fn command() -> ::diode_base::clap::Command
+            where
+                Self: Sized,
+            {
+                let command = ::diode_base::clap::Command::new(#name)#about;
+                #args
+                command
+            }
+
+            async fn run(
+                app: ::std::sync::Arc<::diode::App>,
+                matches: ::diode_base::clap::ArgMatches,
+            ) -> ::std::result::Result<(), ::diode::StdError> {
+                let command = app
+                    .get_component::<::std::sync::Arc<Self>>()
+                    .ok_or_else(|| {
+                        format!(
+                            "Command {} is not registered as a service",
+                            ::std::any::type_name::<Self>()
+                        )
+                    })?;
+                #run_call
+                Ok(())
+            }
+        }
+    })
+}
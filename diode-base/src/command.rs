@@ -36,7 +36,7 @@
 //! }
 //! ```
 
-use std::any::TypeId;
+use std::any::{TypeId, type_name};
 use std::collections::{BTreeMap, HashMap};
 use std::marker::PhantomData;
 use std::mem::take;
@@ -44,10 +44,14 @@ use std::process::ExitCode;
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use clap::{Arg, ArgAction, ArgMatches};
-use diode::{App, AppBuilder};
+use clap::{Arg, ArgAction, ArgMatches, FromArgMatches as _};
+use diode::{AddServiceExt as _, App, AppBuilder, MergeComponent, Service, StdError};
 
-use crate::{CancellationToken, Config, Metrics, RunDaemonsExt, Tracing};
+use crate::daemon::daemon_count;
+use crate::{
+    CancellationToken, Config, ConfigSource, FileConfigSource, Metrics, RunDaemonsExt,
+    ShutdownCause, ShutdownReason, Tracing,
+};
 
 /// Trait for defining CLI commands that can access the application's dependency container.
 ///
@@ -109,6 +113,32 @@ use crate::{CancellationToken, Config, Metrics, RunDaemonsExt, Tracing};
 ///     }
 /// }
 /// ```
+///
+/// Fallible command:
+///
+/// ```rust
+/// use diode_base::{Command, Config};
+/// use diode::{App, StdError};
+/// use clap::{ArgMatches, Command as ClapCommand};
+/// use std::sync::Arc;
+///
+/// struct DumpCommand;
+///
+/// impl Command for DumpCommand {
+///     fn command() -> ClapCommand {
+///         ClapCommand::new("dump")
+///     }
+///
+///     async fn run(app: Arc<App>, _matches: ArgMatches) -> Result<(), StdError> {
+///         let config = app
+///             .get_component_ref::<Config>()
+///             .ok_or("Config component is missing")?;
+///         let name: String = config.get("name")?;
+///         println!("{name}");
+///         Ok(())
+///     }
+/// }
+/// ```
 pub trait Command: Send + Sync {
     /// Defines the CLI command structure for this command.
     ///
@@ -127,6 +157,10 @@ pub trait Command: Send + Sync {
     /// This is the main entry point for command execution. The method receives
     /// the application container and the parsed command-line arguments.
     ///
+    /// The default implementation calls [`run`](Command::run), returning
+    /// `ExitCode::SUCCESS` on `Ok` and logging the error and returning
+    /// `ExitCode::FAILURE` on `Err`. Override it to choose exit codes directly.
+    ///
     /// # Arguments
     ///
     /// * `app` - Shared reference to the application container
@@ -139,8 +173,142 @@ pub trait Command: Send + Sync {
         app: Arc<App>,
         matches: ArgMatches,
     ) -> impl std::future::Future<Output = ExitCode> + Send {
+        async move {
+            match Self::run(app, matches).await {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(err) => {
+                    tracing::error!(error = %err, "Command failed");
+                    ExitCode::FAILURE
+                }
+            }
+        }
+    }
+
+    /// Executes the command, reporting failure as an error.
+    ///
+    /// Called by the default [`main`](Command::main), which maps the result to
+    /// an `ExitCode`. Implement this instead of `main` to use `?` in the command
+    /// body.
+    ///
+    /// # Arguments
+    ///
+    /// * `app` - Shared reference to the application container
+    /// * `matches` - Parsed command-line arguments for this command
+    ///
+    /// # Errors
+    ///
+    /// The default implementation always returns an error, since the command
+    /// implements neither `run` nor `main`.
+    fn run(
+        app: Arc<App>,
+        matches: ArgMatches,
+    ) -> impl std::future::Future<Output = Result<(), StdError>> + Send {
         let _ = (app, matches);
-        async move { ExitCode::FAILURE }
+        async move { Err(format!("Command {} is not implemented", type_name::<Self>()).into()) }
+    }
+}
+
+/// A [`Command`] that receives its arguments parsed into a typed struct.
+///
+/// `Args` is usually a `#[derive(clap::Args)]` struct (enable clap's `derive`
+/// feature): its arguments are added to [`command`](TypedCommand::command) and
+/// [`main`](TypedCommand::main) receives the parsed value instead of raw
+/// `ArgMatches`. Every `TypedCommand` is a [`Command`], registered with
+/// [`add_command`](AddCommandExt::add_command) as usual; implement [`Command`]
+/// directly for advanced cases.
+///
+/// ```rust,ignore
+/// use diode::{App, StdError};
+/// use diode_base::TypedCommand;
+/// use std::sync::Arc;
+///
+/// #[derive(clap::Args)]
+/// struct GreetArgs {
+///     /// Name to greet
+///     name: String,
+///     #[arg(long, default_value_t = 1)]
+///     times: u32,
+/// }
+///
+/// struct GreetCommand;
+///
+/// impl TypedCommand for GreetCommand {
+///     type Args = GreetArgs;
+///
+///     fn command() -> clap::Command {
+///         clap::Command::new("greet").about("Greets a user")
+///     }
+///
+///     async fn run(_app: Arc<App>, args: GreetArgs) -> Result<(), StdError> {
+///         for _ in 0..args.times {
+///             println!("Hello, {}!", args.name);
+///         }
+///         Ok(())
+///     }
+/// }
+/// ```
+pub trait TypedCommand: Send + Sync {
+    /// Arguments of the command.
+    type Args: clap::Args + Send;
+
+    /// Defines the CLI command, without the arguments of [`Args`](TypedCommand::Args)
+    /// which are added to it.
+    fn command() -> clap::Command
+    where
+        Self: Sized;
+
+    /// Executes the command with the parsed arguments.
+    ///
+    /// Like [`Command::main`], the default implementation calls
+    /// [`run`](TypedCommand::run) and maps its result to an `ExitCode`.
+    fn main(app: Arc<App>, args: Self::Args) -> impl std::future::Future<Output = ExitCode> + Send {
+        async move {
+            match Self::run(app, args).await {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(err) => {
+                    tracing::error!(error = %err, "Command failed");
+                    ExitCode::FAILURE
+                }
+            }
+        }
+    }
+
+    /// Executes the command, reporting failure as an error.
+    ///
+    /// # Errors
+    ///
+    /// The default implementation always returns an error, since the command
+    /// implements neither `run` nor `main`.
+    fn run(
+        app: Arc<App>,
+        args: Self::Args,
+    ) -> impl std::future::Future<Output = Result<(), StdError>> + Send {
+        let _ = (app, args);
+        async move { Err(format!("Command {} is not implemented", type_name::<Self>()).into()) }
+    }
+}
+
+impl<T> Command for T
+where
+    T: TypedCommand,
+{
+    fn command() -> clap::Command
+    where
+        Self: Sized,
+    {
+        <T::Args as clap::Args>::augment_args(<T as TypedCommand>::command())
+    }
+
+    /// Parses the [`Args`](TypedCommand::Args) and calls [`TypedCommand::main`],
+    /// failing if they do not match.
+    async fn main(app: Arc<App>, matches: ArgMatches) -> ExitCode {
+        match T::Args::from_arg_matches(&matches) {
+            Ok(args) => <T as TypedCommand>::main(app, args).await,
+            Err(err) => {
+                tracing::error!(error = %err, "Invalid command arguments");
+                ExitCode::FAILURE
+            }
+        }
     }
 }
 
@@ -237,7 +405,17 @@ impl CommandRegistry {
     ///
     /// A `clap::Command` configured with all registered subcommands.
     pub fn build_cli(&self) -> clap::Command {
-        let mut cli = clap::Command::default()
+        self.build_cli_from(clap::Command::default())
+    }
+
+    /// Builds the CLI interface on top of a custom root command.
+    ///
+    /// Like [`build_cli`](CommandRegistry::build_cli), but starts from `root`,
+    /// so the binary name, version, about text and global arguments can be
+    /// customized. The registered subcommands and the config options are added
+    /// to it.
+    pub fn build_cli_from(&self, root: clap::Command) -> clap::Command {
+        let mut cli = root
             .subcommand_required(true)
             .arg(Arg::new("config").long("config").short('c').required(true))
             .arg(
@@ -245,6 +423,19 @@ impl CommandRegistry {
                     .long("config-override")
                     .short('o')
                     .action(ArgAction::Append),
+            )
+            .arg(
+                Arg::new("set")
+                    .long("set")
+                    .value_name("PATH=VALUE")
+                    .help("Overrides a config value, e.g. database.port=5433")
+                    .action(ArgAction::Append)
+                    .value_parser(|arg: &str| {
+                        Config::new()
+                            .set_override(arg)
+                            .map(|_| arg.to_owned())
+                            .map_err(|err| err.to_string())
+                    }),
             );
         let mut commands = BTreeMap::new();
         for command in self.commands.values() {
@@ -260,18 +451,35 @@ impl CommandRegistry {
     /// # Arguments
     ///
     /// * `app` - Shared reference to the application container
+    /// * `cli` - CLI that `matches` were parsed with, usually built with
+    ///   [`build_cli_from`](CommandRegistry::build_cli_from)
     /// * `matches` - Parsed command-line arguments including subcommand selection
     ///
     /// # Returns
     ///
-    /// Returns the exit code from the executed command.
-    pub async fn run_main(&self, app: Arc<App>, mut matches: ArgMatches) -> ExitCode {
-        let (name, matches) = matches.remove_subcommand().unwrap();
-        let command = self
+    /// Returns the exit code from the executed command. If `matches` has no
+    /// subcommand, the help of `cli` is printed instead; if its subcommand is
+    /// not registered, an error is. Both return [`ExitCode::FAILURE`].
+    pub async fn run_main(
+        &self,
+        app: Arc<App>,
+        mut cli: clap::Command,
+        mut matches: ArgMatches,
+    ) -> ExitCode {
+        // `build_cli` requires a subcommand, but `matches` may have been parsed
+        // by another CLI.
+        let Some((name, matches)) = matches.remove_subcommand() else {
+            eprintln!("{}", cli.render_help());
+            return ExitCode::FAILURE;
+        };
+        let Some(command) = self
             .commands
             .values()
             .find(|v| v.command().get_name() == name)
-            .unwrap();
+        else {
+            eprintln!("Unknown command: {name}");
+            return ExitCode::FAILURE;
+        };
         command.main(app, matches).await
     }
 
@@ -294,6 +502,13 @@ impl CommandRegistry {
     }
 }
 
+impl MergeComponent for CommandRegistry {
+    fn merge(&mut self, other: Self) -> Result<(), StdError> {
+        self.commands.extend(other.commands);
+        Ok(())
+    }
+}
+
 struct CommandWrapper<T>(PhantomData<T>)
 where
     T: Command;
@@ -379,11 +594,7 @@ impl AddCommandExt for AppBuilder {
     where
         T: Command + 'static,
     {
-        if !self.has_component::<CommandRegistry>() {
-            self.add_component(CommandRegistry::default());
-        }
-        self.get_component_mut::<CommandRegistry>()
-            .unwrap()
+        self.get_or_insert_merge_component::<CommandRegistry>()
             .add_command::<T>();
         self
     }
@@ -397,6 +608,115 @@ impl AddCommandExt for AppBuilder {
     }
 }
 
+/// Extension trait for `AppBuilder` to register commands resolved from the
+/// dependency-injection container.
+///
+/// The command type `T` is a [`Service`]: it is built by the container together
+/// with its dependencies, so its fields can be injected exactly like a
+/// service's. The service is added automatically if not already present. The
+/// `#[command]` attribute macro generates the [`Command`] implementation for
+/// such a type from an `async fn run(&self, matches: ArgMatches)` method.
+///
+/// # Examples
+///
+/// ```rust
+/// use diode::{App, Service, StdError};
+/// use diode_base::{AddCommandServiceExt, Config, command, config_section};
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// #[config_section("database")]
+/// struct DatabaseConfig {
+///     url: String,
+/// }
+///
+/// #[derive(Service)]
+/// struct MigrateCommand {
+///     #[inject(Config)]
+///     config: DatabaseConfig,
+/// }
+///
+/// #[command(name = "migrate", about = "Applies database migrations")]
+/// impl MigrateCommand {
+///     async fn run(&self) -> Result<(), StdError> {
+///         println!("Migrating {}", self.config.url);
+///         Ok(())
+///     }
+/// }
+///
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let app = App::builder()
+///     .add_component(Config::new().with("database", serde_json::json!({"url": "db"})))
+///     .add_command_service::<MigrateCommand>()
+///     .build()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Arguments are declared by an associated function marked `#[args]`, which
+/// receives the `clap::Command` built from `name` and `about`:
+///
+/// ```rust
+/// use diode::{Service, StdError};
+/// use diode_base::clap::{self, Arg, ArgMatches};
+/// use diode_base::command;
+///
+/// #[derive(Service)]
+/// struct GreetCommand;
+///
+/// #[command(name = "greet")]
+/// impl GreetCommand {
+///     #[args]
+///     fn args(command: clap::Command) -> clap::Command {
+///         command.arg(Arg::new("name").required(true))
+///     }
+///
+///     async fn run(&self, matches: ArgMatches) -> Result<(), StdError> {
+///         println!("Hello, {}!", matches.get_one::<String>("name").unwrap());
+///         Ok(())
+///     }
+/// }
+/// ```
+pub trait AddCommandServiceExt {
+    /// Registers the [`Service`] `T` as a command.
+    ///
+    /// # Returns
+    ///
+    /// Returns `&mut Self` for method chaining.
+    fn add_command_service<T>(&mut self) -> &mut Self
+    where
+        T: Service<Handle = Arc<T>> + Command + 'static;
+
+    /// Checks if `T` is registered as a command service.
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` if the command service is registered, `false` otherwise.
+    fn has_command_service<T>(&self) -> bool
+    where
+        T: Service<Handle = Arc<T>> + Command + 'static;
+}
+
+impl AddCommandServiceExt for AppBuilder {
+    fn add_command_service<T>(&mut self) -> &mut Self
+    where
+        T: Service<Handle = Arc<T>> + Command + 'static,
+    {
+        if !self.has_service::<T>() {
+            self.add_service::<T>();
+        }
+        self.add_command::<T>()
+    }
+
+    fn has_command_service<T>(&self) -> bool
+    where
+        T: Service<Handle = Arc<T>> + Command + 'static,
+    {
+        self.has_service::<T>() && self.has_command::<T>()
+    }
+}
+
 /// Extension trait for `AppBuilder` to run the main CLI application.
 ///
 /// This trait provides the main entry point for CLI applications, handling
@@ -408,10 +728,14 @@ pub trait RunMainExt {
     /// 1. Registers default commands (server, config) if not already present
     /// 2. Builds the CLI interface from registered commands
     /// 3. Parses command-line arguments
-    /// 4. Loads and merges configuration files
-    /// 5. Sets up tracing/logging
-    /// 6. Builds the application
-    /// 7. Executes the selected command
+    /// 4. Loads and merges configuration files, unless a [`Config`] component
+    ///    is already registered (load one with [`Config::from_sources`] to
+    ///    read config from elsewhere), then applies the `--set path=value`
+    ///    overrides with [`Config::set_override`]
+    /// 5. Registers the parsed arguments as the [`RootMatches`] component
+    /// 6. Sets up tracing/logging
+    /// 7. Builds the application
+    /// 8. Executes the selected command
     ///
     /// # Returns
     ///
@@ -429,10 +753,40 @@ pub trait RunMainExt {
     /// }
     /// ```
     fn run_main(&mut self) -> impl std::future::Future<Output = ExitCode> + Send;
+
+    /// Runs the main CLI application with a custom root command.
+    ///
+    /// Same as [`run_main`](RunMainExt::run_main), but the CLI is built on top
+    /// of `root` with [`CommandRegistry::build_cli_from`], so the binary can set
+    /// its name, version and about text or add arguments. Commands only receive
+    /// the matches of their subcommand: mark root arguments they need with
+    /// [`Arg::global`](clap::Arg::global), or read them from the
+    /// [`RootMatches`] component.
+    ///
+    /// ```rust,no_run
+    /// use diode::App;
+    /// use diode_base::{RunMainExt, clap};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> std::process::ExitCode {
+    ///     let root = clap::Command::new("my-service")
+    ///         .version("1.0.0")
+    ///         .about("My service");
+    ///     App::builder().run_main_with(root).await
+    /// }
+    /// ```
+    fn run_main_with(
+        &mut self,
+        root: clap::Command,
+    ) -> impl std::future::Future<Output = ExitCode> + Send;
 }
 
 impl RunMainExt for AppBuilder {
     async fn run_main(&mut self) -> ExitCode {
+        self.run_main_with(clap::Command::default()).await
+    }
+
+    async fn run_main_with(&mut self, root: clap::Command) -> ExitCode {
         if !self.has_command::<ServerCommand>() {
             self.add_command::<ServerCommand>();
         }
@@ -440,36 +794,92 @@ impl RunMainExt for AppBuilder {
             self.add_command::<ConfigCommand>();
         }
         // Setup cli.
-        let command_registry = take(&mut *self.get_component_mut::<CommandRegistry>().unwrap());
-        let cli = command_registry.build_cli();
-        let matches = cli.get_matches();
+        let command_registry = take(
+            &mut *self
+                .get_component_mut::<CommandRegistry>()
+                .expect("CommandRegistry component is missing"),
+        );
+        let mut cli = command_registry.build_cli_from(root);
+        let matches = cli.get_matches_mut();
         // Setup config.
         if !self.has_component::<Config>() {
             let config_path = matches.get_one::<String>("config").unwrap();
-            let mut config = Config::parse_file(config_path).await.unwrap();
             let config_override_paths = matches
                 .get_many::<String>("config-override")
                 .unwrap_or_default();
-            for path in config_override_paths {
-                let config_override = Config::parse_file(path).await.unwrap();
-                config.merge_from(config_override).unwrap();
-            }
+            let sources = [config_path]
+                .into_iter()
+                .chain(config_override_paths)
+                .map(|path| Box::new(FileConfigSource::new(path)) as Box<dyn ConfigSource>)
+                .collect();
+            let config = Config::from_sources(sources).await.unwrap();
             self.add_component(config);
         }
+        if let Some(overrides) = matches.get_many::<String>("set") {
+            let mut config = self
+                .get_component_mut::<Config>()
+                .expect("Config component is missing");
+            for arg in overrides {
+                config.set_override(arg).unwrap();
+            }
+        }
+        self.add_component(RootMatches(matches.clone()));
         // Setup tracing.
         Tracing::build(&*self).unwrap();
         // Setup metrics.
         Metrics::build(&*self).unwrap();
         // Start app.
-        let app = Arc::new(self.build().await.unwrap());
-        command_registry.run_main(app, matches).await
+        let app = self.build().await.unwrap().into_handle();
+        command_registry.run_main(app, cli, matches).await
+    }
+}
+
+/// Component holding the arguments matched by the root command, registered by
+/// [`RunMainExt::run_main`] before the app is built.
+///
+/// Commands only receive the matches of their own subcommand; use this to
+/// read root-level arguments such as a `--tenant` flag:
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use diode::{App, StdError};
+/// use diode_base::{Command, RootMatches, clap};
+///
+/// struct ReportCommand;
+///
+/// impl Command for ReportCommand {
+///     fn command() -> clap::Command {
+///         clap::Command::new("report")
+///     }
+///
+///     async fn run(app: Arc<App>, _matches: clap::ArgMatches) -> Result<(), StdError> {
+///         let root = app.get_component_ref::<RootMatches>().ok_or("Not run by run_main")?;
+///         let tenant = root.get_one::<String>("tenant");
+///         println!("Report for {tenant:?}");
+///         Ok(())
+///     }
+/// }
+/// ```
+///
+/// The subcommand matches are included too, as parsed.
+#[derive(Clone, Debug)]
+pub struct RootMatches(pub ArgMatches);
+
+impl std::ops::Deref for RootMatches {
+    type Target = ArgMatches;
+
+    fn deref(&self) -> &ArgMatches {
+        &self.0
     }
 }
 
 /// Built-in server command that runs all registered daemons.
 ///
 /// This command starts the application in server mode, running all registered
-/// daemon services until a shutdown signal (Ctrl+C) is received.
+/// daemon services until a shutdown signal is received: Ctrl+C (SIGINT) or, on
+/// Unix, SIGTERM as sent by systemd and Kubernetes. If no daemon is
+/// registered it logs a warning and returns right away.
 pub struct ServerCommand;
 
 impl Command for ServerCommand {
@@ -480,28 +890,100 @@ impl Command for ServerCommand {
         clap::Command::new("server")
     }
 
-    async fn main(app: Arc<App>, _matches: ArgMatches) -> ExitCode {
-        let shutdown = CancellationToken::new();
-        tokio::spawn({
-            let shutdown = shutdown.clone();
-            async move {
-                tokio::signal::ctrl_c()
-                    .await
-                    .expect("Failed to listen for ctrl_c");
-                shutdown.cancel();
+    async fn run(app: Arc<App>, _matches: ArgMatches) -> Result<(), StdError> {
+        run_server(app).await
+    }
+}
+
+/// Extension trait for `AppBuilder` to run a server app without the CLI.
+pub trait BuildAndRunExt {
+    /// Builds the application and runs its daemons like [`ServerCommand`],
+    /// until Ctrl+C (SIGINT) or, on Unix, SIGTERM.
+    ///
+    /// Unlike [`RunMainExt::run_main`], it parses no arguments and sets up
+    /// neither config, tracing nor metrics: register the [`Config`] component
+    /// and call [`Tracing::build`] beforehand if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the build, or the first error reported by a daemon.
+    ///
+    /// ```rust,no_run
+    /// use diode::{App, StdError};
+    /// use diode_base::BuildAndRunExt as _;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), StdError> {
+    ///     App::builder().build_and_run().await
+    /// }
+    /// ```
+    fn build_and_run(&mut self) -> impl std::future::Future<Output = Result<(), StdError>> + Send;
+}
+
+impl BuildAndRunExt for AppBuilder {
+    async fn build_and_run(&mut self) -> Result<(), StdError> {
+        let app = self.build().await?.into_handle();
+        run_server(app).await
+    }
+}
+
+/// Runs the daemons of `app` until a shutdown signal.
+async fn run_server(app: Arc<App>) -> Result<(), StdError> {
+    if daemon_count(&app) == 0 {
+        tracing::warn!(
+            "No daemons registered, the server has nothing to run; \
+             is the HTTP server plugin added?"
+        );
+        return Ok(());
+    }
+    let shutdown = CancellationToken::new();
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        let reason = app.get_component::<ShutdownReason>();
+        let signal = shutdown_signal();
+        async move {
+            signal.await;
+            if let Some(reason) = reason {
+                reason.set(ShutdownCause::Signal);
+            }
+            shutdown.cancel();
+        }
+    });
+    app.run_daemons(shutdown).await
+}
+
+/// Returns a future that resolves on Ctrl+C or, on Unix, SIGTERM.
+///
+/// The SIGTERM handler is installed before returning, so signals sent after
+/// this call are not lost even if the future is polled later.
+fn shutdown_signal() -> impl Future<Output = ()> + Send {
+    #[cfg(unix)]
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+        .expect("Failed to listen for SIGTERM");
+    async move {
+        #[cfg(unix)]
+        let terminate = terminate.recv();
+        #[cfg(not(unix))]
+        let terminate = std::future::pending::<Option<()>>();
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => {
+                result.expect("Failed to listen for ctrl_c");
+                tracing::info!("Received Ctrl+C, shutting down");
+            }
+            _ = terminate => {
+                tracing::info!("Received SIGTERM, shutting down");
             }
-        });
-        if let Err(err) = app.run_daemons(shutdown).await {
-            panic!("Failed to run server: {err}");
         }
-        ExitCode::SUCCESS
     }
 }
 
 /// Built-in config command that displays the current configuration.
 ///
 /// This command prints the current application configuration in JSON format,
-/// useful for debugging configuration loading and merging.
+/// useful for debugging configuration loading and merging. `--section <key>`
+/// prints only one top-level section and `--compact` prints it on a single
+/// line, e.g. to pipe it into `jq` or diff it. Values resolved from `$env` and
+/// `$file` directives are printed as `"<secret>"`.
 pub struct ConfigCommand;
 
 impl Command for ConfigCommand {
@@ -510,11 +992,38 @@ impl Command for ConfigCommand {
         Self: Sized,
     {
         clap::Command::new("config")
+            .arg(
+                Arg::new("section")
+                    .long("section")
+                    .help("Print only this top-level section"),
+            )
+            .arg(
+                Arg::new("compact")
+                    .long("compact")
+                    .action(ArgAction::SetTrue)
+                    .help("Print JSON on a single line"),
+            )
     }
 
-    async fn main(app: Arc<App>, _matches: ArgMatches) -> ExitCode {
-        let config = app.get_component_ref::<Config>().unwrap();
-        println!("{}", serde_json::to_string_pretty(&config.configs).unwrap());
-        ExitCode::SUCCESS
+    async fn run(app: Arc<App>, matches: ArgMatches) -> Result<(), StdError> {
+        let config = app
+            .get_component_ref::<Config>()
+            .ok_or("Config component is missing")?;
+        let section = matches.try_get_one::<String>("section").ok().flatten();
+        let compact = matches!(matches.try_get_one::<bool>("compact"), Ok(Some(true)));
+        let value = config.redacted_value();
+        let value = match section {
+            Some(key) => value
+                .get(key)
+                .cloned()
+                .ok_or_else(|| format!("Config section {key} is missing"))?,
+            None => value,
+        };
+        if compact {
+            println!("{}", serde_json::to_string(&value)?);
+        } else {
+            println!("{}", serde_json::to_string_pretty(&value)?);
+        }
+        Ok(())
     }
 }
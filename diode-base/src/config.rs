@@ -1,13 +1,27 @@
-use std::collections::BTreeMap;
-use std::path::Path;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
-use diode::{Extract, StdError};
+use async_trait::async_trait;
+use diode::{AppBuilder, Extract, StdError};
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
 
+const INCLUDE_KEY: &str = "$include";
+const ENV_KEY: &str = "$env";
+const FILE_KEY: &str = "$file";
+const SECRET_PLACEHOLDER: &str = "<secret>";
+
 #[derive(Default, Serialize, Deserialize)]
 pub struct Config {
     #[serde(flatten)]
     pub(crate) configs: BTreeMap<String, serde_json::Value>,
+    /// Sections read with [`Config::get`].
+    #[serde(skip)]
+    accessed: Mutex<BTreeSet<String>>,
+    /// Paths of the values resolved from `$env` and `$file` directives.
+    #[serde(skip)]
+    secrets: BTreeSet<Vec<String>>,
 }
 
 pub trait ConfigSection: DeserializeOwned {
@@ -19,18 +33,40 @@ impl Config {
         Self::default()
     }
 
-    pub fn get<T>(&self, name: impl AsRef<str>) -> Result<T, StdError>
+    /// Deserializes the section `name` into `T`.
+    ///
+    /// A missing section is read as `null`, so `Option<T>` yields `None`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ConfigError::MissingKey`] if the section is missing and `T`
+    /// cannot be read from `null`, or [`ConfigError::Deserialize`] if the
+    /// section does not match `T`.
+    pub fn get<T>(&self, name: impl AsRef<str>) -> Result<T, ConfigError>
     where
         T: DeserializeOwned,
     {
-        Ok(serde_json::from_value(
-            self.configs
-                .get(name.as_ref())
-                .cloned()
-                .unwrap_or(serde_json::Value::Null),
-        )?)
+        let key = name.as_ref();
+        self.accessed.lock().unwrap().insert(key.to_owned());
+        match self.configs.get(key) {
+            Some(value) => {
+                serde_json::from_value(value.clone()).map_err(|source| ConfigError::Deserialize {
+                    key: key.to_owned(),
+                    type_name: std::any::type_name::<T>(),
+                    source,
+                })
+            }
+            None => serde_json::from_value(serde_json::Value::Null).map_err(|_| {
+                ConfigError::MissingKey {
+                    key: key.to_owned(),
+                    type_name: std::any::type_name::<T>(),
+                }
+            }),
+        }
     }
 
+    /// Stores `value` as the top-level section `name`, as is; use
+    /// [`set_path`](Config::set_path) to set a nested key.
     pub fn set<T>(&mut self, name: impl Into<String>, value: T) -> Result<(), StdError>
     where
         T: Serialize,
@@ -49,7 +85,105 @@ impl Config {
         self
     }
 
+    /// Sets the value at a dotted `path`, such as `"database.port"`.
+    ///
+    /// Unlike [`set`](Config::set), which stores `name` as a single top-level
+    /// key, the path is split on `.` and the value is stored in nested
+    /// objects, which are created as needed; other keys of existing objects
+    /// are kept, and a non-object value on the way is replaced with an
+    /// object. Escape a dot that is part of a key, or a backslash, with a
+    /// backslash: `"hosts.example\\.com"` sets the key `example.com` of `hosts`.
+    ///
+    /// ```rust
+    /// use diode_base::Config;
+    ///
+    /// let mut config = Config::new().with("database", serde_json::json!({"host": "localhost"}));
+    /// config.set_path("database.port", 5432).unwrap();
+    /// assert_eq!(
+    ///     config.get::<serde_json::Value>("database").unwrap(),
+    ///     serde_json::json!({"host": "localhost", "port": 5432}),
+    /// );
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the path has an empty segment or an invalid escape,
+    /// or if `value` fails to serialize.
+    pub fn set_path<T>(&mut self, path: impl AsRef<str>, value: T) -> Result<(), StdError>
+    where
+        T: Serialize,
+    {
+        let path = path.as_ref();
+        let mut keys = split_config_path(path)?.into_iter();
+        let name = keys.next().unwrap();
+        let mut target = self.configs.entry(name).or_insert(serde_json::Value::Null);
+        for key in keys {
+            if !target.is_object() {
+                *target = serde_json::Value::Object(Default::default());
+            }
+            target = target
+                .as_object_mut()
+                .unwrap()
+                .entry(key)
+                .or_insert(serde_json::Value::Null);
+        }
+        *target = serde_json::to_value(value)?;
+        Ok(())
+    }
+
+    /// Builder variant of [`set_path`](Config::set_path).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `set_path` fails.
+    pub fn with_path<T>(mut self, path: impl AsRef<str>, value: T) -> Self
+    where
+        T: Serialize,
+    {
+        self.set_path(path, value).unwrap();
+        self
+    }
+
+    /// Applies a `path=value` override, as given to the `--set` command-line
+    /// option, with [`set_path`](Config::set_path).
+    ///
+    /// The value is read as JSON when it parses as JSON, so `5433`, `true`,
+    /// `null`, `[1, 2]` and `{"a": 1}` keep their types; anything else, like
+    /// `localhost`, is a string. Quote a string that would parse otherwise:
+    /// `version="1.0"` (usually `--set 'version="1.0"'` in a shell).
+    ///
+    /// ```rust
+    /// use diode_base::Config;
+    ///
+    /// let mut config = Config::new();
+    /// config.set_override("database.port=5433").unwrap();
+    /// config.set_override("database.host=localhost").unwrap();
+    /// assert_eq!(
+    ///     config.get::<serde_json::Value>("database").unwrap(),
+    ///     serde_json::json!({"host": "localhost", "port": 5433}),
+    /// );
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `arg` has no `=` or the path is invalid.
+    pub fn set_override(&mut self, arg: &str) -> Result<(), StdError> {
+        let (path, value) = arg
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid override {arg}: expected path=value"))?;
+        let value = serde_json::from_str(value)
+            .unwrap_or_else(|_| serde_json::Value::String(value.to_owned()));
+        self.set_path(path, value)
+    }
+
     pub fn merge_from(&mut self, other: Self) -> Result<(), StdError> {
+        // Merged arrays are appended, so shift the indices of their secrets.
+        let secrets: Vec<_> = other
+            .secrets
+            .iter()
+            .map(|path| merged_path(&self.configs, &other.configs, path))
+            .collect();
+        self.secrets.extend(secrets);
         for (key, value) in other.configs {
             let entry = self.configs.entry(key);
             merge_json_from(entry.or_insert(serde_json::Value::Null), value)?;
@@ -57,16 +191,217 @@ impl Config {
         Ok(())
     }
 
+    /// Parse config from JSON text
+    ///
+    /// A top-level `"$include": ["path", ...]` directive loads the listed files
+    /// (which may include further files) and merges them in order, followed by
+    /// the remaining keys of the including document. Paths are relative to the
+    /// including file, or to the working directory for text parsed here.
+    ///
+    /// Secrets can be kept out of config files: a `{"$env": "NAME"}` object is
+    /// replaced with the value of the environment variable `NAME`, and a
+    /// `{"$file": "path"}` object with the trimmed contents of the file. Both
+    /// resolve to strings; a missing variable or file is an error.
     pub fn parse<T>(text: T) -> Result<Self, StdError>
     where
         T: AsRef<str>,
     {
-        Ok(serde_json::from_str(text.as_ref())?)
+        Self::parse_included(
+            serde_json::from_str(text.as_ref())?,
+            Path::new(""),
+            &mut Vec::new(),
+        )
     }
 
+    /// Build config from an already parsed JSON value
+    ///
+    /// The value must be a JSON object. The inverse of
+    /// [`as_value`](Config::as_value).
+    ///
+    /// Unlike [`parse`](Config::parse), directives are not resolved:
+    /// `$include`, `$env` and `$file` stay plain values, so a document from an
+    /// untrusted place, such as an HTTP response body, cannot pull local files
+    /// or environment variables into the config.
+    pub fn from_value(value: serde_json::Value) -> Result<Self, StdError> {
+        let serde_json::Value::Object(map) = value else {
+            return Err(format!(
+                "Config must be a JSON object, got {}",
+                json_type_name(&value)
+            )
+            .into());
+        };
+        Ok(Self {
+            configs: map.into_iter().collect(),
+            ..Default::default()
+        })
+    }
+
+    /// Get the whole config as a JSON object, e.g. to embed it into another
+    /// document or hash it for change detection
+    pub fn as_value(&self) -> serde_json::Value {
+        serde_json::Value::Object(self.configs.clone().into_iter().collect())
+    }
+
+    /// Get the whole config like [`as_value`](Config::as_value), with every
+    /// value resolved from a `$env` or `$file` directive replaced with
+    /// `"<secret>"`, e.g. to print or log it
+    ///
+    /// Values interpolated from a secret are replaced as well.
+    pub fn redacted_value(&self) -> serde_json::Value {
+        let mut value = self.as_value();
+        for path in &self.secrets {
+            let target = path.iter().try_fold(&mut value, |value, key| match value {
+                serde_json::Value::Object(map) => map.get_mut(key),
+                serde_json::Value::Array(values) => {
+                    key.parse::<usize>().ok().and_then(|i| values.get_mut(i))
+                }
+                _ => None,
+            });
+            if let Some(target) = target {
+                *target = serde_json::Value::String(SECRET_PLACEHOLDER.to_owned());
+            }
+        }
+        value
+    }
+
+    /// Parse config from JSON read from `reader`
+    ///
+    /// Directives are not resolved, as in [`from_value`](Config::from_value).
+    pub fn from_reader(reader: impl Read) -> Result<Self, StdError> {
+        Self::from_value(serde_json::from_reader(reader)?)
+    }
+
+    /// Parse config from JSON bytes, e.g. embedded with `include_bytes!`
+    ///
+    /// Directives are not resolved, as in [`from_value`](Config::from_value).
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, StdError> {
+        Self::from_value(serde_json::from_slice(bytes)?)
+    }
+
+    /// Parse config from JSON file, resolving `$include` directives
     pub async fn parse_file(path: impl AsRef<Path>) -> Result<Self, StdError> {
-        let text = tokio::fs::read_to_string(path).await?;
-        Self::parse(text)
+        let text = tokio::fs::read_to_string(&path).await?;
+        let path = tokio::fs::canonicalize(path).await?;
+        let value = serde_json::from_str(&text)?;
+        // Included and secret files are read with blocking I/O.
+        tokio::task::spawn_blocking(move || {
+            let base = path.parent().unwrap_or(Path::new("")).to_owned();
+            Self::parse_included(value, &base, &mut vec![path])
+        })
+        .await?
+    }
+
+    fn parse_included(
+        value: serde_json::Value,
+        base: &Path,
+        stack: &mut Vec<PathBuf>,
+    ) -> Result<Self, StdError> {
+        let mut config = Self::from_value(value)?;
+        for (key, value) in &mut config.configs {
+            resolve_secrets(value, base, &mut vec![key.clone()], &mut config.secrets)?;
+        }
+        let Some(includes) = config.configs.remove(INCLUDE_KEY) else {
+            return Ok(config);
+        };
+        let includes: Vec<PathBuf> = serde_json::from_value(includes)
+            .map_err(|err| format!("Invalid {INCLUDE_KEY} directive: {err}"))?;
+        let mut result = Self::new();
+        for include in includes {
+            let path = base.join(include);
+            let path = path
+                .canonicalize()
+                .map_err(|err| format!("Failed to include {}: {err}", path.display()))?;
+            if let Some(pos) = stack.iter().position(|v| *v == path) {
+                let cycle: Vec<_> = stack[pos..]
+                    .iter()
+                    .chain([&path])
+                    .map(|v| v.display().to_string())
+                    .collect();
+                return Err(format!("Config include cycle: {}", cycle.join(" -> ")).into());
+            }
+            let text = std::fs::read_to_string(&path)?;
+            let base = path.parent().unwrap_or(Path::new("")).to_owned();
+            stack.push(path);
+            let included = Self::parse_included(serde_json::from_str(&text)?, &base, stack)?;
+            stack.pop();
+            result.merge_from(included)?;
+        }
+        result.merge_from(config)?;
+        Ok(result)
+    }
+
+    /// Load config from `sources` and merge them in order
+    ///
+    /// Later sources override earlier ones, as with
+    /// [`merge_from`](Config::merge_from).
+    pub async fn from_sources(sources: Vec<Box<dyn ConfigSource>>) -> Result<Self, StdError> {
+        let mut config = Self::new();
+        for source in sources {
+            config.merge_from(source.load().await?)?;
+        }
+        Ok(config)
+    }
+
+    /// Resolve `{path.to.key}` placeholders in string values
+    ///
+    /// Each placeholder is replaced with the value at the dotted path, counted
+    /// from the top of the config (array elements are addressed by index), so
+    /// `{"host": "localhost", "url": "http://{host}:{server.port}"}` resolves
+    /// `url` to `http://localhost:8080` if `server.port` is `8080`. Referenced
+    /// strings are resolved recursively; numbers and booleans are inserted as
+    /// written. Write `{{` and `}}` for literal braces.
+    ///
+    /// The result always contains strings, and a missing key, a reference to
+    /// an object, array or null, or a reference cycle is an error.
+    pub fn interpolate(&self) -> Result<Self, StdError> {
+        let root = self.as_value();
+        let mut interpolator = Interpolator {
+            root: &root,
+            secrets: &self.secrets,
+            resolved: HashMap::new(),
+            stack: Vec::new(),
+            tainted: false,
+            tainted_paths: BTreeSet::new(),
+        };
+        let mut configs = self.configs.clone();
+        for (key, value) in &mut configs {
+            interpolator.interpolate_value(value, key, &mut vec![key.clone()])?;
+        }
+        let mut secrets = interpolator.tainted_paths;
+        secrets.extend(self.secrets.iter().cloned());
+        Ok(Self {
+            configs,
+            secrets,
+            ..Default::default()
+        })
+    }
+
+    /// Check if the config has a section named `name`
+    pub fn contains_key(&self, name: impl AsRef<str>) -> bool {
+        self.configs.contains_key(name.as_ref())
+    }
+
+    /// Get the sections read with [`get`](Config::get) so far, including
+    /// missing ones
+    ///
+    /// Typed sections injected into services and plugins are read while the
+    /// app is built, so checking after the build shows what it used.
+    pub fn accessed_keys(&self) -> BTreeSet<String> {
+        self.accessed.lock().unwrap().clone()
+    }
+
+    /// Get the sections present in the config but never read with
+    /// [`get`](Config::get), e.g. misspelled section names
+    ///
+    /// Sections read only later, such as by a daemon when it starts, are
+    /// reported too, so treat the result as a hint.
+    pub fn unused_keys(&self) -> Vec<String> {
+        let accessed = self.accessed.lock().unwrap();
+        self.configs
+            .keys()
+            .filter(|key| !accessed.contains(*key))
+            .cloned()
+            .collect()
     }
 
     /// Check if the config is empty
@@ -78,6 +413,101 @@ impl Config {
     pub fn len(&self) -> usize {
         self.configs.len()
     }
+
+    /// Compare top-level sections with `other`
+    ///
+    /// Sections only in `other` are added, sections only in `self` are
+    /// removed, and sections in both with different values are changed.
+    pub fn diff(&self, other: &Config) -> ConfigDiff {
+        let mut diff = ConfigDiff::default();
+        for (key, value) in &self.configs {
+            match other.configs.get(key) {
+                Some(other_value) if other_value != value => diff.changed.push(key.clone()),
+                Some(_) => {}
+                None => diff.removed.push(key.clone()),
+            }
+        }
+        diff.added = other
+            .configs
+            .keys()
+            .filter(|key| !self.configs.contains_key(*key))
+            .cloned()
+            .collect();
+        diff
+    }
+}
+
+/// Top-level sections that differ between two configs, returned by
+/// [`Config::diff`]
+///
+/// Every list is sorted by key.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConfigDiff {
+    /// Sections present only in the new config
+    pub added: Vec<String>,
+    /// Sections present only in the old config
+    pub removed: Vec<String>,
+    /// Sections present in both configs with different values
+    pub changed: Vec<String>,
+}
+
+impl ConfigDiff {
+    /// Check if the configs have the same sections and values
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Check if the section `key` was added, removed or changed
+    pub fn contains(&self, key: impl AsRef<str>) -> bool {
+        let key = key.as_ref();
+        [&self.added, &self.removed, &self.changed]
+            .iter()
+            .any(|keys| keys.iter().any(|v| v == key))
+    }
+}
+
+/// A place config is loaded from
+///
+/// Implement it to read config from somewhere other than the local filesystem,
+/// such as object storage, a database or a secrets manager, and combine
+/// sources with [`Config::from_sources`]. Implementations use
+/// [`async_trait`](crate::async_trait).
+///
+/// ```rust
+/// use diode::StdError;
+/// use diode_base::{Config, ConfigSource, async_trait};
+///
+/// struct StaticConfigSource(&'static str);
+///
+/// #[async_trait]
+/// impl ConfigSource for StaticConfigSource {
+///     async fn load(&self) -> Result<Config, StdError> {
+///         Config::parse(self.0)
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait ConfigSource: Send + Sync {
+    /// Load the config provided by this source
+    async fn load(&self) -> Result<Config, StdError>;
+}
+
+/// [`ConfigSource`] reading a JSON file with [`Config::parse_file`]
+pub struct FileConfigSource {
+    path: PathBuf,
+}
+
+impl FileConfigSource {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[async_trait]
+impl ConfigSource for FileConfigSource {
+    async fn load(&self) -> Result<Config, StdError> {
+        Config::parse_file(&self.path).await
+    }
 }
 
 impl<T> Extract<T> for Config
@@ -85,12 +515,384 @@ where
     T: ConfigSection,
 {
     fn extract(ctx: &diode::AppContext) -> Result<T, diode::AppError> {
-        Ok(ctx
-            .get_component_ref::<Config>()
-            .unwrap()
+        ctx.get_component_ref::<Config>()
+            .ok_or(diode::AppError::MissingComponent(std::any::type_name::<
+                Config,
+            >()))?
             .get::<T>(T::key())
-            .unwrap())
+            .map_err(|err| diode::AppError::PluginError(Box::new(err)))
+    }
+}
+
+/// Extracts a list section, such as a list of upstream servers.
+///
+/// The key comes from the entry type: a `Vec<Upstream>` is read from
+/// `Upstream::key()`, so declare the entry with
+/// `#[config_section("upstreams")]` and inject it as
+/// `#[inject(Config)] upstreams: Vec<Upstream>`. A missing section yields an
+/// empty list. Outside of injection, read it with
+/// `config.get::<Vec<Upstream>>("upstreams")`.
+impl<T> Extract<Vec<T>> for Config
+where
+    T: ConfigSection,
+{
+    fn extract(ctx: &diode::AppContext) -> Result<Vec<T>, diode::AppError> {
+        ctx.get_component_ref::<Config>()
+            .ok_or(diode::AppError::MissingComponent(std::any::type_name::<
+                Config,
+            >()))?
+            .get::<Option<Vec<T>>>(T::key())
+            .map(Option::unwrap_or_default)
+            .map_err(|err| diode::AppError::PluginError(Box::new(err)))
+    }
+}
+
+/// Error returned by [`Config::get`].
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The section is missing and the target type cannot be read from `null`.
+    MissingKey {
+        key: String,
+        type_name: &'static str,
+    },
+    /// The section does not match the target type.
+    Deserialize {
+        key: String,
+        type_name: &'static str,
+        source: serde_json::Error,
+    },
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::MissingKey { key, type_name } => {
+                write!(f, "config section '{key}' ({type_name}) is missing")
+            }
+            ConfigError::Deserialize {
+                key,
+                type_name,
+                source,
+            } => write!(f, "config section '{key}' ({type_name}): {source}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Deserialize { source, .. } => Some(source),
+            ConfigError::MissingKey { .. } => None,
+        }
+    }
+}
+
+/// Extension trait for registering optional subsystems on [`AppBuilder`]
+pub trait AddIfConfigSectionExt {
+    /// Run `func` only if the config has the section of `S`
+    ///
+    /// Makes optional subsystems declarative, e.g. registering the HTTP server
+    /// only when its section is configured. The [`Config`] component must be
+    /// added before this call; the check happens immediately, so sections
+    /// added to the config afterwards are not seen.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no [`Config`] component has been added.
+    fn add_if_config_section<S, F>(&mut self, func: F) -> &mut Self
+    where
+        S: ConfigSection,
+        F: FnOnce(&mut Self),
+        Self: Sized;
+}
+
+impl AddIfConfigSectionExt for AppBuilder {
+    fn add_if_config_section<S, F>(&mut self, func: F) -> &mut Self
+    where
+        S: ConfigSection,
+        F: FnOnce(&mut Self),
+        Self: Sized,
+    {
+        let contains_key = self
+            .get_component_ref::<Config>()
+            .expect("Config component must be added before add_if_config_section")
+            .contains_key(S::key());
+        if contains_key {
+            func(self);
+        }
+        self
+    }
+}
+
+/// Resolves the secret directives in `value`, found at `path`, recording the
+/// paths of the resolved values in `secrets`.
+fn resolve_secrets(
+    value: &mut serde_json::Value,
+    base: &Path,
+    path: &mut Vec<String>,
+    secrets: &mut BTreeSet<Vec<String>>,
+) -> Result<(), StdError> {
+    match value {
+        serde_json::Value::Object(map) => {
+            if let Some(secret) = resolve_secret(map, base)? {
+                *value = serde_json::Value::String(secret);
+                secrets.insert(path.clone());
+                return Ok(());
+            }
+            for (key, value) in map {
+                path.push(key.clone());
+                resolve_secrets(value, base, path, secrets)?;
+                path.pop();
+            }
+        }
+        serde_json::Value::Array(values) => {
+            for (i, value) in values.iter_mut().enumerate() {
+                path.push(i.to_string());
+                resolve_secrets(value, base, path, secrets)?;
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Returns where the value at `path` in `rhs` ends up after merging `rhs`
+/// into `lhs` with [`merge_json_from`], which appends arrays.
+fn merged_path(
+    lhs: &BTreeMap<String, serde_json::Value>,
+    rhs: &BTreeMap<String, serde_json::Value>,
+    path: &[String],
+) -> Vec<String> {
+    let Some((name, keys)) = path.split_first() else {
+        return Vec::new();
+    };
+    let mut result = vec![name.clone()];
+    let mut lhs = lhs.get(name);
+    let mut rhs = rhs.get(name);
+    for key in keys {
+        match (lhs, rhs) {
+            (Some(serde_json::Value::Object(l)), Some(serde_json::Value::Object(r))) => {
+                result.push(key.clone());
+                lhs = l.get(key);
+                rhs = r.get(key);
+            }
+            (Some(serde_json::Value::Array(l)), Some(serde_json::Value::Array(r))) => {
+                let index = key.parse::<usize>().ok();
+                result.push(index.map_or(key.clone(), |i| (i + l.len()).to_string()));
+                lhs = None;
+                rhs = index.and_then(|i| r.get(i));
+            }
+            _ => {
+                // The value replaces the left one, so the rest of the path
+                // stays as is.
+                result.push(key.clone());
+                lhs = None;
+                rhs = None;
+            }
+        }
+    }
+    result
+}
+
+fn resolve_secret(
+    map: &serde_json::Map<String, serde_json::Value>,
+    base: &Path,
+) -> Result<Option<String>, StdError> {
+    if map.len() != 1 {
+        return Ok(None);
+    }
+    if let Some(name) = map.get(ENV_KEY) {
+        let name = name
+            .as_str()
+            .ok_or_else(|| format!("Invalid {ENV_KEY} directive: expected string"))?;
+        let value = std::env::var(name)
+            .map_err(|err| format!("Failed to resolve {ENV_KEY} {name}: {err}"))?;
+        return Ok(Some(value));
+    }
+    if let Some(path) = map.get(FILE_KEY) {
+        let path = path
+            .as_str()
+            .ok_or_else(|| format!("Invalid {FILE_KEY} directive: expected string"))?;
+        let path = base.join(path);
+        let value = std::fs::read_to_string(&path)
+            .map_err(|err| format!("Failed to resolve {FILE_KEY} {}: {err}", path.display()))?;
+        return Ok(Some(value.trim().to_owned()));
+    }
+    Ok(None)
+}
+
+struct Interpolator<'a> {
+    root: &'a serde_json::Value,
+    secrets: &'a BTreeSet<Vec<String>>,
+    /// Resolved placeholders, and whether they reference a secret.
+    resolved: HashMap<String, (String, bool)>,
+    stack: Vec<String>,
+    /// Whether the string being interpolated references a secret.
+    tainted: bool,
+    /// Paths of the interpolated values that reference a secret.
+    tainted_paths: BTreeSet<Vec<String>>,
+}
+
+impl Interpolator<'_> {
+    /// Resolves placeholders in `value`, found at `path` in the config; `keys`
+    /// is the same path split into keys.
+    fn interpolate_value(
+        &mut self,
+        value: &mut serde_json::Value,
+        path: &str,
+        keys: &mut Vec<String>,
+    ) -> Result<(), StdError> {
+        match value {
+            serde_json::Value::String(text) => {
+                self.stack.push(path.to_owned());
+                self.tainted = false;
+                let result = self.interpolate_str(text);
+                self.stack.pop();
+                *text = result?;
+                if self.tainted {
+                    self.tainted_paths.insert(keys.clone());
+                }
+            }
+            serde_json::Value::Array(values) => {
+                for (i, value) in values.iter_mut().enumerate() {
+                    keys.push(i.to_string());
+                    self.interpolate_value(value, &format!("{path}.{i}"), keys)?;
+                    keys.pop();
+                }
+            }
+            serde_json::Value::Object(map) => {
+                for (key, value) in map {
+                    keys.push(key.clone());
+                    self.interpolate_value(value, &format!("{path}.{key}"), keys)?;
+                    keys.pop();
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn interpolate_str(&mut self, text: &str) -> Result<String, StdError> {
+        let mut result = String::with_capacity(text.len());
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    result.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    result.push('}');
+                }
+                '{' => {
+                    let mut path = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        path.push(c);
+                    }
+                    if !closed || path.is_empty() || path.contains('{') {
+                        return Err(format!("Invalid placeholder in config value: {text}").into());
+                    }
+                    result.push_str(&self.resolve(&path)?);
+                }
+                '}' => {
+                    return Err(format!("Unmatched `}}` in config value: {text}").into());
+                }
+                c => result.push(c),
+            }
+        }
+        Ok(result)
+    }
+
+    fn resolve(&mut self, path: &str) -> Result<String, StdError> {
+        if let Some((value, tainted)) = self.resolved.get(path) {
+            self.tainted |= tainted;
+            return Ok(value.clone());
+        }
+        if let Some(pos) = self.stack.iter().position(|v| v == path) {
+            let cycle: Vec<_> = self.stack[pos..]
+                .iter()
+                .map(String::as_str)
+                .chain([path])
+                .collect();
+            return Err(format!("Config interpolation cycle: {}", cycle.join(" -> ")).into());
+        }
+        let value = path
+            .split('.')
+            .try_fold(self.root, |value, key| match value {
+                serde_json::Value::Object(map) => map.get(key),
+                serde_json::Value::Array(values) => {
+                    key.parse::<usize>().ok().and_then(|i| values.get(i))
+                }
+                _ => None,
+            })
+            .ok_or_else(|| format!("Config interpolation references missing key {path}"))?;
+        let outer_tainted = std::mem::replace(
+            &mut self.tainted,
+            self.secrets
+                .contains(&path.split('.').map(str::to_owned).collect::<Vec<_>>()),
+        );
+        let result = match value {
+            serde_json::Value::String(text) => {
+                self.stack.push(path.to_owned());
+                let result = self.interpolate_str(text);
+                self.stack.pop();
+                result?
+            }
+            serde_json::Value::Number(number) => number.to_string(),
+            serde_json::Value::Bool(value) => value.to_string(),
+            value => {
+                return Err(format!(
+                    "Config interpolation of {path} must reference a string, a number or a boolean, got {}",
+                    json_type_name(value)
+                )
+                .into());
+            }
+        };
+        self.resolved
+            .insert(path.to_owned(), (result.clone(), self.tainted));
+        self.tainted |= outer_tainted;
+        Ok(result)
+    }
+}
+
+fn json_type_name(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "a boolean",
+        serde_json::Value::Number(_) => "a number",
+        serde_json::Value::String(_) => "a string",
+        serde_json::Value::Array(_) => "an array",
+        serde_json::Value::Object(_) => "an object",
+    }
+}
+
+/// Splits a dotted config path, unescaping `\.` and `\\`.
+fn split_config_path(path: &str) -> Result<Vec<String>, StdError> {
+    let mut keys = Vec::new();
+    let mut key = String::new();
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(c @ ('.' | '\\')) => key.push(c),
+                _ => return Err(format!("Invalid escape in config path: {path}").into()),
+            },
+            '.' => keys.push(std::mem::take(&mut key)),
+            c => key.push(c),
+        }
+    }
+    keys.push(key);
+    if keys.iter().any(String::is_empty) {
+        return Err(format!("Empty key in config path: {path}").into());
     }
+    Ok(keys)
 }
 
 fn merge_json_from(lhs: &mut serde_json::Value, rhs: serde_json::Value) -> Result<(), StdError> {
@@ -1,42 +1,98 @@
 use std::any::{TypeId, type_name};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::marker::PhantomData;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
 use async_trait::async_trait;
 use diode::{
-    AddServiceExt as _, App, AppBuilder, AppContext, Dependencies, Plugin, Service,
-    ServiceDependencyExt as _, StdError,
+    AddServiceExt as _, App, AppBuilder, AppContext, AppError, Dependencies, Extract,
+    MergeComponent, Plugin, Service, ServiceDependencyExt as _, StdError,
 };
 use tokio::task::JoinSet;
 
 /// Cooperative cancellation token used to signal daemons to shut down.
 pub use tokio_util::sync::CancellationToken;
 
-use crate::defer;
+use crate::{Config, ConfigSection, defer};
+
+struct DaemonEntry {
+    daemon: Arc<dyn DynDaemon>,
+    type_id: TypeId,
+    name: &'static str,
+    wait_for: DaemonWaitFor,
+}
 
 #[derive(Default)]
 struct DaemonRegistry {
-    daemons: Vec<Arc<dyn DynDaemon>>,
-    types: HashSet<TypeId>,
+    daemons: Vec<DaemonEntry>,
+    ready: HashMap<TypeId, CancellationToken>,
 }
 
 impl DaemonRegistry {
+    pub fn len(&self) -> usize {
+        self.daemons.len()
+    }
+
     pub fn add_daemon<T>(&mut self, daemon: Arc<T>)
     where
         T: Daemon + 'static,
     {
-        if !self.types.insert(TypeId::of::<T>()) {
+        if self.ready.contains_key(&TypeId::of::<T>()) {
             panic!("Daemon {} already added", type_name::<T>());
         }
-        self.daemons.push(daemon);
+        self.ready
+            .insert(TypeId::of::<T>(), CancellationToken::new());
+        self.daemons.push(DaemonEntry {
+            daemon,
+            type_id: TypeId::of::<T>(),
+            name: type_name::<T>(),
+            wait_for: T::wait_for(),
+        });
     }
 
     pub fn has_daemon<T>(&self) -> bool
     where
         T: Daemon + 'static,
     {
-        self.types.contains(&TypeId::of::<T>())
+        self.ready.contains_key(&TypeId::of::<T>())
+    }
+
+    fn notify_ready<T>(&self)
+    where
+        T: Daemon + 'static,
+    {
+        if let Some(ready) = self.ready.get(&TypeId::of::<T>()) {
+            ready.cancel();
+        }
+    }
+
+    fn check_wait_for(&self) -> Result<(), StdError> {
+        let names: HashMap<TypeId, &'static str> = self
+            .daemons
+            .iter()
+            .map(|entry| (entry.type_id, entry.name))
+            .collect();
+        for entry in self.daemons.iter() {
+            for (id, name) in entry.wait_for.daemons.iter() {
+                if !names.contains_key(id) {
+                    return Err(format!(
+                        "Daemon {} waits for {name}, which is not registered",
+                        entry.name,
+                    )
+                    .into());
+                }
+            }
+        }
+        let waits: HashMap<TypeId, &DaemonWaitFor> = self
+            .daemons
+            .iter()
+            .map(|entry| (entry.type_id, &entry.wait_for))
+            .collect();
+        let mut visited = HashSet::new();
+        for entry in self.daemons.iter() {
+            check_wait_cycle(entry.type_id, &waits, &names, &mut Vec::new(), &mut visited)?;
+        }
+        Ok(())
     }
 
     pub async fn run_daemons(
@@ -44,23 +100,72 @@ impl DaemonRegistry {
         app: Arc<App>,
         shutdown: CancellationToken,
     ) -> Result<(), StdError> {
+        self.check_wait_for()?;
         let span = tracing::info_span!("daemons");
+        let awaited: HashSet<TypeId> = self
+            .daemons
+            .iter()
+            .flat_map(|entry| entry.wait_for.daemons.iter().map(|(id, _)| *id))
+            .collect();
         let mut futures = JoinSet::new();
+        let mut names = HashMap::new();
         tracing::info!(parent: &span, "Daemons starting");
-        for daemon in self.daemons.iter() {
+        for entry in self.daemons.iter() {
             let shutdown = shutdown.child_token();
             let app = app.clone();
-            let daemon = daemon.clone();
-            futures.spawn(async move { daemon.run(&app, shutdown).await });
+            let daemon = entry.daemon.clone();
+            let name = entry.name;
+            let ready = self.ready[&entry.type_id].clone();
+            let dependencies: Vec<_> = entry
+                .wait_for
+                .daemons
+                .iter()
+                .map(|(id, _)| self.ready[id].clone())
+                .collect();
+            let awaited = awaited.contains(&entry.type_id);
+            let handle = futures.spawn(async move {
+                if !dependencies.is_empty() {
+                    tracing::debug!(daemon = name, "Daemon waiting for dependencies");
+                    for dependency in dependencies {
+                        tokio::select! {
+                            _ = dependency.cancelled_owned() => {}
+                            _ = shutdown.cancelled() => return Ok(false),
+                        }
+                    }
+                }
+                daemon.run(&app, shutdown).await?;
+                ready.cancel();
+                // Daemons awaited by others may finish early (e.g. migrations)
+                // without stopping the rest.
+                Ok::<_, StdError>(awaited)
+            });
+            names.insert(handle.id(), name);
         }
         tracing::info!(parent: &span, "Daemons running");
         defer! {
             tracing::info!(parent: &span, "Daemons stopped");
         };
-        let first_result = futures.join_next().await;
+        let first_result = loop {
+            match futures.join_next_with_id().await {
+                Some(Ok((_, Ok(true)))) => continue,
+                result => break result,
+            }
+        };
+        if let Some(result) = &first_result
+            && !shutdown.is_cancelled()
+            && let Some(reason) = app.get_component_ref::<ShutdownReason>()
+        {
+            reason.set(match result {
+                Ok((id, Ok(_))) => ShutdownCause::DaemonStopped { daemon: names[id] },
+                Ok((id, Err(_))) => ShutdownCause::DaemonFailed { daemon: names[id] },
+                Err(err) => ShutdownCause::DaemonFailed {
+                    daemon: names[&err.id()],
+                },
+            });
+        }
         shutdown.cancel();
         if let Some(result) = first_result {
-            result.map_err(Box::new)??;
+            result.map_err(Box::new)?.1?;
             while let Some(result) = futures.join_next().await {
                 result.map_err(Box::new)??;
             }
@@ -69,6 +174,88 @@ impl DaemonRegistry {
     }
 }
 
+impl MergeComponent for DaemonRegistry {
+    fn merge(&mut self, mut other: Self) -> Result<(), StdError> {
+        if let Some(entry) = other
+            .daemons
+            .iter()
+            .find(|entry| self.ready.contains_key(&entry.type_id))
+        {
+            return Err(format!("Daemon {} already added", entry.name).into());
+        }
+        for entry in other.daemons {
+            let ready = other.ready.remove(&entry.type_id).unwrap();
+            self.ready.insert(entry.type_id, ready);
+            self.daemons.push(entry);
+        }
+        Ok(())
+    }
+}
+
+fn check_wait_cycle(
+    id: TypeId,
+    waits: &HashMap<TypeId, &DaemonWaitFor>,
+    names: &HashMap<TypeId, &'static str>,
+    path: &mut Vec<TypeId>,
+    visited: &mut HashSet<TypeId>,
+) -> Result<(), StdError> {
+    if let Some(pos) = path.iter().position(|v| *v == id) {
+        let cycle: Vec<_> = path[pos..].iter().chain([&id]).map(|v| names[v]).collect();
+        return Err(format!("Daemon wait cycle: {}", cycle.join(" -> ")).into());
+    }
+    if !visited.insert(id) {
+        return Ok(());
+    }
+    path.push(id);
+    for (dependency, _) in waits[&id].daemons.iter() {
+        check_wait_cycle(*dependency, waits, names, path, visited)?;
+    }
+    path.pop();
+    Ok(())
+}
+
+/// Set of daemons a [`Daemon`] waits for before it is started.
+///
+/// Returned from [`Daemon::wait_for`]. A daemon becomes ready once it calls
+/// [`DaemonReadyExt::notify_daemon_ready`] or once its
+/// [`run`](Daemon::run) returns `Ok`.
+///
+/// ```rust
+/// use diode_base::{Daemon, DaemonWaitFor};
+///
+/// struct Migrations;
+///
+/// impl Daemon for Migrations {}
+///
+/// struct Worker;
+///
+/// impl Daemon for Worker {
+///     fn wait_for() -> DaemonWaitFor {
+///         DaemonWaitFor::new().daemon::<Migrations>()
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct DaemonWaitFor {
+    daemons: Vec<(TypeId, &'static str)>,
+}
+
+impl DaemonWaitFor {
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Waits for the daemon of type `T` to become ready.
+    pub fn daemon<T>(mut self) -> Self
+    where
+        T: Daemon + 'static,
+    {
+        self.daemons.push((TypeId::of::<T>(), type_name::<T>()));
+        self
+    }
+}
+
 /// A long-running background task managed by the application.
 ///
 /// Daemons are started together by [`RunDaemonsExt::run_daemons`] and run until
@@ -76,12 +263,49 @@ impl DaemonRegistry {
 /// until any one of them returns, at which point the rest are signalled to stop.
 /// Each daemon is given its own child cancellation token.
 ///
+/// A daemon may declare other daemons it depends on with
+/// [`wait_for`](Daemon::wait_for): it is started only once all of them are
+/// ready. A daemon that others wait for may return `Ok` without stopping the
+/// rest, which fits one-shot tasks such as database migrations.
+///
 /// The default [`run`](Daemon::run) implementation does nothing and just waits
 /// for cancellation, which is handy for a daemon that only needs to keep a
 /// component alive.
 ///
-/// Register a daemon with [`AddDaemonExt::add_daemon`] (a concrete instance) or
+/// Register a daemon with [`AddDaemonExt::add_daemon`] (a concrete instance),
+/// [`AddDaemonExt::add_fn_daemon`] (a closure),
+/// [`AddDaemonExt::add_daemon_with_config`] (built from a config section) or
 /// [`AddDaemonServiceExt::add_daemon_service`] (resolved from a [`Service`]).
+///
+/// Since [`AddDaemonExt`] lives on [`AppContext`], a service that owns a
+/// background loop can also register it from its own [`Service::build`],
+/// without a dedicated plugin:
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use diode::{AppContext, Service, StdError};
+/// use diode_base::{AddDaemonExt as _, CancellationToken};
+///
+/// struct Refresher;
+///
+/// impl Service for Refresher {
+///     type Handle = Arc<Self>;
+///
+///     async fn build(ctx: &AppContext) -> Result<Self::Handle, StdError> {
+///         let refresher = Arc::new(Self);
+///         let daemon = refresher.clone();
+///         ctx.add_fn_daemon(move |_app, shutdown: CancellationToken| {
+///             let _refresher = daemon.clone();
+///             async move {
+///                 shutdown.cancelled_owned().await;
+///                 Ok(())
+///             }
+///         });
+///         Ok(refresher)
+///     }
+/// }
+/// ```
 pub trait Daemon: Send + Sync {
     /// Runs the daemon until `shutdown` is cancelled.
     ///
@@ -99,6 +323,17 @@ pub trait Daemon: Send + Sync {
             Ok(())
         }
     }
+
+    /// Returns the daemons that must be ready before this one is started.
+    ///
+    /// Defaults to none. [`RunDaemonsExt::run_daemons`] fails if a listed
+    /// daemon is not registered or if the dependencies form a cycle.
+    fn wait_for() -> DaemonWaitFor
+    where
+        Self: Sized,
+    {
+        DaemonWaitFor::new()
+    }
 }
 
 #[async_trait]
@@ -116,18 +351,49 @@ where
     }
 }
 
+/// Signals that a [`Daemon`] is ready, releasing the daemons waiting for it.
+pub trait DaemonReadyExt {
+    /// Marks the daemon of type `T` as ready.
+    ///
+    /// Call this from [`Daemon::run`] once the daemon is able to serve, for
+    /// example after binding its listener. Does nothing if `T` is not
+    /// registered; calling it more than once is harmless.
+    fn notify_daemon_ready<T>(&self)
+    where
+        T: Daemon + 'static;
+}
+
+impl DaemonReadyExt for App {
+    fn notify_daemon_ready<T>(&self)
+    where
+        T: Daemon + 'static,
+    {
+        if let Some(registry) = self.get_component_ref::<DaemonRegistry>() {
+            registry.notify_ready::<T>();
+        }
+    }
+}
+
+/// Returns the number of daemons registered on `app`.
+pub(crate) fn daemon_count(app: &App) -> usize {
+    app.get_component_ref::<DaemonRegistry>()
+        .map_or(0, DaemonRegistry::len)
+}
+
 /// Runs every registered [`Daemon`] until shutdown.
 pub trait RunDaemonsExt {
     /// Runs all registered daemons concurrently.
     ///
     /// Returns once the `shutdown` token is cancelled or the first daemon
-    /// returns; in either case the remaining daemons are signalled to stop and
+    /// returns (other than one that is waited for finishing with `Ok`); in
+    /// either case the remaining daemons are signalled to stop and
     /// awaited. Returns immediately if no daemons were registered.
     ///
     /// # Errors
     ///
     /// Returns the first error reported by a daemon, or the join error if a
-    /// daemon task panics.
+    /// daemon task panics. Also fails before starting anything if a daemon
+    /// waits for one that is not registered or the waits form a cycle.
     fn run_daemons(
         self,
         shutdown: CancellationToken,
@@ -136,12 +402,16 @@ pub trait RunDaemonsExt {
 
 impl RunDaemonsExt for App {
     async fn run_daemons(self, shutdown: CancellationToken) -> Result<(), StdError> {
-        Arc::new(self).run_daemons(shutdown).await
+        self.into_handle().run_daemons(shutdown).await
     }
 }
 
 impl RunDaemonsExt for Arc<App> {
     async fn run_daemons(self, shutdown: CancellationToken) -> Result<(), StdError> {
+        let shutdown = match self.get_component_ref::<AppShutdown>() {
+            Some(app_shutdown) => app_shutdown.link(shutdown),
+            None => shutdown,
+        };
         match self.get_component_ref::<DaemonRegistry>() {
             Some(v) => v.run_daemons(self.clone(), shutdown).await,
             None => Ok(()),
@@ -149,6 +419,120 @@ impl RunDaemonsExt for Arc<App> {
     }
 }
 
+/// Application-wide shutdown token.
+///
+/// Services that spawn background work in [`Service::build`] can wire it to
+/// this token to be stopped with the daemons. Inject the token with
+/// `#[inject(AppShutdown)] shutdown: CancellationToken`, or call
+/// [`AppShutdown::from_context`]; the component is added on first use.
+///
+/// [`RunDaemonsExt::run_daemons`] links it with the token it is given: the
+/// token is cancelled when that one is (for example by the signal handler of
+/// [`ServerCommand`](crate::ServerCommand)) and when the daemons stop.
+/// Cancelling it, in turn, stops the daemons just like a shutdown signal. It
+/// cannot be reset, so an application runs its daemons at most once.
+///
+/// ```rust
+/// use diode::Service;
+/// use diode_base::{AppShutdown, CancellationToken};
+///
+/// #[derive(Service)]
+/// struct Poller {
+///     #[inject(AppShutdown)]
+///     shutdown: CancellationToken,
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct AppShutdown(CancellationToken);
+
+impl AppShutdown {
+    /// Returns the token of `ctx`, adding the component if needed.
+    pub fn from_context(ctx: &AppContext) -> CancellationToken {
+        ctx.get_or_insert_component::<AppShutdown>().token()
+    }
+
+    /// Returns the application-wide token.
+    pub fn token(&self) -> CancellationToken {
+        self.0.clone()
+    }
+
+    /// Cancels the application-wide token when `shutdown` is cancelled, and
+    /// returns it.
+    fn link(&self, shutdown: CancellationToken) -> CancellationToken {
+        let token = self.token();
+        tokio::spawn({
+            let token = token.clone();
+            async move {
+                tokio::select! {
+                    _ = shutdown.cancelled() => token.cancel(),
+                    _ = token.cancelled() => {}
+                }
+            }
+        });
+        token
+    }
+}
+
+impl Extract<CancellationToken> for AppShutdown {
+    fn extract(ctx: &AppContext) -> Result<CancellationToken, AppError> {
+        Ok(Self::from_context(ctx))
+    }
+}
+
+/// Why the daemons were told to shut down, see [`ShutdownReason`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ShutdownCause {
+    /// A shutdown signal was received: Ctrl+C or, on Unix, SIGTERM.
+    Signal,
+    /// The daemon returned an error or panicked.
+    DaemonFailed { daemon: &'static str },
+    /// The daemon returned, though it was not waited for by another one.
+    DaemonStopped { daemon: &'static str },
+    /// Shutdown requested by the application, with a description.
+    Requested(String),
+}
+
+/// Application-wide record of why shutdown was triggered.
+///
+/// Each trigger sets the cause before cancelling the shutdown token: the
+/// signal handler of [`ServerCommand`](crate::ServerCommand), and
+/// [`RunDaemonsExt::run_daemons`] when a daemon fails or stops. Only the first
+/// cause is kept, so a daemon reading it once its token is cancelled learns
+/// what started the shutdown, e.g. to flush buffered work on a signal but not
+/// after a failure. Application code that cancels [`AppShutdown`] itself can
+/// [`set`](ShutdownReason::set) a [`ShutdownCause::Requested`] first.
+///
+/// Inject it with `#[inject(ShutdownReason)] reason: ShutdownReason`, or call
+/// [`ShutdownReason::from_context`]; the component is added on first use and
+/// causes are only recorded once it is present. Clones share the same cause.
+#[derive(Clone, Debug, Default)]
+pub struct ShutdownReason(Arc<OnceLock<ShutdownCause>>);
+
+impl ShutdownReason {
+    /// Returns the reason of `ctx`, adding the component if needed.
+    pub fn from_context(ctx: &AppContext) -> Self {
+        ctx.get_or_insert_component::<ShutdownReason>().clone()
+    }
+
+    /// Records `cause`, unless a cause is already recorded. Returns whether
+    /// it was recorded.
+    pub fn set(&self, cause: ShutdownCause) -> bool {
+        self.0.set(cause).is_ok()
+    }
+
+    /// Returns the recorded cause, if shutdown was triggered.
+    pub fn get(&self) -> Option<&ShutdownCause> {
+        self.0.get()
+    }
+}
+
+impl Extract<ShutdownReason> for ShutdownReason {
+    fn extract(ctx: &AppContext) -> Result<ShutdownReason, AppError> {
+        Ok(Self::from_context(ctx))
+    }
+}
+
 /// Registers concrete [`Daemon`] instances on the application.
 ///
 /// This extension lives on [`AppContext`], so daemons can be registered both
@@ -166,6 +550,58 @@ pub trait AddDaemonExt {
     where
         T: Daemon + 'static;
 
+    /// Registers the closure `func` to be run as a daemon.
+    ///
+    /// The closure is called once with the built [`App`] and the daemon's
+    /// cancellation token, and the returned future is driven like
+    /// [`Daemon::run`]. The future cannot borrow the [`App`], so resolve the
+    /// components it needs before the `async move` block.
+    ///
+    /// Each closure has its own type, so distinct closures never collide in the
+    /// registry.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the same closure type is registered twice (for example when the
+    /// registering function is called more than once).
+    fn add_fn_daemon<F, Fut>(&self, func: F)
+    where
+        F: Fn(&App, CancellationToken) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), StdError>> + Send + 'static;
+
+    /// Registers the daemon built by `new` from the config section `C`.
+    ///
+    /// The section is read from the [`Config`] component when the app is
+    /// built, so a missing or malformed section fails the build instead of
+    /// the daemon's start.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the same `T` and `C` pair is registered twice. Building the
+    /// [`App`] additionally panics if a daemon of type `T` is registered in
+    /// another way too.
+    fn add_daemon_with_config<T, C, F>(&self, new: F)
+    where
+        T: Daemon + 'static,
+        C: ConfigSection + 'static,
+        F: Fn(C) -> T + Send + Sync + 'static;
+
+    /// Registers the daemon built by `new` only if `condition` holds, e.g.
+    /// when the config section of an optional feature is present.
+    ///
+    /// `new` is not called otherwise, so it can read the section `condition`
+    /// checked for; the skipped daemon is logged at debug level. Errors of
+    /// `new` are returned as is.
+    ///
+    /// # Panics
+    ///
+    /// Panics like [`add_daemon`](AddDaemonExt::add_daemon) if the daemon is
+    /// registered and a daemon of type `T` already is.
+    fn add_daemon_if<T, F>(&self, condition: bool, new: F) -> Result<(), StdError>
+    where
+        T: Daemon + 'static,
+        F: FnOnce() -> Result<T, StdError>;
+
     /// Returns whether a daemon of type `T` is registered.
     fn has_daemon<T>(&self) -> bool
     where
@@ -177,14 +613,40 @@ impl AddDaemonExt for AppContext {
     where
         T: Daemon + 'static,
     {
-        if !self.has_component::<DaemonRegistry>() {
-            self.add_component(DaemonRegistry::default());
-        }
-        self.get_component_mut::<DaemonRegistry>()
-            .unwrap()
+        self.get_or_insert_merge_component::<DaemonRegistry>()
             .add_daemon(daemon.into());
     }
 
+    fn add_fn_daemon<F, Fut>(&self, func: F)
+    where
+        F: Fn(&App, CancellationToken) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), StdError>> + Send + 'static,
+    {
+        self.add_daemon::<FnDaemon<F>>(FnDaemon(func));
+    }
+
+    fn add_daemon_with_config<T, C, F>(&self, new: F)
+    where
+        T: Daemon + 'static,
+        C: ConfigSection + 'static,
+        F: Fn(C) -> T + Send + Sync + 'static,
+    {
+        self.add_plugin(DaemonConfigProvider::<T, C, F>(new, PhantomData));
+    }
+
+    fn add_daemon_if<T, F>(&self, condition: bool, new: F) -> Result<(), StdError>
+    where
+        T: Daemon + 'static,
+        F: FnOnce() -> Result<T, StdError>,
+    {
+        if condition {
+            self.add_daemon::<T>(new()?);
+        } else {
+            tracing::debug!(daemon = type_name::<T>(), "Daemon is disabled, skipping");
+        }
+        Ok(())
+    }
+
     fn has_daemon<T>(&self) -> bool
     where
         T: Daemon + 'static,
@@ -194,6 +656,38 @@ impl AddDaemonExt for AppContext {
     }
 }
 
+/// Daemon backed by a closure, registered with [`AddDaemonExt::add_fn_daemon`].
+struct FnDaemon<F>(F);
+
+impl<F, Fut> Daemon for FnDaemon<F>
+where
+    F: Fn(&App, CancellationToken) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<(), StdError>> + Send,
+{
+    fn run(
+        &self,
+        app: &App,
+        shutdown: CancellationToken,
+    ) -> impl Future<Output = Result<(), StdError>> + Send {
+        (self.0)(app, shutdown)
+    }
+}
+
+struct DaemonConfigProvider<T, C, F>(F, PhantomData<fn(C) -> T>);
+
+impl<T, C, F> Plugin for DaemonConfigProvider<T, C, F>
+where
+    T: Daemon + 'static,
+    C: ConfigSection + 'static,
+    F: Fn(C) -> T + Send + Sync + 'static,
+{
+    async fn build(&self, ctx: &AppContext) -> Result<(), StdError> {
+        let config: C = Config::extract(ctx)?;
+        ctx.add_daemon::<T>((self.0)(config));
+        Ok(())
+    }
+}
+
 struct DaemonServiceProvider<T>(PhantomData<T>);
 
 impl<T> Plugin for DaemonServiceProvider<T>
@@ -201,7 +695,9 @@ where
     T: Service<Handle = Arc<T>> + Daemon + 'static,
 {
     async fn build(&self, ctx: &AppContext) -> Result<(), StdError> {
-        let handle = ctx.get_component::<T::Handle>().unwrap();
+        let handle = ctx
+            .get_component::<T::Handle>()
+            .ok_or_else(|| format!("Missing component: {}", type_name::<T::Handle>()))?;
         ctx.add_daemon::<T>(handle);
         Ok(())
     }
@@ -0,0 +1,123 @@
+use std::time::Duration;
+
+use diode::{App, AppBuilder, StdError};
+
+use crate::{AddDaemonExt as _, CancellationToken, Daemon};
+
+/// An opt-in [`Daemon`] that logs a dump of the tokio runtime when the
+/// process receives `SIGUSR1`, to diagnose hangs in production.
+///
+/// Every dump logs the number of workers and alive tasks of the runtime. Task
+/// backtraces are only available when tokio is built with task dumps, which
+/// are unstable and work on Linux only (x86, x86_64 and aarch64). Build the
+/// binary with
+///
+/// ```text
+/// RUSTFLAGS="--cfg tokio_unstable --cfg tokio_taskdump" cargo build
+/// ```
+///
+/// to log the backtrace of every task the runtime knows about. Collecting
+/// them waits for every task to yield, so a dump is abandoned after
+/// [`with_timeout`](DiagnosticsDaemon::with_timeout) (10 seconds by default)
+/// if some task blocks its worker thread.
+///
+/// On other platforms than Unix the daemon only waits for shutdown.
+///
+/// # Examples
+///
+/// ```rust
+/// use diode::App;
+/// use diode_base::AddDiagnosticsExt as _;
+///
+/// let mut builder = App::builder();
+/// builder.add_diagnostics_daemon();
+/// // Later: kill -USR1 <pid>
+/// ```
+pub struct DiagnosticsDaemon {
+    timeout: Duration,
+}
+
+impl DiagnosticsDaemon {
+    pub fn new() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Sets how long a dump may take before it is abandoned.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    async fn dump(&self) {
+        let handle = tokio::runtime::Handle::current();
+        let metrics = handle.metrics();
+        tracing::info!(
+            workers = metrics.num_workers(),
+            alive_tasks = metrics.num_alive_tasks(),
+            "Runtime dump requested"
+        );
+        #[cfg(all(tokio_unstable, tokio_taskdump))]
+        match tokio::time::timeout(self.timeout, handle.dump()).await {
+            Ok(dump) => {
+                for (index, task) in dump.tasks().iter().enumerate() {
+                    tracing::info!(task = index, trace = %task.trace(), "Task backtrace");
+                }
+            }
+            Err(_) => {
+                tracing::warn!(timeout = ?self.timeout, "Runtime dump timed out");
+            }
+        }
+        #[cfg(not(all(tokio_unstable, tokio_taskdump)))]
+        tracing::info!(
+            timeout = ?self.timeout,
+            "Task backtraces require --cfg tokio_unstable --cfg tokio_taskdump"
+        );
+    }
+}
+
+impl Default for DiagnosticsDaemon {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Daemon for DiagnosticsDaemon {
+    async fn run(&self, _app: &App, shutdown: CancellationToken) -> Result<(), StdError> {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{SignalKind, signal};
+
+            let mut user_defined1 = signal(SignalKind::user_defined1()).map_err(Box::new)?;
+            loop {
+                tokio::select! {
+                    _ = user_defined1.recv() => self.dump().await,
+                    _ = shutdown.cancelled() => return Ok(()),
+                }
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            tracing::warn!("Runtime dumps on signal are only supported on Unix");
+            shutdown.cancelled().await;
+            Ok(())
+        }
+    }
+}
+
+/// Extension trait for `AppBuilder` to register the [`DiagnosticsDaemon`].
+pub trait AddDiagnosticsExt {
+    /// Registers a [`DiagnosticsDaemon`] with the default timeout, unless one
+    /// is registered already.
+    fn add_diagnostics_daemon(&mut self) -> &mut Self;
+}
+
+impl AddDiagnosticsExt for AppBuilder {
+    fn add_diagnostics_daemon(&mut self) -> &mut Self {
+        if !self.has_daemon::<DiagnosticsDaemon>() {
+            self.add_daemon(DiagnosticsDaemon::new());
+        }
+        self
+    }
+}
@@ -4,30 +4,34 @@ use std::collections::BTreeMap;
 use std::marker::PhantomData;
 use std::ops::Deref;
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, RwLock, RwLockReadGuard};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock, RwLockReadGuard, Weak};
 use std::time::Duration;
 
 use diode::{
     AddServiceExt, App, AppBuilder, AppContext, Dependencies, Plugin, Service,
     ServiceDependencyExt, StdError,
 };
-use serde::{Deserialize, Deserializer, Serialize, de::DeserializeOwned};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use tokio_util::sync::CancellationToken;
 
-use crate::{AddDaemonExt, Config, ConfigSection, Daemon, defer};
+use crate::interval::run_interval;
+use crate::{
+    AddDaemonExt, Config, ConfigSection, Daemon, defer, deserialize_env_duration_option,
+    deserialize_env_path_option,
+};
 
 /// Configuration for dynamic config system
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct DynamicConfigConfig {
     /// Path to cache file for persistent storage
-    #[serde(default)]
+    #[serde(default, deserialize_with = "deserialize_env_path_option")]
     pub cache_path: Option<PathBuf>,
     /// How often to write cache to disk (default: 1 second)
-    #[serde(default, deserialize_with = "deserialize_duration_option")]
+    #[serde(default, deserialize_with = "deserialize_env_duration_option")]
     pub cache_period: Option<Duration>,
     /// Path to fallback config file
-    #[serde(default)]
+    #[serde(default, deserialize_with = "deserialize_env_path_option")]
     pub fallback_path: Option<PathBuf>,
 }
 
@@ -46,7 +50,9 @@ pub struct DynamicConfig {
     /// Flag indicating cache needs to be written to disk
     cache_dirty: Arc<AtomicBool>,
     /// Event subscribers for configuration changes
-    subscribers: RwLock<BTreeMap<String, Vec<SubscriberFn>>>,
+    subscribers: RwLock<BTreeMap<String, Vec<(u64, SubscriberFn)>>>,
+    /// Id of the next subscriber, used to unsubscribe it
+    next_subscriber_id: AtomicU64,
 }
 
 type SubscriberFn = Box<dyn Fn(Option<&serde_json::Value>) + Send + Sync>;
@@ -86,8 +92,40 @@ impl DynamicConfig {
         DynamicValue { value }
     }
 
+    /// Get a typed handle to the value of `key`, kept up to date with changes
+    ///
+    /// Unlike [`subscribe`](Self::subscribe), the caller does not need to store
+    /// the latest value itself. Dropping the handle unsubscribes it.
+    pub fn watch<T>(self: &Arc<Self>, key: &str) -> ConfigWatch<T>
+    where
+        T: DeserializeOwned + Send + Sync + 'static,
+    {
+        let value = Arc::new(RwLock::new(None));
+        let id = self.add_subscriber(key, {
+            let value = value.clone();
+            move |v: Option<T>| {
+                *value.write().unwrap() = v.map(Arc::new);
+            }
+        });
+        ConfigWatch {
+            key: key.to_string(),
+            id,
+            value,
+            config: Arc::downgrade(self),
+        }
+    }
+
     /// Subscribe to configuration changes for a specific key
     pub fn subscribe<T, F>(&self, key: &str, callback: F)
+    where
+        T: DeserializeOwned + 'static,
+        F: Fn(Option<T>) + Send + Sync + 'static,
+    {
+        self.add_subscriber(key, callback);
+    }
+
+    /// Add subscriber and return its id
+    fn add_subscriber<T, F>(&self, key: &str, callback: F) -> u64
     where
         T: DeserializeOwned + 'static,
         F: Fn(Option<T>) + Send + Sync + 'static,
@@ -113,8 +151,45 @@ impl DynamicConfig {
             let typed_value = value.and_then(|v| serde_json::from_value(v.clone()).ok());
             callback(typed_value);
         });
+        let id = self.next_subscriber_id.fetch_add(1, Ordering::Relaxed);
         let mut subscribers = self.subscribers.write().unwrap();
-        subscribers.entry(key).or_default().push(wrapper);
+        subscribers.entry(key).or_default().push((id, wrapper));
+        id
+    }
+
+    /// Remove subscriber added with [`add_subscriber`](Self::add_subscriber)
+    fn remove_subscriber(&self, key: &str, id: u64) {
+        let mut subscribers = self.subscribers.write().unwrap();
+        if let Some(key_subscribers) = subscribers.get_mut(key) {
+            key_subscribers.retain(|(subscriber_id, _)| *subscriber_id != id);
+            if key_subscribers.is_empty() {
+                subscribers.remove(key);
+            }
+        }
+    }
+
+    /// Get effective configuration: cached values merged over fallback values
+    pub fn snapshot(&self) -> BTreeMap<String, serde_json::Value> {
+        let cache = self.cache.read().unwrap();
+        let mut snapshot = self.fallback.clone();
+        snapshot.extend(cache.iter().map(|(k, v)| (k.clone(), v.clone())));
+        snapshot
+    }
+
+    /// Get source of every key in [`snapshot`](Self::snapshot)
+    pub fn sources(&self) -> BTreeMap<String, DynamicConfigSource> {
+        let cache = self.cache.read().unwrap();
+        let mut sources: BTreeMap<_, _> = self
+            .fallback
+            .keys()
+            .map(|k| (k.clone(), DynamicConfigSource::Fallback))
+            .collect();
+        sources.extend(
+            cache
+                .keys()
+                .map(|k| (k.clone(), DynamicConfigSource::Cache)),
+        );
+        sources
     }
 
     /// Update configuration snapshot (internal method for providers)
@@ -187,7 +262,7 @@ impl DynamicConfig {
             if let Some(key_subscribers) = subscribers.get(&key) {
                 let value = cache.get(&key).or_else(|| self.fallback.get(&key));
 
-                for subscriber in key_subscribers {
+                for (_, subscriber) in key_subscribers {
                     subscriber(value);
                 }
             }
@@ -206,6 +281,16 @@ impl DynamicConfig {
     }
 }
 
+/// Origin of an effective dynamic config value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DynamicConfigSource {
+    /// Value from provider (or its on-disk cache)
+    Cache,
+    /// Value from fallback file, not overridden by provider
+    Fallback,
+}
+
 pub struct DynamicValue<T> {
     value: Arc<RwLock<Option<T>>>,
 }
@@ -240,28 +325,30 @@ impl<T> Deref for DynamicValueGuard<'_, T> {
     }
 }
 
-/// Custom deserializer for optional Duration that supports string format like "1s", "100ms", etc.
-fn deserialize_duration_option<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    use serde::de::Error;
+/// Typed handle to a dynamic config value, created by [`DynamicConfig::watch`]
+///
+/// The handle is updated on every change of its key. Dropping it unsubscribes
+/// it from the [`DynamicConfig`].
+pub struct ConfigWatch<T> {
+    key: String,
+    id: u64,
+    value: Arc<RwLock<Option<Arc<T>>>>,
+    config: Weak<DynamicConfig>,
+}
 
-    #[derive(Deserialize)]
-    #[serde(untagged)]
-    enum DurationValue {
-        String(String),
-        Number(u64),
+impl<T> ConfigWatch<T> {
+    /// Get latest value, or `None` if the key is missing or cannot be
+    /// deserialized
+    pub fn get(&self) -> Option<Arc<T>> {
+        self.value.read().unwrap().clone()
     }
+}
 
-    let value: Option<DurationValue> = Option::deserialize(deserializer)?;
-
-    match value {
-        None => Ok(None),
-        Some(DurationValue::String(s)) => duration_str::parse(&s)
-            .map(Some)
-            .map_err(|e| D::Error::custom(format!("Invalid duration format '{s}': {e}"))),
-        Some(DurationValue::Number(n)) => Ok(Some(Duration::from_secs(n))),
+impl<T> Drop for ConfigWatch<T> {
+    fn drop(&mut self) {
+        if let Some(config) = self.config.upgrade() {
+            config.remove_subscriber(&self.key, self.id);
+        }
     }
 }
 
@@ -288,6 +375,12 @@ pub trait DynamicConfigService: Service<Handle = Arc<Self>> {
 }
 
 /// Updater interface for providers to update configuration
+///
+/// The dynamic config plugin also registers one as a component, for admin
+/// tooling that changes values at runtime. Such changes are in memory only:
+/// they are written to `cache_path` like provider updates, and are overwritten
+/// by the next provider update of the same key.
+#[derive(Clone)]
 pub struct DynamicConfigUpdater {
     config: Arc<DynamicConfig>,
 }
@@ -320,7 +413,7 @@ where
         // Get plugin configuration
         let config = ctx
             .get_component_ref::<Config>()
-            .unwrap()
+            .ok_or("Config component is missing")?
             .get::<DynamicConfigConfig>("dynamic_config")
             .unwrap_or_default();
         // Get fallback config
@@ -357,8 +450,12 @@ where
             cache: RwLock::new(cache),
             cache_dirty: Arc::new(AtomicBool::new(cache_dirty)),
             subscribers: Default::default(),
+            next_subscriber_id: AtomicU64::new(0),
         });
         ctx.add_component(dynamic_config.clone());
+        ctx.add_component(DynamicConfigUpdater {
+            config: dynamic_config.clone(),
+        });
         ctx.add_daemon(DynamicConfigDaemon {
             dynamic_config,
             service,
@@ -431,31 +528,25 @@ where
                 .cache_period
                 .unwrap_or_else(|| Duration::from_secs(10));
             tracing::debug!(parent: &span, cache_period = ?cache_period, "Starting cache persistence loop");
-            loop {
-                tokio::select! {
-                    _ = tokio::time::sleep(cache_period) => {
-                        if dynamic_config.cache_dirty.compare_exchange(
-                            true,
-                            false,
-                            std::sync::atomic::Ordering::Relaxed,
-                            std::sync::atomic::Ordering::Relaxed
-                        ).is_ok()
-                            && let Err(e) = dynamic_config.save_cache(cache_path).await
-                        {
-                            tracing::warn!(parent: &span, error = %e, "Failed to save cache to disk");
-                            dynamic_config.cache_dirty.store(true, std::sync::atomic::Ordering::Relaxed);
-                        }
-                    }
-                    _ = shutdown.cancelled() => {
-                        if dynamic_config.cache_dirty.load(std::sync::atomic::Ordering::Relaxed) {
-                            if let Err(e) = dynamic_config.save_cache(cache_path).await {
-                                tracing::warn!(parent: &span, error = %e, "Failed to save dynamic config cache during shutdown");
-                            } else {
-                                tracing::debug!(parent: &span, "Saved dynamic config cache during shutdown");
-                            }
-                        }
-                        break;
-                    }
+            let dynamic_config = &dynamic_config;
+            let span = &span;
+            run_interval(cache_period, None, &shutdown, || async move {
+                if dynamic_config
+                    .cache_dirty
+                    .compare_exchange(true, false, Ordering::Relaxed, Ordering::Relaxed)
+                    .is_ok()
+                    && let Err(e) = dynamic_config.save_cache(cache_path).await
+                {
+                    tracing::warn!(parent: span, error = %e, "Failed to save cache to disk");
+                    dynamic_config.cache_dirty.store(true, Ordering::Relaxed);
+                }
+            })
+            .await;
+            if dynamic_config.cache_dirty.load(Ordering::Relaxed) {
+                if let Err(e) = dynamic_config.save_cache(cache_path).await {
+                    tracing::warn!(parent: span, error = %e, "Failed to save dynamic config cache during shutdown");
+                } else {
+                    tracing::debug!(parent: span, "Saved dynamic config cache during shutdown");
                 }
             }
         } else {
@@ -13,6 +13,7 @@ use super::DynamicConfigUpdater;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DynamicConfigFileConfig {
+    #[serde(deserialize_with = "crate::deserialize_env_path")]
     pub path: PathBuf,
 }
 
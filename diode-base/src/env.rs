@@ -0,0 +1,110 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use diode::StdError;
+use serde::{Deserialize, Deserializer, de::Error};
+
+/// Expands `${NAME}` and `${NAME:-default}` references to environment
+/// variables in `text`.
+///
+/// `${NAME:-default}` falls back to `default` when `NAME` is unset or empty;
+/// `${NAME}` requires the variable. Write `$$` for a literal `$`.
+///
+/// ```rust
+/// use diode_base::expand_env;
+///
+/// let value = expand_env("${DIODE_DOC_UNSET_TIMEOUT:-10s}").unwrap();
+/// assert_eq!(value, "10s");
+/// ```
+pub fn expand_env(text: &str) -> Result<String, StdError> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(pos) = rest.find('$') {
+        result.push_str(&rest[..pos]);
+        rest = &rest[pos + 1..];
+        if let Some(tail) = rest.strip_prefix('$') {
+            result.push('$');
+            rest = tail;
+            continue;
+        }
+        let Some(tail) = rest.strip_prefix('{') else {
+            result.push('$');
+            continue;
+        };
+        let end = tail
+            .find('}')
+            .ok_or_else(|| format!("Unclosed `${{` in config value: {text}"))?;
+        let (name, default) = match tail[..end].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&tail[..end], None),
+        };
+        if name.is_empty() {
+            return Err(format!("Empty variable name in config value: {text}").into());
+        }
+        match (std::env::var(name), default) {
+            (Ok(value), Some(default)) if value.is_empty() => result.push_str(default),
+            (Ok(value), _) => result.push_str(&value),
+            (Err(_), Some(default)) => result.push_str(default),
+            (Err(err), None) => {
+                return Err(format!("Failed to resolve ${{{name}}}: {err}").into());
+            }
+        }
+        rest = &tail[end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DurationValue {
+    String(String),
+    Number(u64),
+}
+
+/// Deserializes an optional [`Duration`] such as `"1s"` or `"100ms"`, after
+/// [`expand_env`]; a number is read as seconds.
+///
+/// Use it as `#[serde(default, deserialize_with = "deserialize_env_duration_option")]`,
+/// so that a field like `cache_period: "${CACHE_PERIOD:-10s}"` can be tuned
+/// from the environment.
+pub fn deserialize_env_duration_option<'de, D>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<DurationValue>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(DurationValue::String(s)) => {
+            let s = expand_env(&s).map_err(D::Error::custom)?;
+            duration_str::parse(&s)
+                .map(Some)
+                .map_err(|e| D::Error::custom(format!("Invalid duration format '{s}': {e}")))
+        }
+        Some(DurationValue::Number(n)) => Ok(Some(Duration::from_secs(n))),
+    }
+}
+
+/// Deserializes a [`PathBuf`] after [`expand_env`], e.g.
+/// `"${STATE_DIR:-/var/lib/app}/cache.json"`.
+pub fn deserialize_env_path<'de, D>(deserializer: D) -> Result<PathBuf, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let path = String::deserialize(deserializer)?;
+    expand_env(&path)
+        .map(PathBuf::from)
+        .map_err(D::Error::custom)
+}
+
+/// Optional variant of [`deserialize_env_path`].
+pub fn deserialize_env_path_option<'de, D>(deserializer: D) -> Result<Option<PathBuf>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|path| expand_env(&path).map(PathBuf::from))
+        .transpose()
+        .map_err(D::Error::custom)
+}
@@ -0,0 +1,120 @@
+use std::time::Duration;
+
+use diode::{App, StdError};
+use rand::Rng as _;
+use tokio::time::{Instant, MissedTickBehavior};
+
+use crate::{CancellationToken, Daemon, defer};
+
+/// A [`Daemon`] that runs a task periodically until shutdown.
+///
+/// The task is invoked once per `period`, starting one period after the daemon
+/// starts. Ticks are scheduled with [`tokio::time::interval`], so a slow task
+/// delays the next run instead of making the schedule drift or burst. An
+/// optional jitter adds a random delay (up to the given duration) before each
+/// run, which spreads out replicas started at the same moment.
+///
+/// Errors returned by the task are logged and do not stop the daemon. A run
+/// that has already started is allowed to finish when shutdown is requested.
+///
+/// The task receives the built [`App`]; like
+/// [`add_fn_daemon`](crate::AddDaemonExt::add_fn_daemon), the returned future
+/// cannot borrow it, so resolve components before the `async move` block.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use diode::App;
+/// use diode_base::{AddDaemonExt, IntervalDaemon};
+///
+/// let builder = App::builder();
+/// builder.add_daemon(
+///     IntervalDaemon::new(Duration::from_secs(30), |_app: &App| async move {
+///         tracing::info!("tick");
+///         Ok(())
+///     })
+///     .with_jitter(Duration::from_secs(5)),
+/// );
+/// ```
+pub struct IntervalDaemon<F> {
+    period: Duration,
+    jitter: Option<Duration>,
+    task: F,
+}
+
+impl<F> IntervalDaemon<F> {
+    /// Creates a daemon that runs `task` every `period`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period` is zero.
+    pub fn new(period: Duration, task: F) -> Self {
+        assert!(!period.is_zero(), "Interval period must be non-zero");
+        Self {
+            period,
+            jitter: None,
+            task,
+        }
+    }
+
+    /// Delays each run by a random duration between zero and `jitter`.
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = Some(jitter);
+        self
+    }
+}
+
+impl<F, Fut> Daemon for IntervalDaemon<F>
+where
+    F: Fn(&App) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<(), StdError>> + Send,
+{
+    async fn run(&self, app: &App, shutdown: CancellationToken) -> Result<(), StdError> {
+        let span = tracing::info_span!("interval_daemon", period = ?self.period);
+        tracing::info!(parent: &span, "Interval daemon starting");
+        defer! {
+            tracing::info!(parent: &span, "Interval daemon stopped");
+        }
+        run_interval(self.period, self.jitter, &shutdown, || {
+            let task = (self.task)(app);
+            let span = &span;
+            async move {
+                if let Err(e) = task.await {
+                    tracing::warn!(parent: span, error = %e, "Interval task failed");
+                }
+            }
+        })
+        .await;
+        Ok(())
+    }
+}
+
+/// Calls `task` every `period` (plus optional jitter) until `shutdown` fires.
+pub(crate) async fn run_interval<F, Fut>(
+    period: Duration,
+    jitter: Option<Duration>,
+    shutdown: &CancellationToken,
+    mut task: F,
+) where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.cancelled() => return,
+        }
+        if let Some(jitter) = jitter.filter(|v| !v.is_zero()) {
+            let delay = rand::thread_rng().gen_range(Duration::ZERO..=jitter);
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = shutdown.cancelled() => return,
+            }
+        }
+        task().await;
+    }
+}
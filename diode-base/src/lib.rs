@@ -85,8 +85,11 @@ mod command;
 mod config;
 mod daemon;
 mod defer;
+mod diagnostics;
 mod dynamic_config;
 mod dynamic_config_file;
+mod env;
+mod interval;
 mod metrics;
 mod tracing;
 
@@ -97,8 +100,11 @@ pub use command::*;
 pub use config::*;
 pub use daemon::*;
 pub use defer::*;
+pub use diagnostics::*;
 pub use dynamic_config::*;
 pub use dynamic_config_file::*;
+pub use env::*;
+pub use interval::*;
 pub use metrics::*;
 pub use tracing::*;
 
@@ -106,3 +112,4 @@ pub use tracing::*;
 pub use diode_base_macros::*;
 
 pub use async_trait::async_trait;
+pub use clap;
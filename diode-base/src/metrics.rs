@@ -1,14 +1,14 @@
+use std::net::{TcpStream, ToSocketAddrs};
 use std::time::Duration;
 
-use diode::{AppContext, StdError};
-use duration_str::deserialize_option_duration;
+use diode::{AppContext, PluginError, StdError};
 use opentelemetry::KeyValue;
 use opentelemetry_otlp::WithExportConfig;
 use opentelemetry_sdk::metrics::{MeterProviderBuilder, PeriodicReader, SdkMeterProvider};
 use opentelemetry_sdk::{Resource, runtime};
 use serde::{Deserialize, Serialize};
 
-use crate::{Config, ConfigSection};
+use crate::{Config, ConfigSection, deserialize_env_duration_option};
 
 pub struct Metrics {
     meter_provider: SdkMeterProvider,
@@ -21,26 +21,28 @@ impl Metrics {
         }
         let config = match ctx
             .get_component_ref::<Config>()
-            .unwrap()
-            .get::<Option<MetricsConfig>>("metrics")?
+            .ok_or("Config component is missing")?
+            .get::<Option<MetricsConfig>>("metrics")
+            .map_err(PluginError::misconfiguration)?
         {
             Some(v) => v,
             None => return Ok(()),
         };
         let meter_provider = {
             if let Some(otlp_exporter) = config.otlp_exporter {
+                let endpoint = otlp_exporter
+                    .endpoint
+                    .unwrap_or(DEFAULT_OTLP_EXPORTER_ENDPOINT.into());
+                let timeout = otlp_exporter
+                    .timeout
+                    .unwrap_or(DEFAULT_OTLP_EXPORTER_TIMEOUT);
+                if otlp_exporter.verify_on_start {
+                    verify_otlp_endpoint(&endpoint, timeout).map_err(PluginError::unavailable)?;
+                }
                 let exporter = opentelemetry_otlp::MetricExporter::builder()
                     .with_tonic()
-                    .with_endpoint(
-                        otlp_exporter
-                            .endpoint
-                            .unwrap_or(DEFAULT_OTLP_EXPORTER_ENDPOINT.into()),
-                    )
-                    .with_timeout(
-                        otlp_exporter
-                            .timeout
-                            .unwrap_or(DEFAULT_OTLP_EXPORTER_TIMEOUT),
-                    )
+                    .with_endpoint(endpoint)
+                    .with_timeout(timeout)
                     .build()?;
                 let reader = PeriodicReader::builder(exporter, runtime::Tokio)
                     .with_interval(
@@ -88,10 +90,14 @@ pub struct MetricsOtlpExporterConfig {
     pub service_name: Option<String>,
     #[serde(default)]
     pub endpoint: Option<String>,
-    #[serde(default, deserialize_with = "deserialize_option_duration")]
+    #[serde(default, deserialize_with = "deserialize_env_duration_option")]
     pub timeout: Option<Duration>,
-    #[serde(default, deserialize_with = "deserialize_option_duration")]
+    #[serde(default, deserialize_with = "deserialize_env_duration_option")]
     pub interval: Option<Duration>,
+    /// Fails [`Metrics::build`] if the endpoint does not accept a TCP
+    /// connection within `timeout`, instead of silently dropping exports.
+    #[serde(default)]
+    pub verify_on_start: bool,
 }
 
 impl ConfigSection for MetricsConfig {
@@ -100,6 +106,33 @@ impl ConfigSection for MetricsConfig {
     }
 }
 
+/// Checks that the OTLP collector at `endpoint` accepts TCP connections.
+pub(crate) fn verify_otlp_endpoint(endpoint: &str, timeout: Duration) -> Result<(), StdError> {
+    let (scheme, rest) = endpoint.split_once("://").unwrap_or(("http", endpoint));
+    let authority = rest.split('/').next().unwrap_or_default();
+    let has_port = authority
+        .rsplit_once(':')
+        .is_some_and(|(_, port)| !port.ends_with(']'));
+    let addr = if has_port {
+        authority.to_string()
+    } else {
+        let port = if scheme == "https" { 443 } else { 80 };
+        format!("{authority}:{port}")
+    };
+    let mut last_err = None;
+    for addr in addr.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(_) => return Ok(()),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(match last_err {
+        Some(err) => format!("OTLP endpoint {endpoint} is unreachable: {err}"),
+        None => format!("OTLP endpoint {endpoint} has no address"),
+    }
+    .into())
+}
+
 const DEFAULT_OTLP_EXPORTER_ENDPOINT: &str = "https://localhost:4317/v1/metrics";
 const DEFAULT_OTLP_EXPORTER_TIMEOUT: Duration = Duration::from_secs(10);
 const DEFAULT_OTLP_EXPORTER_INTERVAL: Duration = Duration::from_secs(10);
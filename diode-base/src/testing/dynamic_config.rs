@@ -0,0 +1,121 @@
+//! In-memory dynamic config provider for tests.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use diode::{AppContext, Service, StdError};
+use serde_json::Value;
+
+use crate::{CancellationToken, DynamicConfigService, DynamicConfigUpdater};
+
+/// A [`DynamicConfigService`] backed by an in-memory map.
+///
+/// Register a seeded instance in place of a real provider, then simulate
+/// changes with [`push`](MockDynamicConfig::push) and
+/// [`remove`](MockDynamicConfig::remove) while the daemons run:
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use diode::{AddServiceExt as _, App};
+/// use diode_base::testing::MockDynamicConfig;
+/// use diode_base::{AddDynamicConfigExt as _, Config};
+///
+/// # async fn example() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// let mock = Arc::new(MockDynamicConfig::new().with("limit", 10.into()));
+/// let app = App::builder()
+///     .add_component(Config::new())
+///     .add_service_instance::<MockDynamicConfig>(mock.clone())
+///     .add_dynamic_config::<MockDynamicConfig>()
+///     .build()
+///     .await?;
+/// // Run the daemons, then:
+/// mock.watching().await;
+/// mock.push("limit", 20.into());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct MockDynamicConfig {
+    state: Mutex<MockState>,
+    watching: CancellationToken,
+}
+
+#[derive(Default)]
+struct MockState {
+    values: BTreeMap<String, Value>,
+    updater: Option<DynamicConfigUpdater>,
+}
+
+impl MockDynamicConfig {
+    /// Creates an empty provider.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the initial `value` of `key`.
+    pub fn with(self, key: impl Into<String>, value: Value) -> Self {
+        self.state.lock().unwrap().values.insert(key.into(), value);
+        self
+    }
+
+    /// Sets `key` to `value` and notifies the subscribers of `key`.
+    ///
+    /// Subscribers are notified before this returns once the provider is
+    /// [`watching`](MockDynamicConfig::watching); earlier changes are part of
+    /// the snapshot loaded when the dynamic config daemon starts.
+    pub fn push(&self, key: impl Into<String>, value: Value) {
+        let key = key.into();
+        let mut state = self.state.lock().unwrap();
+        state.values.insert(key.clone(), value.clone());
+        if let Some(updater) = &state.updater {
+            updater.update_key(key, value);
+        }
+    }
+
+    /// Removes `key` and notifies its subscribers, like
+    /// [`push`](MockDynamicConfig::push).
+    pub fn remove(&self, key: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.values.remove(key);
+        if let Some(updater) = &state.updater {
+            updater.remove_key(key);
+        }
+    }
+
+    /// Waits until the dynamic config daemon watches this provider.
+    pub async fn watching(&self) {
+        self.watching.cancelled().await
+    }
+}
+
+impl Service for MockDynamicConfig {
+    type Handle = Arc<Self>;
+
+    async fn build(_ctx: &AppContext) -> Result<Self::Handle, StdError> {
+        Ok(Arc::new(Self::new()))
+    }
+}
+
+impl DynamicConfigService for MockDynamicConfig {
+    async fn get_snapshot(&self) -> Result<BTreeMap<String, Value>, StdError> {
+        Ok(self.state.lock().unwrap().values.clone())
+    }
+
+    async fn watch_changes(
+        &self,
+        updater: DynamicConfigUpdater,
+        shutdown: CancellationToken,
+    ) -> Result<(), StdError> {
+        {
+            let mut state = self.state.lock().unwrap();
+            // Catch up with changes pushed since the initial snapshot.
+            updater.set_snapshot(state.values.clone());
+            state.updater = Some(updater);
+        }
+        self.watching.cancel();
+        shutdown.cancelled().await;
+        self.state.lock().unwrap().updater = None;
+        Ok(())
+    }
+}
@@ -72,6 +72,32 @@ impl FreePort {
     pub fn as_addr(&self) -> SocketAddr {
         SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, self.0))
     }
+
+    /// Binds the port and returns the listener together with its address
+    ///
+    /// Passing the bound listener to the server, instead of an address it
+    /// binds itself, closes the window in which another process may take the
+    /// port. If the port has been taken already, an ephemeral port is bound
+    /// instead, so use the returned address rather than [`as_addr`](FreePort::as_addr).
+    ///
+    /// The listener is in non-blocking mode, ready for
+    /// `tokio::net::TcpListener::from_std`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no port can be bound.
+    pub fn into_listener(self) -> (SocketAddr, TcpListener) {
+        let listener = TcpListener::bind(self.as_addr())
+            .or_else(|_| TcpListener::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0)))
+            .expect("Unable to bind a free port");
+        listener
+            .set_nonblocking(true)
+            .expect("Unable to make the listener non-blocking");
+        let addr = listener
+            .local_addr()
+            .expect("Unable to get the listener address");
+        (addr, listener)
+    }
 }
 
 impl Drop for FreePort {
@@ -174,6 +200,16 @@ mod tests {
         assert_eq!(ports.len(), 10);
     }
 
+    #[test]
+    fn test_into_listener() {
+        let (addr, listener) = FreePort::new().into_listener();
+        assert_eq!(listener.local_addr().unwrap(), addr);
+        assert!(addr.ip().is_loopback());
+
+        // The port stays bound by the listener, so it can't be taken.
+        assert!(TcpListener::bind(addr).is_err());
+    }
+
     #[test]
     fn test_as_addr_format() {
         let port = FreePort::new();
@@ -1,3 +1,5 @@
+mod dynamic_config;
 mod free_port;
 
+pub use dynamic_config::*;
 pub use free_port::*;
@@ -4,8 +4,7 @@ use std::str::FromStr as _;
 use std::sync::Arc;
 use std::time::Duration;
 
-use diode::{App, AppContext, StdError};
-use duration_str::deserialize_option_duration;
+use diode::{App, AppContext, PluginError, StdError};
 use opentelemetry::trace::{SpanKind, TracerProvider as _};
 use opentelemetry::{Key, KeyValue};
 use opentelemetry_otlp::WithExportConfig;
@@ -18,11 +17,16 @@ use tracing_subscriber::layer::SubscriberExt as _;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::{Registry, reload};
 
-use crate::{AddDaemonExt, CancellationToken, Config, ConfigSection, Daemon, DynamicConfig};
+use crate::metrics::verify_otlp_endpoint;
+use crate::{
+    AddDaemonExt, CancellationToken, Config, ConfigSection, Daemon, DynamicConfig,
+    deserialize_env_duration_option,
+};
 
 pub struct Tracing {
     default_level: tracing::Level,
     directives: Vec<Directive>,
+    env_directives: Vec<Directive>,
     reload_handle: reload::Handle<EnvFilter, Registry>,
     tracer_provider: TracerProvider,
 }
@@ -37,34 +41,41 @@ impl Tracing {
         }
         let config = match ctx
             .get_component_ref::<Config>()
-            .unwrap()
-            .get::<Option<TracingConfig>>("tracing")?
+            .ok_or("Config component is missing")?
+            .get::<Option<TracingConfig>>("tracing")
+            .map_err(PluginError::misconfiguration)?
         {
             Some(v) => v,
             None => return Ok(()),
         };
         let mut directives = Vec::new();
         for directive in config.directives {
-            directives.push(directive.parse().map_err(Box::new)?);
+            directives.push(directive.parse().map_err(PluginError::misconfiguration)?);
         }
+        let env_directives = if config.use_rust_log {
+            rust_log_directives().map_err(PluginError::misconfiguration)?
+        } else {
+            Vec::new()
+        };
         // Setup dynamic config level filter.
         let (env_filter, reload_handle) =
-            reload::Layer::new(new_env_filter(&directives, config.level));
+            reload::Layer::new(new_env_filter(&directives, config.level, &env_directives));
         // Setup OpenTelemetry tracer.
         let tracer_provider = {
             if let Some(otlp_exporter) = config.otlp_exporter {
+                let endpoint = otlp_exporter
+                    .endpoint
+                    .unwrap_or(DEFAULT_OTLP_EXPORTER_ENDPOINT.into());
+                let timeout = otlp_exporter
+                    .timeout
+                    .unwrap_or(DEFAULT_OTLP_EXPORTER_TIMEOUT);
+                if otlp_exporter.verify_on_start {
+                    verify_otlp_endpoint(&endpoint, timeout).map_err(PluginError::unavailable)?;
+                }
                 let exporter_builder = opentelemetry_otlp::SpanExporter::builder()
                     .with_tonic()
-                    .with_endpoint(
-                        otlp_exporter
-                            .endpoint
-                            .unwrap_or(DEFAULT_OTLP_EXPORTER_ENDPOINT.into()),
-                    )
-                    .with_timeout(
-                        otlp_exporter
-                            .timeout
-                            .unwrap_or(DEFAULT_OTLP_EXPORTER_TIMEOUT),
-                    );
+                    .with_endpoint(endpoint)
+                    .with_timeout(timeout);
                 let exporter = CustomSpanExporter::new(exporter_builder.build().unwrap());
                 TracerProvider::builder()
                     .with_resource(Resource::new(vec![KeyValue::new(
@@ -87,6 +98,7 @@ impl Tracing {
         ctx.add_component(Self {
             default_level: config.level,
             directives,
+            env_directives,
             reload_handle,
             tracer_provider,
         });
@@ -111,9 +123,12 @@ const TRACING_LEVEL_CONFIG_KEY: &str = "tracing_level";
 
 impl Daemon for TracingDaemon {
     async fn run(&self, app: &App, shutdown: CancellationToken) -> Result<(), StdError> {
-        let tracing = app.get_component_ref::<Tracing>().unwrap();
+        let tracing = app
+            .get_component_ref::<Tracing>()
+            .ok_or("Tracing component is missing")?;
         let default_level = tracing.default_level;
         let directives = tracing.directives.clone();
+        let env_directives = tracing.env_directives.clone();
         let reload_handle = tracing.reload_handle.clone();
         if let Some(dynamic_config) = app.get_component::<Arc<DynamicConfig>>() {
             dynamic_config.subscribe(TRACING_LEVEL_CONFIG_KEY, move |level: Option<String>| {
@@ -128,7 +143,7 @@ impl Daemon for TracingDaemon {
                     None => default_level,
                 };
                 reload_handle
-                    .reload(new_env_filter(&directives, level))
+                    .reload(new_env_filter(&directives, level, &env_directives))
                     .unwrap();
             });
         }
@@ -142,6 +157,7 @@ impl Default for TracingConfig {
         Self {
             level: default_level(),
             directives: Default::default(),
+            use_rust_log: default_use_rust_log(),
             otlp_exporter: None,
         }
     }
@@ -153,8 +169,12 @@ pub struct TracingOtlpExporterConfig {
     pub service_name: Option<String>,
     #[serde(default)]
     pub endpoint: Option<String>,
-    #[serde(default, deserialize_with = "deserialize_option_duration")]
+    #[serde(default, deserialize_with = "deserialize_env_duration_option")]
     pub timeout: Option<Duration>,
+    /// Fails [`Tracing::build`] if the endpoint does not accept a TCP
+    /// connection within `timeout`.
+    #[serde(default)]
+    pub verify_on_start: bool,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -167,6 +187,15 @@ pub struct TracingConfig {
     pub level: tracing::Level,
     #[serde(default)]
     pub directives: Vec<String>,
+    /// Applies the directives of the `RUST_LOG` environment variable on top
+    /// of `level` and `directives`, so that `RUST_LOG=debug cargo run` works.
+    ///
+    /// `RUST_LOG` takes precedence: its directives override config
+    /// directives for the same target, and a bare level in it overrides
+    /// both `level` and the dynamic `tracing_level` key. An invalid
+    /// `RUST_LOG` fails [`Tracing::build`]. Enabled by default.
+    #[serde(default = "default_use_rust_log")]
+    pub use_rust_log: bool,
     #[serde(default)]
     pub otlp_exporter: Option<TracingOtlpExporterConfig>,
 }
@@ -177,12 +206,38 @@ impl ConfigSection for TracingConfig {
     }
 }
 
-fn new_env_filter(directives: &Vec<Directive>, level: tracing::Level) -> EnvFilter {
+fn new_env_filter(
+    directives: &Vec<Directive>,
+    level: tracing::Level,
+    env_directives: &Vec<Directive>,
+) -> EnvFilter {
     let mut filter = EnvFilter::default();
     for directive in directives {
         filter = filter.add_directive(directive.clone());
     }
-    filter.add_directive(level.into())
+    filter = filter.add_directive(level.into());
+    // Added last, so that they win over the config.
+    for directive in env_directives {
+        filter = filter.add_directive(directive.clone());
+    }
+    filter
+}
+
+/// Parses the directives of the `RUST_LOG` environment variable, if set.
+fn rust_log_directives() -> Result<Vec<Directive>, StdError> {
+    let Ok(value) = std::env::var(EnvFilter::DEFAULT_ENV) else {
+        return Ok(Vec::new());
+    };
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(|v| {
+            v.parse().map_err(|err| {
+                format!("Invalid {} directive {v}: {err}", EnvFilter::DEFAULT_ENV).into()
+            })
+        })
+        .collect()
 }
 
 const DEFAULT_OTLP_EXPORTER_ENDPOINT: &str = "https://localhost:4317/v1/traces";
@@ -275,3 +330,7 @@ where
 fn default_level() -> tracing::Level {
     tracing::Level::DEBUG
 }
+
+fn default_use_rust_log() -> bool {
+    true
+}
@@ -1,8 +1,13 @@
-use clap::{Arg, ArgMatches, Command as ClapCommand};
-use diode::App;
-use diode_base::{AddCommandExt, Command, CommandRegistry, Config, ConfigCommand, ServerCommand};
+use clap::{Arg, ArgAction, ArgMatches, Command as ClapCommand};
+use diode::{AddServiceExt as _, App, Service, StdError};
+use diode_base::{
+    AddCommandExt, AddCommandServiceExt as _, AddDaemonExt as _, BuildAndRunExt as _, Command,
+    CommandRegistry, Config, ConfigCommand, RootMatches, ServerCommand, TypedCommand, command,
+    config_section,
+};
+use serde::{Deserialize, Serialize};
 use std::process::ExitCode;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio::time::sleep;
 
@@ -49,6 +54,101 @@ impl Command for DefaultCommand {
     }
 }
 
+// Command implemented through the fallible `run`
+struct RunCommand;
+
+impl Command for RunCommand {
+    fn command() -> ClapCommand {
+        ClapCommand::new("run").arg(Arg::new("fail").long("fail").action(ArgAction::SetTrue))
+    }
+
+    async fn run(_app: Arc<App>, matches: ArgMatches) -> Result<(), StdError> {
+        if matches.get_flag("fail") {
+            return Err("command failed".into());
+        }
+        Ok(())
+    }
+}
+
+// Command reading a root-level argument
+struct TenantCommand;
+
+impl Command for TenantCommand {
+    fn command() -> ClapCommand {
+        ClapCommand::new("tenant")
+    }
+
+    async fn run(app: Arc<App>, _matches: ArgMatches) -> Result<(), StdError> {
+        let root = app
+            .get_component_ref::<RootMatches>()
+            .ok_or("RootMatches component is missing")?;
+        match root.get_one::<String>("tenant").map(String::as_str) {
+            Some("acme") => Ok(()),
+            tenant => Err(format!("unexpected tenant {tenant:?}").into()),
+        }
+    }
+}
+
+// Arguments implemented by hand, as `#[derive(clap::Args)]` would
+struct RepeatArgs {
+    word: String,
+    times: u32,
+}
+
+impl clap::FromArgMatches for RepeatArgs {
+    fn from_arg_matches(matches: &ArgMatches) -> Result<Self, clap::Error> {
+        let word = matches
+            .try_get_one::<String>("word")
+            .ok()
+            .flatten()
+            .ok_or_else(|| clap::Error::new(clap::error::ErrorKind::MissingRequiredArgument))?;
+        Ok(Self {
+            word: word.clone(),
+            times: *matches.get_one::<u32>("times").unwrap_or(&1),
+        })
+    }
+
+    fn update_from_arg_matches(&mut self, matches: &ArgMatches) -> Result<(), clap::Error> {
+        *self = Self::from_arg_matches(matches)?;
+        Ok(())
+    }
+}
+
+impl clap::Args for RepeatArgs {
+    fn augment_args(command: ClapCommand) -> ClapCommand {
+        command.arg(Arg::new("word").required(true)).arg(
+            Arg::new("times")
+                .long("times")
+                .value_parser(clap::value_parser!(u32)),
+        )
+    }
+
+    fn augment_args_for_update(command: ClapCommand) -> ClapCommand {
+        Self::augment_args(command)
+    }
+}
+
+static REPEATED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+// Command receiving typed arguments
+struct RepeatCommand;
+
+impl TypedCommand for RepeatCommand {
+    type Args = RepeatArgs;
+
+    fn command() -> ClapCommand {
+        ClapCommand::new("repeat")
+    }
+
+    async fn run(_app: Arc<App>, args: RepeatArgs) -> Result<(), StdError> {
+        let mut repeated = REPEATED.lock().unwrap();
+        for _ in 0..args.times {
+            repeated.push(args.word.clone());
+        }
+        Ok(())
+    }
+}
+
 // Mock command that takes time to execute
 struct SlowCommand;
 
@@ -105,6 +205,59 @@ async fn test_command_registry_build_cli() {
     );
 }
 
+#[tokio::test]
+async fn test_command_registry_build_cli_from() {
+    let mut registry = CommandRegistry::default();
+    registry.add_command::<MockCommand>();
+
+    let root = ClapCommand::new("my-service").about("My service").arg(
+        Arg::new("verbose")
+            .long("verbose")
+            .action(ArgAction::SetTrue)
+            .global(true),
+    );
+    let cli = registry.build_cli_from(root);
+    assert_eq!(cli.get_name(), "my-service");
+    assert_eq!(cli.get_about().unwrap().to_string(), "My service");
+    assert!(cli.is_subcommand_required_set());
+    assert!(cli.get_arguments().any(|arg| arg.get_id() == "config"));
+
+    let mut matches = cli
+        .try_get_matches_from(["my-service", "-c", "config.json", "mock", "--verbose"])
+        .unwrap();
+    let (name, matches) = matches.remove_subcommand().unwrap();
+    assert_eq!(name, "mock");
+    assert!(matches.get_flag("verbose"));
+}
+
+#[tokio::test]
+async fn test_command_registry_build_cli_set() {
+    let registry = CommandRegistry::default();
+    let cli = registry.build_cli().subcommand(ClapCommand::new("mock"));
+
+    let matches = cli
+        .clone()
+        .try_get_matches_from([
+            "app",
+            "-c",
+            "config.json",
+            "--set",
+            "a.b=1",
+            "--set",
+            "c=x",
+            "mock",
+        ])
+        .unwrap();
+    let overrides: Vec<_> = matches.get_many::<String>("set").unwrap().collect();
+    assert_eq!(overrides, ["a.b=1", "c=x"]);
+
+    // Malformed overrides are rejected while parsing the arguments.
+    let err = cli
+        .try_get_matches_from(["app", "-c", "config.json", "--set", "a.b", "mock"])
+        .unwrap_err();
+    assert!(err.to_string().contains("expected path=value"), "{err}");
+}
+
 #[tokio::test]
 async fn test_command_registry_build_cli_requires_subcommand() {
     let registry = CommandRegistry::default();
@@ -112,6 +265,101 @@ async fn test_command_registry_build_cli_requires_subcommand() {
     assert!(cli.is_subcommand_required_set());
 }
 
+#[tokio::test]
+async fn test_command_registry_run_main_without_subcommand() {
+    let mut registry = CommandRegistry::default();
+    registry.add_command::<MockCommand>();
+    let app = App::builder().build().await.unwrap().into_handle();
+
+    let matches = ClapCommand::new("app").get_matches_from(["app"]);
+    let exit_code = registry
+        .run_main(app.clone(), registry.build_cli(), matches)
+        .await;
+    assert_eq!(exit_code, ExitCode::FAILURE);
+
+    let matches = ClapCommand::new("app")
+        .subcommand(ClapCommand::new("unknown"))
+        .get_matches_from(["app", "unknown"]);
+    let exit_code = registry
+        .run_main(app.clone(), registry.build_cli(), matches)
+        .await;
+    assert_eq!(exit_code, ExitCode::FAILURE);
+
+    let matches = registry.build_cli().get_matches_from([
+        "app",
+        "-c",
+        "config.json",
+        "mock",
+        "--test-arg",
+        "x",
+    ]);
+    let exit_code = registry.run_main(app, registry.build_cli(), matches).await;
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+}
+
+#[tokio::test]
+async fn test_command_registry_run_main_help() {
+    // The help goes to stderr, so it is checked from a child process running
+    // this test again.
+    if std::env::var_os("DIODE_TEST_RUN_MAIN_HELP").is_some() {
+        let mut registry = CommandRegistry::default();
+        registry.add_command::<MockCommand>();
+        let app = App::builder().build().await.unwrap().into_handle();
+        let cli = registry.build_cli_from(ClapCommand::new("my-service").about("My service"));
+        let matches = ClapCommand::new("app").get_matches_from(["app"]);
+        let exit_code = registry.run_main(app, cli, matches).await;
+        assert_eq!(exit_code, ExitCode::FAILURE);
+        return;
+    }
+    let output = std::process::Command::new(std::env::current_exe().unwrap())
+        .args([
+            "test_command_registry_run_main_help",
+            "--exact",
+            "--nocapture",
+        ])
+        .env("DIODE_TEST_RUN_MAIN_HELP", "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("My service"), "{stderr}");
+    assert!(stderr.contains("Usage: my-service"), "{stderr}");
+    assert!(stderr.contains("mock"), "{stderr}");
+}
+
+#[tokio::test]
+async fn test_root_matches() {
+    let mut registry = CommandRegistry::default();
+    registry.add_command::<TenantCommand>();
+    let root = ClapCommand::new("app").arg(Arg::new("tenant").long("tenant"));
+    let matches = registry.build_cli_from(root.clone()).get_matches_from([
+        "app",
+        "-c",
+        "config.json",
+        "--tenant",
+        "acme",
+        "tenant",
+    ]);
+    let app = App::builder()
+        .add_component(RootMatches(matches.clone()))
+        .build()
+        .await
+        .unwrap()
+        .into_handle();
+    let exit_code = registry
+        .run_main(app, registry.build_cli_from(root), matches)
+        .await;
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+
+    // Without the component the command fails instead of guessing.
+    let matches = registry
+        .build_cli()
+        .get_matches_from(["app", "-c", "config.json", "tenant"]);
+    let app = App::builder().build().await.unwrap().into_handle();
+    let exit_code = registry.run_main(app, registry.build_cli(), matches).await;
+    assert_eq!(exit_code, ExitCode::FAILURE);
+}
+
 #[tokio::test]
 async fn test_server_command_definition() {
     let cmd = ServerCommand::command();
@@ -143,6 +391,23 @@ async fn test_config_command_execution() {
     assert_eq!(exit_code, ExitCode::SUCCESS);
 }
 
+#[tokio::test]
+async fn test_config_command_section() {
+    let config = Config::new()
+        .with("app_name", "test_app")
+        .with("server", serde_json::json!({"port": 8080}));
+    let app = Arc::new(App::builder().add_component(config).build().await.unwrap());
+
+    let matches =
+        ConfigCommand::command().get_matches_from(["config", "--section", "server", "--compact"]);
+    let exit_code = ConfigCommand::main(app.clone(), matches).await;
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+
+    let matches = ConfigCommand::command().get_matches_from(["config", "--section", "missing"]);
+    let exit_code = ConfigCommand::main(app, matches).await;
+    assert_eq!(exit_code, ExitCode::FAILURE);
+}
+
 #[tokio::test]
 async fn test_add_command_ext() {
     let mut app_builder = App::builder();
@@ -179,6 +444,45 @@ async fn test_default_command_implementation() {
     assert_eq!(exit_code, ExitCode::FAILURE);
 }
 
+#[tokio::test]
+async fn test_run_command() {
+    let app = Arc::new(App::builder().build().await.unwrap());
+
+    let matches = RunCommand::command().get_matches_from(["run"]);
+    let exit_code = RunCommand::main(app.clone(), matches).await;
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+
+    let matches = RunCommand::command().get_matches_from(["run", "--fail"]);
+    let exit_code = RunCommand::main(app, matches).await;
+    assert_eq!(exit_code, ExitCode::FAILURE);
+}
+
+#[tokio::test]
+async fn test_typed_command() {
+    let app = Arc::new(
+        App::builder()
+            .add_command::<RepeatCommand>()
+            .build()
+            .await
+            .unwrap(),
+    );
+    let registry = app.get_component_ref::<CommandRegistry>().unwrap();
+    let cli = registry.build_cli();
+    let repeat = cli.find_subcommand("repeat").unwrap();
+    assert!(repeat.get_arguments().any(|arg| arg.get_id() == "word"));
+    assert!(repeat.get_arguments().any(|arg| arg.get_id() == "times"));
+
+    let matches =
+        <RepeatCommand as Command>::command().get_matches_from(["repeat", "hi", "--times", "2"]);
+    let exit_code = <RepeatCommand as Command>::main(app.clone(), matches).await;
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+    assert_eq!(*REPEATED.lock().unwrap(), ["hi", "hi"]);
+
+    // Matches without the required argument fail to parse
+    let exit_code = <RepeatCommand as Command>::main(app, ArgMatches::default()).await;
+    assert_eq!(exit_code, ExitCode::FAILURE);
+}
+
 #[tokio::test]
 async fn test_command_registry_with_real_app() {
     let mut app_builder = App::builder();
@@ -204,3 +508,112 @@ async fn test_slow_command_execution() {
     assert_eq!(exit_code, ExitCode::SUCCESS);
     assert!(duration >= Duration::from_millis(50)); // Should take some time
 }
+
+#[tokio::test]
+async fn test_server_command_without_daemons() {
+    let app = Arc::new(App::builder().build().await.unwrap());
+
+    let exit_code = tokio::time::timeout(
+        Duration::from_secs(5),
+        ServerCommand::main(app, ArgMatches::default()),
+    )
+    .await
+    .expect("Server without daemons did not return");
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+}
+
+#[tokio::test]
+async fn test_build_and_run() {
+    let mut app_builder = App::builder();
+    app_builder.add_fn_daemon(|_app, _shutdown| async move { Ok(()) });
+    app_builder.build_and_run().await.unwrap();
+
+    let mut app_builder = App::builder();
+    app_builder.add_fn_daemon(|_app, _shutdown| async move { Err("daemon failed".into()) });
+    let err = app_builder.build_and_run().await.unwrap_err();
+    assert_eq!(err.to_string(), "daemon failed");
+
+    // Without daemons there is nothing to run.
+    App::builder().build_and_run().await.unwrap();
+}
+
+#[derive(Deserialize, Serialize)]
+#[config_section("greeting")]
+struct GreetingConfig {
+    prefix: String,
+}
+
+#[derive(Service)]
+struct Greeter;
+
+impl Greeter {
+    fn greet(&self, prefix: &str, name: &str) -> String {
+        format!("{prefix}, {name}!")
+    }
+}
+
+#[derive(Service)]
+struct GreetCommand {
+    #[inject(Config)]
+    config: GreetingConfig,
+    greeter: Arc<Greeter>,
+}
+
+#[command(name = "greet", about = "Greets someone")]
+impl GreetCommand {
+    #[args]
+    fn args(command: ClapCommand) -> ClapCommand {
+        command.arg(Arg::new("name").required(true))
+    }
+
+    async fn run(&self, matches: ArgMatches) -> Result<(), StdError> {
+        let name = matches.get_one::<String>("name").unwrap();
+        if name.is_empty() {
+            return Err("name is empty".into());
+        }
+        assert_eq!(
+            self.greeter.greet(&self.config.prefix, name),
+            "Hello, World!"
+        );
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_command_service() {
+    let mut app_builder = App::builder();
+    app_builder
+        .add_component(Config::new().with(
+            "greeting",
+            GreetingConfig {
+                prefix: "Hello".to_string(),
+            },
+        ))
+        .add_service::<Greeter>()
+        .add_command_service::<GreetCommand>();
+    assert!(app_builder.has_command_service::<GreetCommand>());
+    let app = Arc::new(app_builder.build().await.unwrap());
+
+    let cmd = GreetCommand::command();
+    assert_eq!(cmd.get_name(), "greet");
+    assert_eq!(cmd.get_about().unwrap().to_string(), "Greets someone");
+
+    let matches = cmd.clone().get_matches_from(["greet", "World"]);
+    let exit_code = GreetCommand::main(app.clone(), matches).await;
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+
+    let matches = cmd.get_matches_from(["greet", ""]);
+    let exit_code = GreetCommand::main(app, matches).await;
+    assert_eq!(exit_code, ExitCode::FAILURE);
+}
+
+#[tokio::test]
+async fn test_command_service_not_registered() {
+    let mut app_builder = App::builder();
+    app_builder.add_command::<GreetCommand>();
+    let app = Arc::new(app_builder.build().await.unwrap());
+
+    let matches = GreetCommand::command().get_matches_from(["greet", "World"]);
+    let exit_code = GreetCommand::main(app, matches).await;
+    assert_eq!(exit_code, ExitCode::FAILURE);
+}
@@ -0,0 +1,429 @@
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use diode::{AddServiceExt as _, App, AppContext, Service, StdError};
+use diode_base::{
+    AddDaemonExt as _, AddDiagnosticsExt as _, AppShutdown, CancellationToken, Config,
+    ConfigSection, Daemon, DaemonReadyExt as _, DaemonWaitFor, DiagnosticsDaemon, IntervalDaemon,
+    RunDaemonsExt as _, ShutdownCause, ShutdownReason,
+};
+use serde::Deserialize;
+
+#[tokio::test]
+async fn test_fn_daemon() {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let mut builder = App::builder();
+    builder.add_component(counter.clone());
+    builder.add_fn_daemon(|app, shutdown| {
+        let counter = app.get_component::<Arc<AtomicUsize>>().unwrap();
+        async move {
+            counter.fetch_add(1, Ordering::SeqCst);
+            shutdown.cancelled_owned().await;
+            Ok(())
+        }
+    });
+    builder.add_fn_daemon(|_app, _shutdown| async move { Ok(()) });
+    let app = builder.build().await.unwrap();
+
+    // The second daemon returns immediately, which stops the first one.
+    app.run_daemons(CancellationToken::new()).await.unwrap();
+    assert_eq!(counter.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_fn_daemon_error() {
+    let mut builder = App::builder();
+    builder.add_fn_daemon(|_app, _shutdown| async move { Err("daemon failed".into()) });
+    builder.add_fn_daemon(|_app, shutdown: CancellationToken| async move {
+        shutdown.cancelled_owned().await;
+        Ok(())
+    });
+    let app = builder.build().await.unwrap();
+
+    let err = app.run_daemons(CancellationToken::new()).await.unwrap_err();
+    assert_eq!(err.to_string(), "daemon failed");
+}
+
+#[derive(Deserialize)]
+struct CounterConfig {
+    step: usize,
+}
+
+impl ConfigSection for CounterConfig {
+    fn key() -> &'static str {
+        "counter"
+    }
+}
+
+struct CounterDaemon(usize);
+
+impl Daemon for CounterDaemon {
+    async fn run(&self, app: &App, _shutdown: CancellationToken) -> Result<(), StdError> {
+        let counter = app.get_component::<Arc<AtomicUsize>>().unwrap();
+        counter.fetch_add(self.0, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_daemon_with_config() {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let mut builder = App::builder();
+    builder
+        .add_component(counter.clone())
+        .add_component(Config::new().with("counter", serde_json::json!({"step": 5})));
+    builder.add_daemon_with_config(|config: CounterConfig| CounterDaemon(config.step));
+    let app = builder.build().await.unwrap();
+
+    app.run_daemons(CancellationToken::new()).await.unwrap();
+    assert_eq!(counter.load(Ordering::SeqCst), 5);
+
+    // A missing section fails the build, not the daemon start.
+    let mut builder = App::builder();
+    builder.add_component(Config::new());
+    builder.add_daemon_with_config(|config: CounterConfig| CounterDaemon(config.step));
+    let err = builder.build().await.err().unwrap();
+    assert!(err.to_string().contains("config section 'counter'"));
+}
+
+#[tokio::test]
+async fn test_add_daemon_if() {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let mut builder = App::builder();
+    builder.add_component(counter.clone());
+    builder
+        .add_daemon_if(false, || -> Result<CounterDaemon, StdError> {
+            unreachable!("disabled daemon must not be built")
+        })
+        .unwrap();
+    assert!(!builder.has_daemon::<CounterDaemon>());
+    builder
+        .add_daemon_if(true, || Ok(CounterDaemon(3)))
+        .unwrap();
+    assert!(builder.has_daemon::<CounterDaemon>());
+    let app = builder.build().await.unwrap();
+
+    app.run_daemons(CancellationToken::new()).await.unwrap();
+    assert_eq!(counter.load(Ordering::SeqCst), 3);
+
+    let builder = App::builder();
+    let err = builder
+        .add_daemon_if(true, || -> Result<CounterDaemon, StdError> {
+            Err("invalid counter".into())
+        })
+        .unwrap_err();
+    assert_eq!(err.to_string(), "invalid counter");
+    assert!(!builder.has_daemon::<CounterDaemon>());
+}
+
+#[tokio::test]
+async fn test_diagnostics_daemon() {
+    let mut builder = App::builder();
+    builder.add_diagnostics_daemon().add_diagnostics_daemon();
+    assert!(builder.has_daemon::<DiagnosticsDaemon>());
+    let app = builder.build().await.unwrap();
+
+    // The daemon waits for signals until shutdown.
+    let shutdown = CancellationToken::new();
+    let task = tokio::spawn(app.run_daemons(shutdown.clone()));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!task.is_finished());
+    shutdown.cancel();
+    task.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_interval_daemon() {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let mut builder = App::builder();
+    builder.add_component(counter.clone());
+    builder.add_daemon(
+        IntervalDaemon::new(Duration::from_millis(10), |app: &App| {
+            let counter = app.get_component::<Arc<AtomicUsize>>().unwrap();
+            async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Err("tick failed".into())
+            }
+        })
+        .with_jitter(Duration::from_millis(5)),
+    );
+    let app = builder.build().await.unwrap();
+
+    // Task errors are logged, so the daemon keeps running until shutdown.
+    let shutdown = CancellationToken::new();
+    let handle = tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move { app.run_daemons(shutdown).await }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    shutdown.cancel();
+    handle.await.unwrap().unwrap();
+    assert!(counter.load(Ordering::SeqCst) >= 2);
+}
+
+struct SelfRunningService {
+    runs: AtomicUsize,
+}
+
+impl Service for SelfRunningService {
+    type Handle = Arc<Self>;
+
+    async fn build(ctx: &AppContext) -> Result<Self::Handle, StdError> {
+        let service = Arc::new(Self {
+            runs: AtomicUsize::new(0),
+        });
+        ctx.add_daemon::<Self>(service.clone());
+        Ok(service)
+    }
+}
+
+impl Daemon for SelfRunningService {
+    async fn run(&self, _app: &App, _shutdown: CancellationToken) -> Result<(), StdError> {
+        self.runs.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_service_registers_daemon() {
+    let app = App::builder()
+        .add_service::<SelfRunningService>()
+        .build()
+        .await
+        .unwrap();
+    let service = app.get_component::<Arc<SelfRunningService>>().unwrap();
+
+    app.run_daemons(CancellationToken::new()).await.unwrap();
+    assert_eq!(service.runs.load(Ordering::SeqCst), 1);
+}
+
+type Events = Arc<Mutex<Vec<&'static str>>>;
+
+struct Migrations(Events);
+
+impl Daemon for Migrations {
+    async fn run(&self, _app: &App, _shutdown: CancellationToken) -> Result<(), StdError> {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        self.0.lock().unwrap().push("migrations");
+        Ok(())
+    }
+}
+
+struct Listener(Events);
+
+impl Daemon for Listener {
+    async fn run(&self, app: &App, shutdown: CancellationToken) -> Result<(), StdError> {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        self.0.lock().unwrap().push("listener");
+        app.notify_daemon_ready::<Self>();
+        shutdown.cancelled_owned().await;
+        Ok(())
+    }
+
+    fn wait_for() -> DaemonWaitFor {
+        DaemonWaitFor::new().daemon::<Migrations>()
+    }
+}
+
+struct Worker(Events);
+
+impl Daemon for Worker {
+    async fn run(&self, _app: &App, shutdown: CancellationToken) -> Result<(), StdError> {
+        self.0.lock().unwrap().push("worker");
+        shutdown.cancelled_owned().await;
+        Ok(())
+    }
+
+    fn wait_for() -> DaemonWaitFor {
+        DaemonWaitFor::new()
+            .daemon::<Migrations>()
+            .daemon::<Listener>()
+    }
+}
+
+#[tokio::test]
+async fn test_daemon_wait_for() {
+    let events = Events::default();
+    let mut builder = App::builder();
+    builder.add_daemon(Worker(events.clone()));
+    builder.add_daemon(Listener(events.clone()));
+    builder.add_daemon(Migrations(events.clone()));
+    let app = builder.build().await.unwrap();
+
+    let shutdown = CancellationToken::new();
+    let handle = tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move { app.run_daemons(shutdown).await }
+    });
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    // Finished migrations do not stop the other daemons.
+    assert!(!handle.is_finished());
+    assert_eq!(
+        *events.lock().unwrap(),
+        ["migrations", "listener", "worker"]
+    );
+    shutdown.cancel();
+    handle.await.unwrap().unwrap();
+}
+
+struct Ping;
+
+impl Daemon for Ping {
+    fn wait_for() -> DaemonWaitFor {
+        DaemonWaitFor::new().daemon::<Pong>()
+    }
+}
+
+struct Pong;
+
+impl Daemon for Pong {
+    fn wait_for() -> DaemonWaitFor {
+        DaemonWaitFor::new().daemon::<Ping>()
+    }
+}
+
+#[tokio::test]
+async fn test_daemon_wait_for_errors() {
+    let mut builder = App::builder();
+    builder.add_daemon(Worker(Events::default()));
+    let app = builder.build().await.unwrap();
+    let err = app.run_daemons(CancellationToken::new()).await.unwrap_err();
+    assert!(
+        err.to_string().ends_with("which is not registered"),
+        "{err}"
+    );
+
+    let mut builder = App::builder();
+    builder.add_daemon(Ping);
+    builder.add_daemon(Pong);
+    let app = builder.build().await.unwrap();
+    // The cycle is detected before any daemon is started.
+    let err = app.run_daemons(CancellationToken::new()).await.unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Daemon wait cycle: daemons::Ping -> daemons::Pong -> daemons::Ping"
+    );
+}
+
+#[tokio::test]
+async fn test_merge_app_daemons() {
+    let events = Events::default();
+    let mut builder = App::builder();
+    builder.add_daemon(Migrations(events.clone()));
+    let mut app = builder.build().await.unwrap();
+    let mut builder = App::builder();
+    builder.add_daemon(Worker(events.clone()));
+    builder.add_daemon(Listener(events.clone()));
+    let extension = builder.build().await.unwrap();
+
+    // Daemons of both applications end up in one registry, so the worker of
+    // the extension still waits for the migrations of the core application.
+    app.merge(extension).unwrap();
+    let shutdown = CancellationToken::new();
+    let handle = tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move { app.run_daemons(shutdown).await }
+    });
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert_eq!(
+        *events.lock().unwrap(),
+        ["migrations", "listener", "worker"]
+    );
+    shutdown.cancel();
+    handle.await.unwrap().unwrap();
+}
+
+#[derive(Service)]
+struct BackgroundWorker {
+    #[inject(AppShutdown)]
+    shutdown: CancellationToken,
+}
+
+#[tokio::test]
+async fn test_app_shutdown() {
+    let mut builder = App::builder();
+    builder.add_service::<BackgroundWorker>();
+    builder.add_fn_daemon(|_app, shutdown| async move {
+        shutdown.cancelled_owned().await;
+        Ok(())
+    });
+    let app = builder.build().await.unwrap().into_handle();
+    let worker = app.get_component::<Arc<BackgroundWorker>>().unwrap();
+    assert!(!worker.shutdown.is_cancelled());
+
+    // Cancelling the app-wide token stops the daemons.
+    let handle = tokio::spawn(app.clone().run_daemons(CancellationToken::new()));
+    worker.shutdown.cancel();
+    tokio::time::timeout(Duration::from_secs(5), handle)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+    // And the shutdown signal cancels the app-wide token.
+    let mut builder = App::builder();
+    builder.add_service::<BackgroundWorker>();
+    let app = builder.build().await.unwrap().into_handle();
+    let worker = app.get_component::<Arc<BackgroundWorker>>().unwrap();
+    let shutdown = CancellationToken::new();
+    let handle = tokio::spawn(app.run_daemons(shutdown.clone()));
+    shutdown.cancel();
+    handle.await.unwrap().unwrap();
+    tokio::time::timeout(Duration::from_secs(5), worker.shutdown.cancelled())
+        .await
+        .unwrap();
+}
+
+struct FailingDaemon;
+
+impl Daemon for FailingDaemon {
+    async fn run(&self, _app: &App, _shutdown: CancellationToken) -> Result<(), StdError> {
+        Err("daemon failed".into())
+    }
+}
+
+#[derive(Service)]
+struct ShutdownObserver {
+    #[inject(ShutdownReason)]
+    reason: ShutdownReason,
+}
+
+#[tokio::test]
+async fn test_shutdown_reason() {
+    let mut builder = App::builder();
+    builder.add_service::<ShutdownObserver>();
+    builder.add_daemon(FailingDaemon);
+    builder.add_fn_daemon(|_app, shutdown: CancellationToken| async move {
+        shutdown.cancelled_owned().await;
+        Ok(())
+    });
+    let app = builder.build().await.unwrap().into_handle();
+    let observer = app.get_component::<Arc<ShutdownObserver>>().unwrap();
+    assert_eq!(observer.reason.get(), None);
+
+    app.run_daemons(CancellationToken::new()).await.unwrap_err();
+    assert_eq!(
+        observer.reason.get(),
+        Some(&ShutdownCause::DaemonFailed {
+            daemon: std::any::type_name::<FailingDaemon>(),
+        })
+    );
+    assert!(!observer.reason.set(ShutdownCause::Signal));
+
+    // Cancelling the token passed in records no cause.
+    let mut builder = App::builder();
+    builder.add_service::<ShutdownObserver>();
+    builder.add_fn_daemon(|_app, shutdown: CancellationToken| async move {
+        shutdown.cancelled_owned().await;
+        Ok(())
+    });
+    let app = builder.build().await.unwrap().into_handle();
+    let observer = app.get_component::<Arc<ShutdownObserver>>().unwrap();
+    let shutdown = CancellationToken::new();
+    let handle = tokio::spawn(app.run_daemons(shutdown.clone()));
+    shutdown.cancel();
+    handle.await.unwrap().unwrap();
+    assert_eq!(observer.reason.get(), None);
+}
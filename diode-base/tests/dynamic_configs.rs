@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use diode::{AddServiceExt as _, App, AppContext, Service, StdError};
+use diode_base::{
+    AddDynamicConfigExt as _, CancellationToken, Config, DynamicConfig, DynamicConfigService,
+    DynamicConfigUpdater, RunDaemonsExt as _, testing::MockDynamicConfig,
+};
+use serde::{Deserialize, Deserializer};
+use serde_json::json;
+
+type UpdaterSlot = Arc<Mutex<Option<DynamicConfigUpdater>>>;
+
+/// Provider that hands its updater to the test.
+struct TestDynamicConfig(UpdaterSlot);
+
+impl Service for TestDynamicConfig {
+    type Handle = Arc<Self>;
+
+    async fn build(ctx: &AppContext) -> Result<Self::Handle, StdError> {
+        Ok(Arc::new(Self(ctx.get_component::<UpdaterSlot>().unwrap())))
+    }
+}
+
+impl DynamicConfigService for TestDynamicConfig {
+    async fn get_snapshot(&self) -> Result<BTreeMap<String, serde_json::Value>, StdError> {
+        Ok(BTreeMap::from([("limit".to_string(), json!(10))]))
+    }
+
+    async fn watch_changes(
+        &self,
+        updater: DynamicConfigUpdater,
+        shutdown: CancellationToken,
+    ) -> Result<(), StdError> {
+        *self.0.lock().unwrap() = Some(updater);
+        shutdown.cancelled().await;
+        Ok(())
+    }
+}
+
+static LIMIT_READS: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, PartialEq)]
+struct Limit(u32);
+
+impl<'de> Deserialize<'de> for Limit {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        LIMIT_READS.fetch_add(1, Ordering::SeqCst);
+        u32::deserialize(deserializer).map(Limit)
+    }
+}
+
+#[tokio::test]
+async fn test_dynamic_config_watch() {
+    let slot = UpdaterSlot::default();
+    let app = App::builder()
+        .add_dynamic_config::<TestDynamicConfig>()
+        .add_component(slot.clone())
+        .add_component(Config::new())
+        .build()
+        .await
+        .unwrap()
+        .into_handle();
+    let dynamic_config = app.get_component::<Arc<DynamicConfig>>().unwrap();
+    let limit = dynamic_config.watch::<Limit>("limit");
+    assert_eq!(limit.get().as_deref(), Some(&Limit(10)));
+
+    let shutdown = CancellationToken::new();
+    let handle = tokio::spawn(app.clone().run_daemons(shutdown.clone()));
+    let updater = loop {
+        if let Some(updater) = slot.lock().unwrap().take() {
+            break updater;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+
+    updater.update_key("limit".to_string(), json!(20));
+    assert_eq!(limit.get().as_deref(), Some(&Limit(20)));
+    updater.remove_key("limit");
+    assert_eq!(limit.get(), None);
+
+    // A dropped handle no longer receives updates.
+    let reads = LIMIT_READS.load(Ordering::SeqCst);
+    drop(limit);
+    updater.update_key("limit".to_string(), json!(30));
+    assert_eq!(LIMIT_READS.load(Ordering::SeqCst), reads);
+    assert_eq!(dynamic_config.get::<u32>("limit"), Some(30));
+
+    shutdown.cancel();
+    handle.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_mock_dynamic_config() {
+    let mock = Arc::new(MockDynamicConfig::new().with("limit", json!(10)));
+    let app = App::builder()
+        .add_component(Config::new())
+        .add_service_instance::<MockDynamicConfig>(mock.clone())
+        .add_dynamic_config::<MockDynamicConfig>()
+        .build()
+        .await
+        .unwrap()
+        .into_handle();
+    let dynamic_config = app.get_component::<Arc<DynamicConfig>>().unwrap();
+    let limit = dynamic_config.watch::<u32>("limit");
+    assert_eq!(limit.get().as_deref(), Some(&10));
+
+    // Pushed before the daemon runs, picked up when it starts watching.
+    mock.push("name", json!("diode"));
+    let shutdown = CancellationToken::new();
+    let handle = tokio::spawn(app.clone().run_daemons(shutdown.clone()));
+    mock.watching().await;
+    assert_eq!(
+        dynamic_config.get::<String>("name").as_deref(),
+        Some("diode")
+    );
+
+    mock.push("limit", json!(20));
+    assert_eq!(limit.get().as_deref(), Some(&20));
+    mock.remove("limit");
+    assert_eq!(limit.get(), None);
+
+    shutdown.cancel();
+    handle.await.unwrap().unwrap();
+}
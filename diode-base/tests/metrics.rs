@@ -0,0 +1,30 @@
+use std::net::TcpListener;
+
+use diode::App;
+use diode_base::{Config, Metrics};
+use serde_json::json;
+
+#[tokio::test]
+async fn test_metrics_verify_on_start() {
+    // Nothing listens on a port released right after binding.
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    let mut builder = App::builder();
+    builder.add_component(Config::new().with(
+        "metrics",
+        json!({
+            "otlp_exporter": {
+                "endpoint": format!("http://127.0.0.1:{port}"),
+                "timeout": "1s",
+                "verify_on_start": true,
+            },
+        }),
+    ));
+    let err = Metrics::build(&builder).err().unwrap();
+    assert!(err.to_string().contains("is unreachable"), "{err}");
+    assert!(!builder.has_component::<Metrics>());
+}
@@ -0,0 +1,41 @@
+//! Sends SIGTERM to the test process itself, so it lives in its own test
+//! binary: any other test running a server would be shut down as well.
+#![cfg(unix)]
+
+use clap::ArgMatches;
+use diode::App;
+use diode_base::{AddDaemonExt as _, CancellationToken, Command, ServerCommand};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_server_command_sigterm() {
+    // Cancelled by the daemon once the server (and its signal handler) is up.
+    let started = CancellationToken::new();
+    let mut app_builder = App::builder();
+    app_builder.add_component(started.clone());
+    app_builder.add_fn_daemon(|app, shutdown| {
+        let started = app.get_component::<CancellationToken>().unwrap();
+        async move {
+            started.cancel();
+            shutdown.cancelled_owned().await;
+            Ok(())
+        }
+    });
+    let app = Arc::new(app_builder.build().await.unwrap());
+
+    let server = tokio::spawn(ServerCommand::main(app, ArgMatches::default()));
+    started.cancelled().await;
+    let status = std::process::Command::new("kill")
+        .args(["-TERM", &std::process::id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let exit_code = tokio::time::timeout(Duration::from_secs(5), server)
+        .await
+        .expect("Server did not stop on SIGTERM")
+        .unwrap();
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+}
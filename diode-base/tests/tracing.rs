@@ -0,0 +1,26 @@
+use diode::App;
+use diode_base::{Config, Tracing};
+use serde_json::json;
+
+#[test]
+fn test_tracing_rust_log() {
+    let mut builder = App::builder();
+    builder.add_component(Config::new().with("tracing", json!({"level": "info"})));
+
+    // SAFETY: this is the only test of this binary.
+    unsafe { std::env::set_var("RUST_LOG", "diode=nope") };
+    let err = Tracing::build(&builder).unwrap_err();
+    assert!(
+        err.to_string()
+            .starts_with("Invalid RUST_LOG directive diode=nope"),
+        "{err}"
+    );
+
+    // SAFETY: as above.
+    unsafe { std::env::set_var("RUST_LOG", "warn,diode_base=trace") };
+    Tracing::build(&builder).unwrap();
+    // `RUST_LOG` overrides the configured level.
+    assert!(!tracing::enabled!(tracing::Level::INFO));
+    assert!(tracing::enabled!(tracing::Level::WARN));
+    assert!(tracing::enabled!(target: "diode_base::config", tracing::Level::TRACE));
+}
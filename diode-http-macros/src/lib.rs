@@ -3,7 +3,12 @@ use proc_macro2::Span;
 use quote::quote;
 use syn::punctuated::Punctuated;
 use syn::spanned::Spanned;
-use syn::{Error, Expr, ExprPath, Ident, ImplItem, ItemImpl, Lit, Meta, Token};
+use syn::{
+    Error, Expr, ExprPath, FnArg, Ident, ImplItem, ItemImpl, Lit, LitStr, Meta, Signature, Token,
+};
+
+const QUERY_ATTR: &str = "query";
+const JSON_ATTR: &str = "json";
 
 #[proc_macro_attribute]
 pub fn router(attr: TokenStream, item: TokenStream) -> TokenStream {
@@ -72,21 +77,32 @@ fn parse_router_attribute(attr: TokenStream) -> Result<RouterAttribute, Error> {
 }
 
 struct RouteAttribute {
+    method: Ident,
     http_method: proc_macro2::TokenStream,
     path: String,
     middleware: Vec<ExprPath>,
+    status: Option<u16>,
+    scopes: Vec<LitStr>,
+    sse: bool,
 }
 
 fn parse_route_attribute(attr: &syn::Attribute) -> Result<RouteAttribute, Error> {
     let meta_items: Punctuated<Meta, Token![,]> =
         attr.parse_args_with(Punctuated::parse_terminated)?;
 
+    let mut method = None;
     let mut http_method = None;
     let mut path = None;
     let mut middleware = Vec::new();
+    let mut status = None;
+    let mut scopes = Vec::new();
+    let mut sse = false;
 
     for meta in meta_items {
         match meta {
+            Meta::Path(path_meta) if path_meta.is_ident("sse") => {
+                sse = true;
+            }
             Meta::Path(path_meta) => {
                 let ident = path_meta
                     .get_ident()
@@ -110,6 +126,7 @@ fn parse_route_attribute(attr: &syn::Attribute) -> Result<RouteAttribute, Error>
                         ));
                     }
                 });
+                method = Some(ident.clone());
             }
             Meta::NameValue(nv) if nv.path.is_ident("path") => {
                 if let Expr::Lit(expr_lit) = &nv.value
@@ -142,6 +159,37 @@ fn parse_route_attribute(attr: &syn::Attribute) -> Result<RouteAttribute, Error>
                     ));
                 }
             }
+            Meta::NameValue(nv) if nv.path.is_ident("scopes") => {
+                let Expr::Array(expr_array) = &nv.value else {
+                    return Err(Error::new_spanned(
+                        &nv.value,
+                        "`scopes` attribute requires an array of string literals",
+                    ));
+                };
+                for expr in &expr_array.elems {
+                    if let Expr::Lit(expr_lit) = expr
+                        && let Lit::Str(lit_str) = &expr_lit.lit
+                    {
+                        scopes.push(lit_str.clone());
+                    } else {
+                        return Err(Error::new_spanned(expr, "Scope must be a string literal"));
+                    }
+                }
+            }
+            Meta::NameValue(nv) if nv.path.is_ident("status") => {
+                if let Expr::Lit(expr_lit) = &nv.value
+                    && let Lit::Int(lit_int) = &expr_lit.lit
+                    && let Ok(code) = lit_int.base10_parse::<u16>()
+                    && (100..1000).contains(&code)
+                {
+                    status = Some(code);
+                    continue;
+                }
+                return Err(Error::new_spanned(
+                    &nv.value,
+                    "`status` attribute requires an HTTP status code between 100 and 999",
+                ));
+            }
             _ => {
                 return Err(Error::new_spanned(
                     meta,
@@ -151,19 +199,94 @@ fn parse_route_attribute(attr: &syn::Attribute) -> Result<RouteAttribute, Error>
         }
     }
 
-    let http_method = http_method
-        .ok_or_else(|| Error::new_spanned(attr, "Missing HTTP method in #[route] attribute"))?;
+    // Server-sent events are usually subscribed to with `GET`.
+    if sse && method.is_none() {
+        method = Some(Ident::new("get", Span::call_site()));
+        http_method = Some(quote! { ::diode_http::routing::get });
+    }
+
+    let (Some(method), Some(http_method)) = (method, http_method) else {
+        return Err(Error::new_spanned(
+            attr,
+            "Missing HTTP method in #[route] attribute",
+        ));
+    };
 
     let path =
         path.ok_or_else(|| Error::new_spanned(attr, "Missing path in #[route] attribute"))?;
 
     Ok(RouteAttribute {
+        method,
         http_method,
         path,
         middleware,
+        status,
+        scopes,
+        sse,
     })
 }
 
+enum RouteParam {
+    Plain,
+    Query(Box<syn::Type>),
+    Json(Box<syn::Type>),
+}
+
+/// Parses the handler parameters (excluding `self`) and their `#[query]` /
+/// `#[json]` attributes.
+fn parse_route_params(sig: &Signature) -> Result<Vec<RouteParam>, Error> {
+    let inputs: Vec<_> = sig
+        .inputs
+        .iter()
+        .filter_map(|input| match input {
+            FnArg::Typed(pat_type) => Some(pat_type),
+            FnArg::Receiver(_) => None,
+        })
+        .collect();
+
+    let mut params = Vec::with_capacity(inputs.len());
+    let mut json_attr = None;
+    for (i, pat_type) in inputs.iter().enumerate() {
+        let mut param = RouteParam::Plain;
+        for attr in &pat_type.attrs {
+            let is_query = attr.path().is_ident(QUERY_ATTR);
+            let is_json = attr.path().is_ident(JSON_ATTR);
+            if !is_query && !is_json {
+                continue;
+            }
+            attr.meta.require_path_only()?;
+            if !matches!(param, RouteParam::Plain) {
+                return Err(Error::new_spanned(
+                    attr,
+                    "Parameter can have only one #[query] or #[json] attribute",
+                ));
+            }
+            if is_query {
+                param = RouteParam::Query(pat_type.ty.clone());
+                continue;
+            }
+            if json_attr.is_some() {
+                return Err(Error::new_spanned(
+                    attr,
+                    "Only one #[json] parameter allowed",
+                ));
+            }
+            json_attr = Some((i, attr));
+            param = RouteParam::Json(pat_type.ty.clone());
+        }
+        params.push(param);
+    }
+    if let Some((i, attr)) = json_attr
+        && i + 1 != inputs.len()
+    {
+        return Err(Error::new_spanned(
+            attr,
+            "#[json] parameter must be the last one, as it consumes the request body",
+        ));
+    }
+    Ok(params)
+}
+
 fn handle_router_impl(input: ItemImpl, router_attr: RouterAttribute) -> TokenStream {
     if input.trait_.is_some() {
         return Error::new(input.span(), "Trait impls are not supported")
@@ -176,16 +299,30 @@ fn handle_router_impl(input: ItemImpl, router_attr: RouterAttribute) -> TokenStr
     let mut errors = Vec::new();
 
     let router_middleware = router_attr.middleware;
+    // `(method, path, scopes)` of every route declaring `scopes`.
+    let mut route_scopes = Vec::new();
+    // Every (method, path) pair seen so far, with the handler that defines it.
+    let mut defined_routes: Vec<(String, String, Ident)> = Vec::new();
 
-    // Reverse so that the first middleware in the list ends up outermost: each
-    // `.layer()` wraps the previous one, so the last applied layer runs first.
-    let router_middleware: Vec<_> = router_middleware.into_iter().rev().collect();
-
-    // Create cleaned impl with route attributes removed
+    // Create cleaned impl with route and route parameter attributes removed
     let mut cleaned_input = input.clone();
     for item in &mut cleaned_input.items {
         if let ImplItem::Fn(fn_item) = item {
+            if !fn_item
+                .attrs
+                .iter()
+                .any(|attr| attr.path().is_ident("route"))
+            {
+                continue;
+            }
             fn_item.attrs.retain(|attr| !attr.path().is_ident("route"));
+            for input in &mut fn_item.sig.inputs {
+                if let FnArg::Typed(pat_type) = input {
+                    pat_type.attrs.retain(|attr| {
+                        !attr.path().is_ident(QUERY_ATTR) && !attr.path().is_ident(JSON_ATTR)
+                    });
+                }
+            }
         }
     }
 
@@ -194,6 +331,21 @@ fn handle_router_impl(input: ItemImpl, router_attr: RouterAttribute) -> TokenStr
             continue;
         };
 
+        if !fn_item
+            .attrs
+            .iter()
+            .any(|attr| attr.path().is_ident("route"))
+        {
+            continue;
+        }
+        let params = match parse_route_params(&fn_item.sig) {
+            Ok(v) => v,
+            Err(e) => {
+                errors.push(e);
+                continue;
+            }
+        };
+
         for attr in &fn_item.attrs {
             if !attr.path().is_ident("route") {
                 continue;
@@ -201,37 +353,78 @@ fn handle_router_impl(input: ItemImpl, router_attr: RouterAttribute) -> TokenStr
 
             match parse_route_attribute(attr) {
                 Ok(RouteAttribute {
+                    method,
                     http_method,
                     path,
                     middleware,
+                    status,
+                    scopes,
+                    sse,
                 }) => {
-                    // Reverse so the first middleware in the list ends up outermost
-                    // (see the router-level note above).
-                    let middleware: Vec<_> = middleware.into_iter().rev().collect();
                     let ident = &fn_item.sig.ident;
-                    let arg_count = fn_item.sig.inputs.len().saturating_sub(1); // Exclude self
-                    let args: Vec<_> = (0..arg_count)
+                    let method = method.to_string();
+                    // `any` handles every method, so it overlaps with all of them.
+                    if let Some((other_method, _, other)) =
+                        defined_routes.iter().find(|(other_method, other_path, _)| {
+                            *other_path == path
+                                && (*other_method == method
+                                    || *other_method == "any"
+                                    || method == "any")
+                        })
+                    {
+                        errors.push(Error::new_spanned(
+                            attr,
+                            format!(
+                                "Route `{} {path}` conflicts with `{} {path}` defined by `{other}`",
+                                method.to_uppercase(),
+                                other_method.to_uppercase(),
+                            ),
+                        ));
+                        continue;
+                    }
+                    if !scopes.is_empty() {
+                        route_scopes.push(quote! { (#method, #path, &[#(#scopes),*]) });
+                    }
+                    defined_routes.push((method, path.clone(), ident.clone()));
+                    let args: Vec<_> = (0..params.len())
                         .map(|i| Ident::new(&format!("arg{i}"), Span::call_site()))
                         .collect();
+                    let extractors = args.iter().zip(&params).map(|(arg, param)| match param {
+                        RouteParam::Plain => quote! { #arg },
+                        RouteParam::Query(ty) => quote! {
+                            ::diode_http::axum::extract::Query(#arg): ::diode_http::axum::extract::Query<#ty>
+                        },
+                        RouteParam::Json(ty) => quote! {
+                            ::diode_http::axum::Json(#arg): ::diode_http::axum::Json<#ty>
+                        },
+                    });
+
+                    let call = quote! { Self::#ident(&this, #(#args,)*).await };
+                    let call = if sse {
+                        quote! { ::diode_http::sse_response(#call) }
+                    } else {
+                        call
+                    };
+                    let call = match status {
+                        Some(status) => quote! {
+                            ::diode_http::with_default_status(#call, #status)
+                        },
+                        None => call,
+                    };
 
                     routes.push(quote! {
                         let mut route = #http_method({
                             let this = self.clone();
-                            move |#(#args,)*| {
-                                async move { Self::#ident(&this, #(#args,)*).await }
+                            move |#(#extractors,)*| {
+                                async move { #call }
                             }
                         });
+                        let mut middleware =
+                            ::diode_http::MiddlewareStack::<::diode_http::routing::MethodRouter>::new();
                         #(
-                            let middleware = app
-                                .get_component::<::std::sync::Arc<#middleware>>()
-                                .unwrap_or_else(|| {
-                                    panic!(
-                                        "Middleware {} is not registered",
-                                        ::std::any::type_name::<#middleware>()
-                                    )
-                                });
-                            route = route.layer(::diode_http::MiddlewareLayerImpl(middleware));
+                            middleware.push::<#middleware, _>(app, |route, layer| route.layer(layer))?;
                         )*
+                        route = middleware.layer(route)?;
                         router = router.route(#path, route);
                     });
                 }
@@ -255,21 +448,20 @@ fn handle_router_impl(input: ItemImpl, router_attr: RouterAttribute) -> TokenStr
         #cleaned_input
 
         impl ::diode_http::RouterBuilder for #self_ty {
-            fn build_router(self: ::std::sync::Arc<Self>, app: &::diode::App) -> ::diode_http::Router {
+            fn build_router(
+                self: ::std::sync::Arc<Self>,
+                app: &::diode::App,
+            ) -> ::std::result::Result<::diode_http::Router, ::diode::StdError> {
                 let mut router = ::diode_http::Router::new();
                 #(#routes)*
+                let mut middleware = ::diode_http::MiddlewareStack::<::diode_http::Router>::new();
                 #(
-                    let middleware = app
-                        .get_component::<::std::sync::Arc<#router_middleware>>()
-                        .unwrap_or_else(|| {
-                            panic!(
-                                "Middleware {} is not registered",
-                                ::std::any::type_name::<#router_middleware>()
-                            )
-                        });
-                    router = router.layer(::diode_http::MiddlewareLayerImpl(middleware));
+                    middleware.push::<#router_middleware, _>(app, |router, layer| router.layer(layer))?;
                 )*
-                router
+                ::std::result::Result::Ok(::diode_http::with_route_scopes(
+                    middleware.layer(router)?,
+                    &[#(#route_scopes),*],
+                ))
             }
         }
     }
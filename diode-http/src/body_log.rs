@@ -0,0 +1,142 @@
+use std::sync::Arc;
+
+use axum::body::{Body, Bytes};
+use axum::http::{HeaderMap, StatusCode, header};
+use diode::{AppContext, Service, StdError};
+use diode_base::{Config, ConfigSection as _, config_section};
+use serde::{Deserialize, Serialize};
+
+use crate::{Middleware, Next, Request, Response};
+
+/// Configuration for [`BodyLogMiddleware`], read from the optional
+/// `http_body_log` config section.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[config_section("http_body_log")]
+pub struct BodyLogConfig {
+    /// Maximum number of body bytes written to the log, 4096 by default.
+    ///
+    /// Longer bodies are truncated in the log only; they are still buffered
+    /// and forwarded in full.
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: usize,
+    /// Content type prefixes of the bodies to log. Bodies of other types, or
+    /// without a `Content-Type`, pass through without being buffered.
+    ///
+    /// Defaults to JSON, form and plain text bodies.
+    #[serde(default = "default_content_types")]
+    pub content_types: Vec<String>,
+}
+
+fn default_max_bytes() -> usize {
+    4096
+}
+
+fn default_content_types() -> Vec<String> {
+    vec![
+        "application/json".into(),
+        "application/x-www-form-urlencoded".into(),
+        "text/plain".into(),
+    ]
+}
+
+impl Default for BodyLogConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes: default_max_bytes(),
+            content_types: default_content_types(),
+        }
+    }
+}
+
+/// Middleware that logs request and response bodies at `trace` level.
+///
+/// Meant for debugging integrations, not for production: a logged body is
+/// buffered in memory in full before it is forwarded, which adds latency,
+/// breaks streaming and lets large bodies use a lot of memory. Bodies often
+/// carry credentials and personal data, which end up in the logs. The
+/// middleware does nothing unless `trace` level is enabled for this module.
+///
+/// Register it with
+/// [`add_middleware(BodyLogMiddleware::new(config))`](crate::AddMiddlewareExt::add_middleware),
+/// or with
+/// [`add_middleware_service`](crate::AddMiddlewareServiceExt::add_middleware_service)
+/// to read the `http_body_log` config section.
+#[derive(Clone, Debug, Default)]
+pub struct BodyLogMiddleware {
+    config: BodyLogConfig,
+}
+
+impl BodyLogMiddleware {
+    pub fn new(config: BodyLogConfig) -> Self {
+        Self { config }
+    }
+
+    fn should_log(&self, headers: &HeaderMap) -> bool {
+        let Some(content_type) = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+        else {
+            return false;
+        };
+        self.config
+            .content_types
+            .iter()
+            .any(|v| content_type.starts_with(v.as_str()))
+    }
+
+    fn log(&self, message: &str, body: &Bytes) {
+        let truncated = body.len() > self.config.max_bytes;
+        let logged = &body[..body.len().min(self.config.max_bytes)];
+        tracing::trace!(
+            size = body.len(),
+            truncated,
+            body = %String::from_utf8_lossy(logged),
+            "{message}",
+        );
+    }
+}
+
+impl Service for BodyLogMiddleware {
+    type Handle = Arc<Self>;
+
+    async fn build(ctx: &AppContext) -> Result<Self::Handle, StdError> {
+        let config = match ctx.get_component_ref::<Config>() {
+            Some(config) => config
+                .get::<Option<BodyLogConfig>>(BodyLogConfig::key())?
+                .unwrap_or_default(),
+            None => BodyLogConfig::default(),
+        };
+        Ok(Arc::new(Self::new(config)))
+    }
+}
+
+impl Middleware for BodyLogMiddleware {
+    type Error = StatusCode;
+
+    async fn call(&self, request: Request, next: impl Next) -> Result<Response, StatusCode> {
+        let request = if self.should_log(request.headers()) {
+            let (parts, body) = request.into_parts();
+            let body = axum::body::to_bytes(body, usize::MAX)
+                .await
+                .map_err(|_| StatusCode::BAD_REQUEST)?;
+            self.log("HTTP request body", &body);
+            Request::from_parts(parts, Body::from(body))
+        } else {
+            request
+        };
+        let response = next.call(request).await;
+        if !self.should_log(response.headers()) {
+            return Ok(response);
+        }
+        let (parts, body) = response.into_parts();
+        let body = axum::body::to_bytes(body, usize::MAX)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        self.log("HTTP response body", &body);
+        Ok(Response::from_parts(parts, Body::from(body)))
+    }
+
+    fn should_apply(&self, _request: &Request) -> bool {
+        tracing::enabled!(tracing::Level::TRACE)
+    }
+}
@@ -6,15 +6,22 @@ use std::sync::Arc;
 
 use axum::Router;
 use diode::{
-    AddServiceExt as _, App, AppBuilder, AppContext, Dependencies, Plugin, Service,
+    AddServiceExt as _, App, AppBuilder, AppContext, Dependencies, Plugin, PluginError, Service,
     ServiceDependencyExt as _, StdError,
 };
-use diode_base::{AddDaemonExt as _, CancellationToken, Config, Daemon, config_section, defer};
+use diode_base::{
+    AddDaemonExt as _, CancellationToken, Config, Daemon, DaemonReadyExt as _, config_section,
+    defer,
+};
 use serde::{Deserialize, Serialize};
-use tokio::net::TcpListener;
 
-use crate::tracing::TracingLayer;
-use crate::{HealthCheckRegistry, HealthClient, RouterBuilder};
+use crate::router::default_catch_panic;
+use crate::server::{bind_tcp, serve};
+use crate::tracing::{TracingLayer, catch_panic_layer};
+use crate::{
+    HealthCheckRegistry, HealthClient, HealthConfig, HttpTuning, RouterBuilder, ServerListener,
+    layer_control_middleware,
+};
 
 #[derive(Default)]
 struct ControlRouterRegistry {
@@ -34,39 +41,53 @@ impl ControlRouterRegistry {
         self.types.contains(&TypeId::of::<T>())
     }
 
-    fn build_router(&self, app: &App) -> Router {
-        self.routers.iter().fold(Router::new(), |acc, v| {
-            acc.merge(v.clone().build_router(app))
-        })
+    fn build_router(&self, app: &App) -> Result<Router, StdError> {
+        let mut router = Router::new();
+        for v in self.routers.iter() {
+            router = router.merge(v.clone().build_router(app)?);
+        }
+        layer_control_middleware(router, app)
     }
 }
 
-struct ControlServerDaemon {
+/// Daemon running the control HTTP server, added by [`ControlServerPlugin`].
+///
+/// It becomes ready once its listener is bound, so other daemons can wait for
+/// it with `DaemonWaitFor::new().daemon::<ControlServerDaemon>()`.
+pub struct ControlServerDaemon {
     addr: SocketAddr,
+    catch_panic: bool,
+    http: HttpTuning,
 }
 
 impl Daemon for ControlServerDaemon {
     async fn run(&self, app: &App, shutdown: CancellationToken) -> Result<(), StdError> {
         let span = tracing::info_span!("control_server", addr = ?self.addr);
-        let router = app
-            .get_component_ref::<ControlRouterRegistry>()
-            .unwrap()
-            .build_router(app)
-            .layer(TracingLayer);
+        let router = build_control_router(app, self.catch_panic)?;
         tracing::info!(parent: &span, "Control server starting");
         defer! {
             tracing::info!(parent: &span, "Control server stopped")
         };
-        let listener = TcpListener::bind(self.addr).await.map_err(Box::new)?;
+        let listener = bind_tcp(self.addr, ServerListener::<Self>::take(app), &self.http).await?;
         tracing::info!(parent: &span, "Control server started");
-        axum::serve(listener, router)
-            .with_graceful_shutdown(shutdown.cancelled_owned())
-            .await
-            .map_err(Box::new)?;
-        Ok(())
+        app.notify_daemon_ready::<Self>();
+        serve(listener, router, None, &self.http, shutdown).await
     }
 }
 
+/// Merges the control routers and wraps them in the control middleware and
+/// the server-wide layers.
+fn build_control_router(app: &App, catch_panic: bool) -> Result<Router, StdError> {
+    let mut router = app
+        .get_component_ref::<ControlRouterRegistry>()
+        .ok_or("ControlRouterRegistry component is missing")?
+        .build_router(app)?;
+    if catch_panic {
+        router = router.layer(catch_panic_layer());
+    }
+    Ok(router.layer(TracingLayer::default()))
+}
+
 /// Configuration for the control HTTP server, read from the `control_server`
 /// config section.
 #[derive(Serialize, Deserialize)]
@@ -74,6 +95,30 @@ impl Daemon for ControlServerDaemon {
 pub struct ControlServerConfig {
     /// Socket address the control server binds and listens on.
     pub addr: SocketAddr,
+    /// Answers `500 Internal Server Error` when a handler or middleware
+    /// panics, instead of dropping the connection, and logs the panic message
+    /// on the request span.
+    ///
+    /// Enabled by default.
+    #[serde(default = "default_catch_panic")]
+    pub catch_panic: bool,
+    /// TCP and HTTP connection tuning, see [`HttpTuning`].
+    #[serde(default)]
+    pub http: HttpTuning,
+}
+
+impl ControlServerConfig {
+    /// Config listening on `addr`, with the defaults of every other setting.
+    ///
+    /// Like [`HttpServerConfig::new`](crate::HttpServerConfig::new), prefer it
+    /// to a struct literal.
+    pub fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            catch_panic: default_catch_panic(),
+            http: HttpTuning::default(),
+        }
+    }
 }
 
 /// Plugin that runs the control HTTP server.
@@ -82,25 +127,37 @@ pub struct ControlServerConfig {
 /// operational endpoints (health, readiness, and so on). It serves the routers
 /// registered through [`AddControlRouterExt`] / [`AddControlRouterServiceExt`],
 /// hosts the health-check registry used by [`HealthRouter`](crate::HealthRouter),
-/// and exposes a [`HealthClient`] component pointed at its own `/health`
-/// endpoint. It binds the address from [`ControlServerConfig`] (config section
+/// and exposes a [`HealthClient`] component pointed at its own health endpoint
+/// (`/health` unless changed in [`HealthConfig`](crate::HealthConfig)). It
+/// binds the address from [`ControlServerConfig`] (config section
 /// `control_server`).
+#[derive(Default)]
 pub struct ControlServerPlugin;
 
 impl Plugin for ControlServerPlugin {
     async fn build(&self, ctx: &AppContext) -> Result<(), StdError> {
-        if !ctx.has_component::<ControlRouterRegistry>() {
-            ctx.add_component(ControlRouterRegistry::default());
-        }
-        if !ctx.has_component::<HealthCheckRegistry>() {
-            ctx.add_component(HealthCheckRegistry::default());
-        }
+        ctx.get_or_insert_component::<ControlRouterRegistry>();
+        ctx.get_or_insert_component::<HealthCheckRegistry>();
         let config = ctx
             .get_component_ref::<Config>()
             .ok_or_else(|| "Config component is missing".to_string())?
-            .get::<ControlServerConfig>("control_server")?;
-        ctx.add_component(HealthClient::new(format!("http://{}/health", config.addr)));
-        ctx.add_daemon(ControlServerDaemon { addr: config.addr });
+            .get::<ControlServerConfig>("control_server")
+            .map_err(PluginError::misconfiguration)?;
+        let health_path = HealthConfig::from_context(ctx)
+            .map_err(PluginError::misconfiguration)?
+            .path;
+        ctx.add_component(HealthClient::new(format!(
+            "http://{}{health_path}",
+            config.addr
+        )));
+        // Fail the app build, not the daemon, if a router cannot be built.
+        let catch_panic = config.catch_panic;
+        ctx.add_ready_hook(move |app| build_control_router(app, catch_panic).map(|_| ()));
+        ctx.add_daemon(ControlServerDaemon {
+            addr: config.addr,
+            catch_panic: config.catch_panic,
+            http: config.http,
+        });
         Ok(())
     }
 }
@@ -112,9 +169,11 @@ where
     T: Service<Handle = Arc<T>> + RouterBuilder + 'static,
 {
     async fn build(&self, ctx: &AppContext) -> Result<(), StdError> {
-        let component = ctx.get_component::<T::Handle>().unwrap();
+        let component = ctx
+            .get_component::<T::Handle>()
+            .ok_or_else(|| format!("Missing component: {}", type_name::<T::Handle>()))?;
         ctx.get_component_mut::<ControlRouterRegistry>()
-            .unwrap()
+            .ok_or("ControlRouterRegistry component is missing")?
             .add_router(component);
         Ok(())
     }
@@ -157,11 +216,7 @@ impl AddControlRouterExt for AppContext {
     where
         T: RouterBuilder + 'static,
     {
-        if !self.has_component::<ControlRouterRegistry>() {
-            self.add_component(ControlRouterRegistry::default());
-        }
-        self.get_component_mut::<ControlRouterRegistry>()
-            .unwrap()
+        self.get_or_insert_component::<ControlRouterRegistry>()
             .add_router(router.into());
     }
 
@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::{Json, Router, routing};
+use diode::{App, AppContext, Service, StdError};
+use diode_base::{DynamicConfig, DynamicConfigSource, DynamicConfigUpdater};
+use serde::Serialize;
+
+use crate::{Middleware, MiddlewareStack, RouterBuilder};
+
+/// Router exposing `GET /debug/dynamic-config`, the effective runtime
+/// [`DynamicConfig`].
+///
+/// Responds with a JSON [`DynamicConfigDebug`]: the merged values and, for each
+/// key, whether it comes from the provider cache or the fallback file. Register
+/// it with
+/// [`add_control_router_service`](crate::AddControlRouterServiceExt::add_control_router_service)
+/// to keep it off the public server.
+///
+/// # Errors
+///
+/// Building the router fails if no [`DynamicConfig`] component is registered
+/// (see [`AddDynamicConfigExt`](diode_base::AddDynamicConfigExt)).
+#[derive(Service)]
+pub struct DynamicConfigRouter;
+
+impl RouterBuilder for DynamicConfigRouter {
+    fn build_router(self: Arc<Self>, app: &App) -> Result<Router, StdError> {
+        let dynamic_config = app
+            .get_component::<Arc<DynamicConfig>>()
+            .ok_or("DynamicConfig component is missing")?;
+        Ok(Router::new().route(
+            "/debug/dynamic-config",
+            routing::get(|| async move { Json(DynamicConfigDebug::new(&dynamic_config)) }),
+        ))
+    }
+}
+
+/// Body returned by [`DynamicConfigRouter`].
+#[derive(Clone, Debug, Serialize)]
+pub struct DynamicConfigDebug {
+    /// Effective values, as returned by [`DynamicConfig::snapshot`].
+    pub values: BTreeMap<String, serde_json::Value>,
+    /// Source of every key in `values`, as returned by [`DynamicConfig::sources`].
+    pub sources: BTreeMap<String, DynamicConfigSource>,
+}
+
+impl DynamicConfigDebug {
+    fn new(dynamic_config: &DynamicConfig) -> Self {
+        Self {
+            values: dynamic_config.snapshot(),
+            sources: dynamic_config.sources(),
+        }
+    }
+}
+
+/// Router exposing `PUT /admin/config/{key}` and `DELETE /admin/config/{key}`,
+/// which set a [`DynamicConfig`] key to the JSON request body or remove it.
+///
+/// Both routes are wrapped in the auth middleware `A`, which must reject
+/// unauthorized requests; register it with
+/// [`add_middleware`](crate::AddMiddlewareExt::add_middleware) or
+/// [`add_middleware_service`](crate::AddMiddlewareServiceExt::add_middleware_service),
+/// and the router with
+/// [`add_control_router_service`](crate::AddControlRouterServiceExt::add_control_router_service).
+/// Successful changes respond with `204 No Content`.
+///
+/// Changes are applied in memory through the [`DynamicConfigUpdater`]: they are
+/// persisted with the provider cache when `cache_path` is set, and the next
+/// provider update of the same key overwrites them. Removing a key falls back
+/// to its value in the fallback file, if any.
+///
+/// # Errors
+///
+/// Building the router fails if no [`DynamicConfigUpdater`] component is
+/// registered (see [`AddDynamicConfigExt`](diode_base::AddDynamicConfigExt)) or
+/// if `A` is not registered.
+pub struct DynamicConfigAdminRouter<A>(PhantomData<fn() -> A>);
+
+impl<A> Service for DynamicConfigAdminRouter<A>
+where
+    A: Middleware + 'static,
+{
+    type Handle = Arc<Self>;
+
+    async fn build(_ctx: &AppContext) -> Result<Self::Handle, StdError> {
+        Ok(Arc::new(Self(PhantomData)))
+    }
+}
+
+impl<A> RouterBuilder for DynamicConfigAdminRouter<A>
+where
+    A: Middleware + 'static,
+{
+    fn build_router(self: Arc<Self>, app: &App) -> Result<Router, StdError> {
+        let updater = app
+            .get_component::<DynamicConfigUpdater>()
+            .ok_or("DynamicConfigUpdater component is missing")?;
+        let remover = updater.clone();
+        let router = Router::new().route(
+            "/admin/config/{key}",
+            routing::put(
+                |Path(key): Path<String>, Json(value): Json<serde_json::Value>| async move {
+                    tracing::info!(key, "Updating dynamic config key");
+                    updater.update_key(key, value);
+                    StatusCode::NO_CONTENT
+                },
+            )
+            .delete(|Path(key): Path<String>| async move {
+                tracing::info!(key, "Removing dynamic config key");
+                remover.remove_key(&key);
+                StatusCode::NO_CONTENT
+            }),
+        );
+        let mut middleware = MiddlewareStack::new();
+        middleware.push::<A, _>(app, |router: Router, layer| router.layer(layer))?;
+        middleware.layer(router)
+    }
+}
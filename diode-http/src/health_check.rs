@@ -1,9 +1,11 @@
 use async_trait::async_trait;
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
 use axum::{Router, routing};
 use diode::{
     AddServiceExt as _, App, AppBuilder, AppContext, Dependencies, Plugin, Service,
     ServiceDependencyExt as _, StdError,
 };
+use diode_base::{Config, ConfigSection as _, config_section};
 use serde::{Deserialize, Serialize};
 use std::{
     any::{TypeId, type_name},
@@ -89,9 +91,11 @@ where
     T: Service<Handle = Arc<T>> + HealthCheck + 'static,
 {
     async fn build(&self, ctx: &AppContext) -> Result<(), StdError> {
-        let component = ctx.get_component::<T::Handle>().unwrap();
+        let component = ctx
+            .get_component::<T::Handle>()
+            .ok_or_else(|| format!("Missing component: {}", type_name::<T::Handle>()))?;
         ctx.get_component_mut::<HealthCheckRegistry>()
-            .unwrap()
+            .ok_or("HealthCheckRegistry component is missing")?
             .add_health_check(component);
         Ok(())
     }
@@ -133,11 +137,7 @@ impl AddHealthCheckExt for AppContext {
     where
         T: HealthCheck + 'static,
     {
-        if !self.has_component::<HealthCheckRegistry>() {
-            self.add_component(HealthCheckRegistry::default());
-        }
-        self.get_component_mut::<HealthCheckRegistry>()
-            .unwrap()
+        self.get_or_insert_component::<HealthCheckRegistry>()
             .add_health_check(health_check.into());
     }
 
@@ -192,16 +192,26 @@ impl AddHealthCheckServiceExt for AppBuilder {
     }
 }
 
+const DEFAULT_HEALTH_CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+const WAIT_FOR_READY_INTERVAL: Duration = Duration::from_millis(100);
+
 /// Client for probing a service's `/health` endpoint over HTTP.
 ///
 /// The [`ControlServerPlugin`] registers a `HealthClient` component pointed at
 /// its own health endpoint; it can also be built directly with
 /// [`new`](HealthClient::new) to probe a remote service (for example to wait for
 /// a dependency to become ready).
+///
+/// Each probe times out after 5 seconds by default, so a hung server fails the
+/// probe instead of stalling it; change it with
+/// [`with_timeout`](HealthClient::with_timeout). Protected endpoints can be
+/// probed by adding headers with [`with_header`](HealthClient::with_header).
 #[derive(Clone)]
 pub struct HealthClient {
     client: reqwest::Client,
     endpoint: String,
+    timeout: Duration,
+    headers: HeaderMap,
 }
 
 impl HealthClient {
@@ -213,9 +223,23 @@ impl HealthClient {
         Self {
             client: reqwest::Client::new(),
             endpoint,
+            timeout: DEFAULT_HEALTH_CLIENT_TIMEOUT,
+            headers: HeaderMap::new(),
         }
     }
 
+    /// Sets the timeout of a single probe.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Adds a header sent with every probe, e.g. `Authorization`.
+    pub fn with_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
     /// Performs a single health probe.
     ///
     /// # Errors
@@ -223,9 +247,15 @@ impl HealthClient {
     /// Returns a [`HealthCheckError`] if the request cannot be sent or the
     /// endpoint responds with a non-success status.
     pub async fn health_check(&self) -> Result<(), HealthCheckError> {
+        self.probe(self.timeout).await
+    }
+
+    async fn probe(&self, timeout: Duration) -> Result<(), HealthCheckError> {
         let response = self
             .client
             .get(&self.endpoint)
+            .headers(self.headers.clone())
+            .timeout(timeout)
             .send()
             .await
             .map_err(|err| HealthCheckError {
@@ -246,6 +276,9 @@ impl HealthClient {
     /// Polls the endpoint until it reports healthy or `timeout` elapses,
     /// retrying every 100 ms.
     ///
+    /// Probes are cut short so that waiting does not run much past `timeout`,
+    /// even if the per-probe timeout is longer.
+    ///
     /// # Errors
     ///
     /// Returns the last [`HealthCheckError`] if the endpoint is still not healthy
@@ -253,39 +286,102 @@ impl HealthClient {
     pub async fn wait_for_ready(&self, timeout: Duration) -> Result<(), HealthCheckError> {
         let start = Instant::now();
         loop {
-            match self.health_check().await {
+            let remaining = timeout.saturating_sub(start.elapsed());
+            let probe_timeout = self.timeout.min(remaining).max(WAIT_FOR_READY_INTERVAL);
+            match self.probe(probe_timeout).await {
                 Ok(_) => return Ok(()),
                 Err(err) => {
                     if start.elapsed() >= timeout {
                         return Err(err);
                     }
-                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    tokio::time::sleep(WAIT_FOR_READY_INTERVAL).await;
                 }
             }
         }
     }
 }
 
+/// Configuration for the health endpoints, read from the optional `health`
+/// config section.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[config_section("health")]
+pub struct HealthConfig {
+    /// Path of the [`HealthRouter`] endpoint, `/health` by default.
+    #[serde(default = "default_health_path")]
+    pub path: String,
+    /// Path of the [`PingHandler`] endpoint, `/ping` by default.
+    #[serde(default = "default_ping_path")]
+    pub ping_path: String,
+}
+
+fn default_health_path() -> String {
+    "/health".into()
+}
+
+fn default_ping_path() -> String {
+    "/ping".into()
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            path: default_health_path(),
+            ping_path: default_ping_path(),
+        }
+    }
+}
+
+impl HealthConfig {
+    /// Reads the `health` section, falling back to the defaults when the
+    /// section or the [`Config`] component is missing.
+    pub(crate) fn from_context(ctx: &AppContext) -> Result<Self, StdError> {
+        let config = match ctx.get_component_ref::<Config>() {
+            Some(config) => config.get::<Option<Self>>(Self::key())?.unwrap_or_default(),
+            None => Self::default(),
+        };
+        for path in [&config.path, &config.ping_path] {
+            if !path.starts_with('/') {
+                return Err(
+                    format!("Invalid health endpoint path {path:?}: must start with `/`").into(),
+                );
+            }
+        }
+        Ok(config)
+    }
+}
+
 /// Router exposing `GET /health` on the control server.
 ///
 /// Aggregates every registered [`HealthCheck`]: the endpoint returns `200` with
 /// body `healthy` when all checks pass, or `500` with a JSON [`HealthCheckError`]
 /// naming the first check that failed. Register it with
 /// [`add_control_router_service`](crate::AddControlRouterServiceExt::add_control_router_service);
-/// it relies on [`ControlServerPlugin`] for the health-check registry.
-#[derive(Service)]
-pub struct HealthRouter;
+/// it relies on [`ControlServerPlugin`] for the health-check registry. The path
+/// can be changed with [`HealthConfig::path`].
+pub struct HealthRouter {
+    path: String,
+}
+
+impl Service for HealthRouter {
+    type Handle = Arc<Self>;
+
+    async fn build(ctx: &AppContext) -> Result<Self::Handle, StdError> {
+        let path = HealthConfig::from_context(ctx)?.path;
+        Ok(Arc::new(Self { path }))
+    }
+}
 
 impl RouterBuilder for HealthRouter {
-    fn build_router(self: Arc<Self>, app: &App) -> Router {
+    fn build_router(self: Arc<Self>, app: &App) -> Result<Router, StdError> {
         let health_checks = app
             .get_component_ref::<HealthCheckRegistry>()
-            .unwrap()
+            .ok_or("HealthCheckRegistry component is missing")?
             .build_health_checks();
-        Router::new().route(
-            "/health",
+        let path = self.path.clone();
+        Ok(Router::new().route(
+            &path,
             routing::get(|| async move { self.health(health_checks.as_ref()).await }),
-        )
+        ))
     }
 }
 
@@ -331,9 +427,20 @@ impl axum::response::IntoResponse for HealthCheckError {
 
 /// Router exposing `GET /ping`, which always returns `pong`.
 ///
-/// A trivial liveness endpoint; register it as a router on either server.
-#[derive(Service)]
-pub struct PingHandler;
+/// A trivial liveness endpoint; register it as a router on either server. The
+/// path can be changed with [`HealthConfig::ping_path`].
+pub struct PingHandler {
+    path: String,
+}
+
+impl Service for PingHandler {
+    type Handle = Arc<Self>;
+
+    async fn build(ctx: &AppContext) -> Result<Self::Handle, StdError> {
+        let path = HealthConfig::from_context(ctx)?.ping_path;
+        Ok(Arc::new(Self { path }))
+    }
+}
 
 impl PingHandler {
     async fn ping() -> &'static str {
@@ -342,7 +449,10 @@ impl PingHandler {
 }
 
 impl RouterBuilder for PingHandler {
-    fn build_router(self: Arc<Self>, _app: &App) -> Router {
-        Router::new().route("/ping", routing::get(|| async move { Self::ping().await }))
+    fn build_router(self: Arc<Self>, _app: &App) -> Result<Router, StdError> {
+        Ok(Router::new().route(
+            &self.path,
+            routing::get(|| async move { Self::ping().await }),
+        ))
     }
 }
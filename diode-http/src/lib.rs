@@ -1,19 +1,36 @@
+mod body_log;
 mod control_router;
+mod dynamic_config;
 mod health_check;
 mod middleware;
+mod negotiation;
+mod reload;
+mod request_context;
 mod router;
+mod scopes;
+mod server;
+pub mod testing;
 mod tracing;
 
+pub use body_log::*;
 pub use control_router::*;
+pub use dynamic_config::*;
 pub use health_check::*;
 pub use middleware::*;
+pub use negotiation::*;
+pub use reload::RouterReloader;
+pub use request_context::*;
 pub use router::*;
+pub use scopes::*;
+pub use server::{HttpTuning, ServerListener};
+pub use tracing::{RequestId, RequestStart};
 
 pub use axum;
 
 pub use axum::Router;
 pub use axum::extract::Request;
 pub use axum::response::Response;
+pub use axum::response::sse::Event as SseEvent;
 pub use axum::routing;
 
 #[cfg(feature = "macros")]
@@ -1,10 +1,16 @@
+use std::any::{TypeId, type_name};
+use std::collections::{HashMap, HashSet};
 use std::mem::replace;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
+use axum::Router;
+use axum::http::StatusCode;
 use axum::response::Response;
 use axum::{extract::Request, response::IntoResponse};
-use diode::{AddServiceExt as _, AppBuilder, AppContext, Service};
+use diode::{AddServiceExt as _, App, AppBuilder, AppContext, Service, StdError};
 
 /// The continuation passed to a [`Middleware`]: runs the rest of the chain (the
 /// next middleware, or the route handler).
@@ -31,12 +37,51 @@ where
     }
 }
 
+/// A [`Next`] that records how long the rest of the chain took.
+struct TimedNext<N> {
+    next: N,
+    elapsed_nanos: Arc<AtomicU64>,
+}
+
+impl<N> Next for TimedNext<N>
+where
+    N: Next,
+{
+    async fn call(self, request: Request) -> Response {
+        let start = Instant::now();
+        let response = self.next.call(request).await;
+        let elapsed = u64::try_from(start.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        self.elapsed_nanos.store(elapsed, Ordering::Relaxed);
+        response
+    }
+}
+
+/// Component that enables per-middleware timing.
+///
+/// When it is registered, every [`Middleware`] applied by the router macros or
+/// [`AddMiddlewareExt::add_global_middleware`] logs a `Middleware finished`
+/// event with its type name and the time spent in the middleware itself, that
+/// is excluding the rest of the chain behind [`Next`]. The
+/// [`HttpServerPlugin`](crate::HttpServerPlugin) registers it when
+/// `middleware_timing` is set in [`HttpServerConfig`](crate::HttpServerConfig).
+///
+/// Timing applies to every server of the application and adds a clock read
+/// and a log event per middleware per request, so keep it off in production
+/// unless you are chasing latency.
+pub struct MiddlewareTiming;
+
 #[doc(hidden)]
-pub struct MiddlewareLayerImpl<T>(pub Arc<T>);
+pub struct MiddlewareLayerImpl<T> {
+    middleware: Arc<T>,
+    timing: bool,
+}
 
 impl<T> Clone for MiddlewareLayerImpl<T> {
     fn clone(&self) -> Self {
-        Self(self.0.clone())
+        Self {
+            middleware: self.middleware.clone(),
+            timing: self.timing,
+        }
     }
 }
 
@@ -45,7 +90,8 @@ impl<T, S> tower::Layer<S> for MiddlewareLayerImpl<T> {
 
     fn layer(&self, inner: S) -> Self::Service {
         Self::Service {
-            middleware: self.0.clone(),
+            middleware: self.middleware.clone(),
+            timing: self.timing,
             inner,
         }
     }
@@ -54,6 +100,7 @@ impl<T, S> tower::Layer<S> for MiddlewareLayerImpl<T> {
 #[doc(hidden)]
 pub struct MiddlewareServiceImpl<T, S> {
     middleware: Arc<T>,
+    timing: bool,
     inner: S,
 }
 
@@ -64,6 +111,7 @@ where
     fn clone(&self) -> Self {
         Self {
             middleware: self.middleware.clone(),
+            timing: self.timing,
             inner: self.inner.clone(),
         }
     }
@@ -94,16 +142,80 @@ where
         let clone = self.inner.clone();
         let inner = replace(&mut self.inner, clone);
         let middleware = self.middleware.clone();
+        let timing = self.timing;
         let next = NextImpl(inner);
         Box::pin(async move {
-            match middleware.call(request, next).await {
+            if !middleware.should_apply(&request) {
+                return Ok(next.call(request).await);
+            }
+            let result = if timing {
+                let elapsed_nanos = Arc::new(AtomicU64::new(0));
+                let next = TimedNext {
+                    next,
+                    elapsed_nanos: elapsed_nanos.clone(),
+                };
+                let start = Instant::now();
+                let result = middleware.call(request, next).await;
+                let next_elapsed = Duration::from_nanos(elapsed_nanos.load(Ordering::Relaxed));
+                tracing::info!(
+                    middleware = type_name::<T>(),
+                    elapsed = ?start.elapsed().saturating_sub(next_elapsed),
+                    "Middleware finished"
+                );
+                result
+            } else {
+                middleware.call(request, next).await
+            };
+            match result {
                 Ok(response) => Ok(response.into_response()),
-                Err(err) => Ok(err.into_response()),
+                Err(err) => {
+                    let mut response = err.into_response();
+                    if response.status().is_server_error()
+                        && response.extensions().get::<Arc<StdError>>().is_none()
+                    {
+                        let error = MiddlewareError {
+                            middleware: type_name::<T>(),
+                            status: response.status(),
+                        };
+                        response
+                            .extensions_mut()
+                            .insert::<Arc<StdError>>(Arc::new(Box::new(error)));
+                    }
+                    Ok(response)
+                }
             }
         })
     }
 }
 
+/// Error recorded when a [`Middleware`] short-circuits with its `Error`.
+///
+/// A server error response (5xx) gets an `Arc<StdError>` extension, which the
+/// tracing layer logs as a `Response error`, the same way as for handler
+/// responses carrying one. An error whose response already has that extension
+/// keeps it; otherwise this error is inserted, naming the middleware and the
+/// response status. Client errors (4xx), such as a rejected authentication,
+/// are routine and get no extension.
+#[derive(Clone, Debug)]
+pub struct MiddlewareError {
+    /// Type name of the middleware.
+    pub middleware: &'static str,
+    /// Status of the error response.
+    pub status: StatusCode,
+}
+
+impl std::fmt::Display for MiddlewareError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "middleware {} failed with status {}",
+            self.middleware, self.status
+        )
+    }
+}
+
+impl std::error::Error for MiddlewareError {}
+
 /// Middleware that wraps request handling on an HTTP server.
 ///
 /// A middleware receives each request together with a [`Next`] continuation. It
@@ -114,8 +226,10 @@ where
 /// or resolved from the DI container with
 /// [`AddMiddlewareServiceExt::add_middleware_service`] (the latter additionally
 /// requires the type to be a [`Service`], so it can hold injected dependencies).
-/// Attach it to routes with the `#[router(middleware = [..])]` /
-/// `#[route(middleware = [..])]` macro attributes.
+/// Registration alone does not apply it: attach it to routes with the
+/// `#[router(middleware = [..])]` / `#[route(middleware = [..])]` macro
+/// attributes, or to every router with
+/// [`AddMiddlewareExt::add_global_middleware`].
 ///
 /// # Ordering
 ///
@@ -124,6 +238,17 @@ where
 /// route-level middleware. So `#[router(middleware = [A, B])]` combined with
 /// `#[route(middleware = [C, D])]` enters as `A, B, C, D` and unwinds as
 /// `D, C, B, A`.
+///
+/// A middleware can also declare its position relative to others with
+/// [`order`](Middleware::order). The constraints are applied within each list:
+/// the list is reordered as little as needed to satisfy them, keeping the
+/// declared order otherwise. Constraints on middleware that are not in the same
+/// list are ignored, so they cannot move route-level middleware outside
+/// router-level middleware.
+///
+/// Global middleware wraps the merged router of each server, so it is outside of
+/// all router-level middleware. Global middleware runs in registration order,
+/// subject to its own [`order`](Middleware::order) constraints.
 pub trait Middleware: Send + Sync {
     /// Error type rendered into a response when [`call`](Middleware::call)
     /// returns `Err`.
@@ -135,6 +260,206 @@ pub trait Middleware: Send + Sync {
         request: Request,
         next: impl Next,
     ) -> impl Future<Output = Result<Response, Self::Error>> + Send;
+
+    /// Returns whether this middleware handles `request`.
+    ///
+    /// When it returns `false`, [`call`](Middleware::call) is skipped and the
+    /// request goes straight to the rest of the chain. Useful when the
+    /// condition depends on the request (a path prefix, a header) rather than
+    /// on the route. Defaults to `true`.
+    fn should_apply(&self, request: &Request) -> bool {
+        let _ = request;
+        true
+    }
+
+    /// Declares which middleware this one must run before or after.
+    ///
+    /// Defaults to no constraints.
+    fn order() -> MiddlewareOrder
+    where
+        Self: Sized,
+    {
+        MiddlewareOrder::new()
+    }
+}
+
+/// Ordering constraints of a [`Middleware`] relative to other middleware,
+/// returned by [`Middleware::order`].
+///
+/// "Before" means outside: a middleware that runs before `T` sees the request
+/// first and the response last.
+///
+/// ```rust
+/// use std::convert::Infallible;
+///
+/// use diode_http::{Middleware, MiddlewareOrder, Next, Request, Response};
+///
+/// struct RequestId;
+/// struct Auth;
+///
+/// impl Middleware for RequestId {
+///     type Error = Infallible;
+///
+///     async fn call(&self, request: Request, next: impl Next) -> Result<Response, Infallible> {
+///         Ok(next.call(request).await)
+///     }
+///
+///     fn order() -> MiddlewareOrder {
+///         MiddlewareOrder::new().before::<Auth>()
+///     }
+/// }
+/// # impl Middleware for Auth {
+/// #     type Error = Infallible;
+/// #
+/// #     async fn call(&self, request: Request, next: impl Next) -> Result<Response, Infallible> {
+/// #         Ok(next.call(request).await)
+/// #     }
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MiddlewareOrder {
+    before: HashSet<TypeId>,
+    after: HashSet<TypeId>,
+}
+
+impl MiddlewareOrder {
+    /// Creates an empty set of constraints.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs this middleware before (outside of) `T`.
+    pub fn before<T>(mut self) -> Self
+    where
+        T: Middleware + 'static,
+    {
+        self.before.insert(TypeId::of::<T>());
+        self
+    }
+
+    /// Runs this middleware after (inside of) `T`.
+    pub fn after<T>(mut self) -> Self
+    where
+        T: Middleware + 'static,
+    {
+        self.after.insert(TypeId::of::<T>());
+        self
+    }
+}
+
+struct MiddlewareEntry<R> {
+    type_id: TypeId,
+    name: &'static str,
+    order: MiddlewareOrder,
+    layer: Box<dyn FnOnce(R) -> R>,
+}
+
+/// A list of middleware applied to a router or route, ordered by
+/// [`Middleware::order`]. Used by the router macros.
+#[doc(hidden)]
+pub struct MiddlewareStack<R> {
+    entries: Vec<MiddlewareEntry<R>>,
+}
+
+impl<R> Default for MiddlewareStack<R> {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl<R> MiddlewareStack<R> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends middleware `T` resolved from `app`; `layer` wraps a target in it.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `T` is not registered.
+    pub fn push<T, F>(&mut self, app: &App, layer: F) -> Result<(), StdError>
+    where
+        T: Middleware + 'static,
+        F: FnOnce(R, MiddlewareLayerImpl<T>) -> R + 'static,
+    {
+        let middleware = app
+            .get_component::<Arc<T>>()
+            .ok_or_else(|| format!("Middleware {} is not registered", type_name::<T>()))?;
+        let middleware = MiddlewareLayerImpl {
+            middleware,
+            timing: app.has_component::<MiddlewareTiming>(),
+        };
+        self.entries.push(MiddlewareEntry {
+            type_id: TypeId::of::<T>(),
+            name: type_name::<T>(),
+            order: T::order(),
+            layer: Box::new(move |target| layer(target, middleware)),
+        });
+        Ok(())
+    }
+
+    /// Wraps `target` in every middleware, the first one (after ordering)
+    /// outermost.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the ordering constraints form a cycle.
+    pub fn layer(self, target: R) -> Result<R, StdError> {
+        Ok(self
+            .into_ordered()?
+            .into_iter()
+            .rev()
+            .fold(target, |target, entry| (entry.layer)(target)))
+    }
+
+    fn into_ordered(self) -> Result<Vec<MiddlewareEntry<R>>, StdError> {
+        let index: HashMap<_, _> = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (entry.type_id, i))
+            .collect();
+        // For every entry, the entries that must be placed outside of it.
+        let mut outer = vec![Vec::new(); self.entries.len()];
+        for (i, entry) in self.entries.iter().enumerate() {
+            for type_id in &entry.order.before {
+                if let Some(&j) = index.get(type_id) {
+                    outer[j].push(i);
+                }
+            }
+            for type_id in &entry.order.after {
+                if let Some(&j) = index.get(type_id) {
+                    outer[i].push(j);
+                }
+            }
+        }
+        let mut entries: Vec<_> = self.entries.into_iter().map(Some).collect();
+        let mut placed = vec![false; entries.len()];
+        let mut ordered = Vec::with_capacity(entries.len());
+        while ordered.len() < entries.len() {
+            let Some(next) =
+                (0..entries.len()).find(|&i| !placed[i] && outer[i].iter().all(|&j| placed[j]))
+            else {
+                let cycle: Vec<_> = entries
+                    .iter()
+                    .zip(&placed)
+                    .filter(|(_, placed)| !**placed)
+                    .filter_map(|(entry, _)| entry.as_ref().map(|v| v.name))
+                    .collect();
+                return Err(
+                    format!("Middleware ordering cycle between {}", cycle.join(", ")).into(),
+                );
+            };
+            placed[next] = true;
+            ordered.push(next);
+        }
+        Ok(ordered
+            .into_iter()
+            .map(|i| entries[i].take().unwrap())
+            .collect())
+    }
 }
 
 /// Registers concrete [`Middleware`] instances so the router macros can resolve
@@ -144,6 +469,11 @@ pub trait Middleware: Send + Sync {
 /// [`AppBuilder`] or from within a plugin's `build`. A middleware is identified by
 /// its type and stored as an `Arc<T>` component; reference it from a router with
 /// the `#[router(middleware = [..])]` / `#[route(middleware = [..])]` attributes.
+///
+/// Registering a middleware only makes it available; it is applied only where a
+/// router references it. Use
+/// [`add_global_middleware`](AddMiddlewareExt::add_global_middleware) to apply
+/// it to every router.
 pub trait AddMiddlewareExt {
     /// Registers `middleware` so routers can reference it by type `T`.
     ///
@@ -161,6 +491,50 @@ pub trait AddMiddlewareExt {
     fn has_middleware<T>(&self) -> bool
     where
         T: Middleware + 'static;
+
+    /// Applies middleware `T` to every router served by the
+    /// [`HttpServerPlugin`](crate::HttpServerPlugin) and the
+    /// [`ControlServerPlugin`](crate::ControlServerPlugin).
+    ///
+    /// `T` must still be registered, with
+    /// [`add_middleware`](AddMiddlewareExt::add_middleware) or
+    /// [`AddMiddlewareServiceExt::add_middleware_service`]. It wraps the merged
+    /// router of each server, outside of any router-level middleware.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `T` is already applied globally. Building the app fails with
+    /// an error if `T` is not registered.
+    fn add_global_middleware<T>(&self)
+    where
+        T: Middleware + 'static;
+
+    /// Returns whether middleware `T` is applied globally.
+    fn has_global_middleware<T>(&self) -> bool
+    where
+        T: Middleware + 'static;
+
+    /// Applies middleware `T` to every router served by the
+    /// [`ControlServerPlugin`](crate::ControlServerPlugin) only.
+    ///
+    /// Like [`add_global_middleware`](AddMiddlewareExt::add_global_middleware),
+    /// but for stricter middleware the public server must not get, such as an
+    /// allowlist of internal addresses. `T` is ordered together with the global
+    /// middleware of the control server, and is applied once if it is also
+    /// global.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `T` is already applied to the control server. Building the app
+    /// fails with an error if `T` is not registered.
+    fn add_control_middleware<T>(&self)
+    where
+        T: Middleware + 'static;
+
+    /// Returns whether middleware `T` is applied to the control server only.
+    fn has_control_middleware<T>(&self) -> bool
+    where
+        T: Middleware + 'static;
 }
 
 impl AddMiddlewareExt for AppContext {
@@ -177,6 +551,125 @@ impl AddMiddlewareExt for AppContext {
     {
         self.has_component::<Arc<T>>()
     }
+
+    fn add_global_middleware<T>(&self)
+    where
+        T: Middleware + 'static,
+    {
+        self.get_or_insert_component::<GlobalMiddlewareRegistry>()
+            .add_middleware::<T>();
+    }
+
+    fn has_global_middleware<T>(&self) -> bool
+    where
+        T: Middleware + 'static,
+    {
+        self.get_component_ref::<GlobalMiddlewareRegistry>()
+            .is_some_and(|v| v.has_middleware::<T>())
+    }
+
+    fn add_control_middleware<T>(&self)
+    where
+        T: Middleware + 'static,
+    {
+        self.get_or_insert_component::<ControlMiddlewareRegistry>()
+            .add_middleware::<T>();
+    }
+
+    fn has_control_middleware<T>(&self) -> bool
+    where
+        T: Middleware + 'static,
+    {
+        self.get_component_ref::<ControlMiddlewareRegistry>()
+            .is_some_and(|v| v.has_middleware::<T>())
+    }
+}
+
+type PushMiddlewareFn = fn(&mut MiddlewareStack<Router>, &App) -> Result<(), StdError>;
+
+#[derive(Default)]
+struct MiddlewareRegistry {
+    middleware: Vec<(TypeId, PushMiddlewareFn)>,
+}
+
+impl MiddlewareRegistry {
+    fn add_middleware<T: Middleware + 'static>(&mut self, kind: &str) {
+        if self.has_middleware::<T>() {
+            panic!("{kind} middleware {} already added", type_name::<T>());
+        }
+        self.middleware.push((TypeId::of::<T>(), |stack, app| {
+            stack.push::<T, _>(app, |router, layer| router.layer(layer))
+        }));
+    }
+
+    fn has_middleware<T: Middleware + 'static>(&self) -> bool {
+        let type_id = TypeId::of::<T>();
+        self.middleware.iter().any(|(v, _)| *v == type_id)
+    }
+}
+
+#[derive(Default)]
+struct GlobalMiddlewareRegistry(MiddlewareRegistry);
+
+impl GlobalMiddlewareRegistry {
+    fn add_middleware<T: Middleware + 'static>(&mut self) {
+        self.0.add_middleware::<T>("Global");
+    }
+
+    fn has_middleware<T: Middleware + 'static>(&self) -> bool {
+        self.0.has_middleware::<T>()
+    }
+}
+
+#[derive(Default)]
+struct ControlMiddlewareRegistry(MiddlewareRegistry);
+
+impl ControlMiddlewareRegistry {
+    fn add_middleware<T: Middleware + 'static>(&mut self) {
+        self.0.add_middleware::<T>("Control");
+    }
+
+    fn has_middleware<T: Middleware + 'static>(&self) -> bool {
+        self.0.has_middleware::<T>()
+    }
+}
+
+/// Wraps a server's merged `router` in the global middleware.
+pub(crate) fn layer_global_middleware(router: Router, app: &App) -> Result<Router, StdError> {
+    let global = app.get_component_ref::<GlobalMiddlewareRegistry>();
+    layer_middleware(router, app, global.iter().map(|v| &v.0))
+}
+
+/// Wraps the control server's merged `router` in the global and the
+/// control-only middleware.
+pub(crate) fn layer_control_middleware(router: Router, app: &App) -> Result<Router, StdError> {
+    let global = app.get_component_ref::<GlobalMiddlewareRegistry>();
+    let control = app.get_component_ref::<ControlMiddlewareRegistry>();
+    layer_middleware(
+        router,
+        app,
+        global
+            .iter()
+            .map(|v| &v.0)
+            .chain(control.iter().map(|v| &v.0)),
+    )
+}
+
+fn layer_middleware<'a>(
+    router: Router,
+    app: &App,
+    registries: impl Iterator<Item = &'a MiddlewareRegistry>,
+) -> Result<Router, StdError> {
+    let mut stack = MiddlewareStack::new();
+    let mut types = HashSet::new();
+    for registry in registries {
+        for (type_id, push) in &registry.middleware {
+            if types.insert(*type_id) {
+                push(&mut stack, app)?;
+            }
+        }
+    }
+    stack.layer(router)
 }
 
 /// Registers middleware resolved from the dependency-injection container.
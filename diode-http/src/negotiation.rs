@@ -0,0 +1,127 @@
+use std::convert::Infallible;
+
+use axum::http::{HeaderValue, header};
+use axum::response::IntoResponse;
+use axum::{Form, Json};
+use serde::Serialize;
+
+use crate::{Middleware, Next, Request, Response};
+
+tokio::task_local! {
+    static RESPONSE_FORMAT: ResponseFormat;
+}
+
+/// Body format of a [`ContentNegotiated`] response.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ResponseFormat {
+    /// `application/json`.
+    #[default]
+    Json,
+    /// `application/x-www-form-urlencoded`.
+    Form,
+}
+
+impl ResponseFormat {
+    /// Picks the format for an `Accept` header value.
+    ///
+    /// Media ranges are ranked by their `q` parameter, earlier ones winning
+    /// ties. Falls back to [`Json`](ResponseFormat::Json) if no range matches
+    /// a supported format.
+    pub fn from_accept(accept: &str) -> Self {
+        let mut best = None;
+        for range in accept.split(',') {
+            let mut params = range.split(';');
+            let format = match params.next().unwrap_or_default().trim() {
+                "application/json" | "application/*" | "*/*" => ResponseFormat::Json,
+                "application/x-www-form-urlencoded" => ResponseFormat::Form,
+                _ => continue,
+            };
+            let quality = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if quality > 0.0 && best.is_none_or(|(_, best)| quality > best) {
+                best = Some((format, quality));
+            }
+        }
+        best.map(|(format, _)| format).unwrap_or_default()
+    }
+
+    /// Returns the format negotiated for the current request, or
+    /// [`Json`](ResponseFormat::Json) outside of [`ContentNegotiationMiddleware`].
+    pub fn current() -> Self {
+        RESPONSE_FORMAT
+            .try_with(|format| *format)
+            .unwrap_or_default()
+    }
+}
+
+/// Response serialized in the format requested by the `Accept` header.
+///
+/// The format is negotiated by [`ContentNegotiationMiddleware`], which must
+/// wrap the route; without it the body is JSON. The response carries a
+/// `Vary: Accept` header.
+///
+/// ```rust
+/// use diode_http::{ContentNegotiated, router};
+/// use serde::Serialize;
+///
+/// #[derive(Serialize)]
+/// struct User {
+///     name: String,
+/// }
+///
+/// struct UserRouter;
+///
+/// #[router]
+/// impl UserRouter {
+///     #[route(get, path = "/user")]
+///     async fn user(&self) -> ContentNegotiated<User> {
+///         ContentNegotiated(User {
+///             name: "alice".to_string(),
+///         })
+///     }
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ContentNegotiated<T>(pub T);
+
+impl<T> IntoResponse for ContentNegotiated<T>
+where
+    T: Serialize,
+{
+    fn into_response(self) -> Response {
+        let mut response = match ResponseFormat::current() {
+            ResponseFormat::Json => Json(self.0).into_response(),
+            ResponseFormat::Form => Form(self.0).into_response(),
+        };
+        response
+            .headers_mut()
+            .append(header::VARY, HeaderValue::from_static("accept"));
+        response
+    }
+}
+
+/// Middleware that negotiates the [`ResponseFormat`] of [`ContentNegotiated`]
+/// responses from the request's `Accept` header.
+///
+/// The format is also added to the request extensions. It is kept in a
+/// `tokio::task_local!`, so a response built in a spawned task falls back to
+/// JSON.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ContentNegotiationMiddleware;
+
+impl Middleware for ContentNegotiationMiddleware {
+    type Error = Infallible;
+
+    async fn call(&self, mut request: Request, next: impl Next) -> Result<Response, Infallible> {
+        let format = request
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .map(ResponseFormat::from_accept)
+            .unwrap_or_default();
+        request.extensions_mut().insert(format);
+        Ok(RESPONSE_FORMAT.scope(format, next.call(request)).await)
+    }
+}
@@ -0,0 +1,77 @@
+use std::convert::Infallible;
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+
+use axum::Router;
+use tokio::sync::Notify;
+use tower::ServiceExt as _;
+use tower::util::Oneshot;
+
+use crate::Request;
+
+/// Component that lets the public HTTP server rebuild its routers while it
+/// runs, e.g. to enable routes behind a feature flag.
+///
+/// Add it to the app to opt in. [`reload`](RouterReloader::reload) makes the
+/// server call [`RouterBuilder::build_router`](crate::RouterBuilder::build_router)
+/// of every registered router again and serve the new router for the
+/// following requests; requests in flight finish on the old one. If the
+/// rebuild fails, the error is logged and the old router is kept.
+///
+/// ```rust
+/// use diode::App;
+/// use diode_http::{HttpServerPlugin, RouterReloader};
+///
+/// let mut builder = App::builder();
+/// builder
+///     .add_plugin(HttpServerPlugin)
+///     .add_component(RouterReloader::new());
+/// // Later, with the built app:
+/// // app.get_component::<RouterReloader>().unwrap().reload();
+/// ```
+///
+/// With reloading enabled every request takes a read lock and clones the
+/// current [`Router`], a few reference-count updates. That is cheap next to
+/// routing itself, but it is paid even if the routers never change, so only
+/// add the component when routes really change at runtime.
+#[derive(Clone, Default)]
+pub struct RouterReloader {
+    notify: Arc<Notify>,
+}
+
+impl RouterReloader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks the server to rebuild its routers.
+    ///
+    /// Requests made before the server picks up the previous one are merged
+    /// into a single rebuild.
+    pub fn reload(&self) {
+        self.notify.notify_one();
+    }
+
+    pub(crate) async fn reloaded(&self) {
+        self.notify.notified().await
+    }
+}
+
+/// Service serving the current router of a [`RouterReloader`]-enabled server.
+#[derive(Clone)]
+pub(crate) struct ReloadableRouter(pub(crate) Arc<RwLock<Router>>);
+
+impl tower::Service<Request> for ReloadableRouter {
+    type Response = crate::Response;
+    type Error = Infallible;
+    type Future = Oneshot<Router, Request>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let router = self.0.read().unwrap().clone();
+        router.oneshot(request)
+    }
+}
@@ -0,0 +1,101 @@
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+
+use axum::http::Extensions;
+
+use crate::{Middleware, Next, Request, Response};
+
+tokio::task_local! {
+    static REQUEST_CONTEXT: RequestContext;
+}
+
+/// Ambient per-request values, readable without passing them around.
+///
+/// [`RequestContextMiddleware`] starts a context for each request with a copy
+/// of the request extensions it sees (for example the [`RequestId`](crate::RequestId)),
+/// and makes it the [`current`](RequestContext::current) context for the rest
+/// of the chain. Inner middleware can [`insert`](RequestContext::insert) more
+/// values, such as an authenticated tenant id, and any code called by the
+/// handler can read them with [`get`](RequestContext::get).
+///
+/// The context is a `tokio::task_local!`: it is visible to everything the
+/// request's task polls, including awaited futures, but not to tasks started
+/// with `tokio::spawn` or to blocking threads. Carry it into a spawned task
+/// with [`scope`](RequestContext::scope):
+///
+/// ```rust
+/// use diode_http::RequestContext;
+///
+/// # async fn example() {
+/// if let Some(context) = RequestContext::current() {
+///     tokio::spawn(context.scope(async {
+///         let _context = RequestContext::current();
+///     }));
+/// }
+/// # }
+/// ```
+///
+/// Clones share the same values, so an insert is seen by every holder.
+#[derive(Clone, Default)]
+pub struct RequestContext {
+    extensions: Arc<Mutex<Extensions>>,
+}
+
+impl RequestContext {
+    /// Creates a context holding `extensions`.
+    pub fn new(extensions: Extensions) -> Self {
+        Self {
+            extensions: Arc::new(Mutex::new(extensions)),
+        }
+    }
+
+    /// Returns the context of the current request, or `None` outside of
+    /// [`RequestContextMiddleware`] and [`scope`](RequestContext::scope).
+    pub fn current() -> Option<Self> {
+        REQUEST_CONTEXT.try_with(Clone::clone).ok()
+    }
+
+    /// Returns a clone of the value of type `T`, if any.
+    pub fn get<T>(&self) -> Option<T>
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        self.extensions.lock().unwrap().get::<T>().cloned()
+    }
+
+    /// Stores `value`, returning the previous value of type `T`, if any.
+    pub fn insert<T>(&self, value: T) -> Option<T>
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        self.extensions.lock().unwrap().insert(value)
+    }
+
+    /// Runs `future` with this context as the current one.
+    pub async fn scope<F>(self, future: F) -> F::Output
+    where
+        F: Future,
+    {
+        REQUEST_CONTEXT.scope(self, future).await
+    }
+}
+
+/// Middleware that makes a [`RequestContext`] current for the rest of the chain.
+///
+/// Register it with [`add_middleware`](crate::AddMiddlewareExt::add_middleware)
+/// and usually apply it to every router with
+/// [`add_global_middleware`](crate::AddMiddlewareExt::add_global_middleware), so
+/// that it wraps the middleware that fill the context. The context is also
+/// added to the request extensions.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RequestContextMiddleware;
+
+impl Middleware for RequestContextMiddleware {
+    type Error = Infallible;
+
+    async fn call(&self, mut request: Request, next: impl Next) -> Result<Response, Infallible> {
+        let context = RequestContext::new(request.extensions().clone());
+        request.extensions_mut().insert(context.clone());
+        Ok(context.scope(next.call(request)).await)
+    }
+}
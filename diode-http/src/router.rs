@@ -1,19 +1,33 @@
 use std::any::{TypeId, type_name};
 use std::collections::HashSet;
+use std::fmt;
 use std::marker::PhantomData;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
 
-use axum::Router;
+use axum::http::{HeaderName, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::{BoxError, Router};
 use diode::{
-    AddServiceExt as _, App, AppBuilder, AppContext, Dependencies, Plugin, Service,
+    AddServiceExt as _, App, AppBuilder, AppContext, Dependencies, Plugin, PluginError, Service,
     ServiceDependencyExt as _, StdError,
 };
-use diode_base::{AddDaemonExt as _, CancellationToken, Config, Daemon, config_section, defer};
+use diode_base::{
+    AddDaemonExt as _, CancellationToken, Config, Daemon, DaemonReadyExt as _, config_section,
+    defer,
+};
+use futures_core::Stream;
 use serde::{Deserialize, Serialize};
-use tokio::net::TcpListener;
 
-use crate::tracing::TracingLayer;
+use crate::reload::ReloadableRouter;
+use crate::server::{bind_tcp, serve};
+use crate::tracing::{TracingLayer, catch_panic_layer};
+use crate::{
+    HttpTuning, MiddlewareTiming, RouterReloader, ServerListener, layer_global_middleware,
+};
 
 /// Builds the [`axum::Router`] contributed by a type to an HTTP server.
 ///
@@ -26,7 +40,40 @@ use crate::tracing::TracingLayer;
 /// [`Service`]). The server merges every registered router into one.
 pub trait RouterBuilder: Send + Sync {
     /// Builds this type's routes into a [`Router`].
-    fn build_router(self: Arc<Self>, app: &App) -> Router;
+    ///
+    /// Called once the app is built, to check that the routes can be built,
+    /// and again when the server starts. Return an error, rather than panic,
+    /// when a component the routes need is missing: the app build then fails
+    /// with it.
+    fn build_router(self: Arc<Self>, app: &App) -> Result<Router, StdError>;
+}
+
+/// Converts a handler result into a response, replacing a `200 OK` status with
+/// `status`. Used by `#[route(status = ..)]`; any other status set by the
+/// handler is kept.
+#[doc(hidden)]
+pub fn with_default_status(response: impl IntoResponse, status: u16) -> Response {
+    let mut response = response.into_response();
+    if response.status() == StatusCode::OK {
+        *response.status_mut() = StatusCode::from_u16(status).expect("Invalid status code");
+    }
+    response
+}
+
+/// Streams `stream` as server-sent events, sending a keep-alive comment while
+/// the stream is idle.
+///
+/// `#[route(sse, path = "..")]` applies this to the stream returned by the
+/// handler, so the route keeps its middleware. Call it directly from a
+/// handler that needs to do more than return the stream.
+pub fn sse_response<S, E>(stream: S) -> Response
+where
+    S: Stream<Item = Result<Event, E>> + Send + 'static,
+    E: Into<BoxError>,
+{
+    Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response()
 }
 
 #[derive(Default)]
@@ -47,46 +94,282 @@ impl RouterRegistry {
         self.types.contains(&TypeId::of::<T>())
     }
 
-    fn build_router(&self, app: &App) -> Router {
-        self.routers.iter().fold(Router::new(), |acc, v| {
-            acc.merge(v.clone().build_router(app))
-        })
+    fn build_router(&self, app: &App) -> Result<Router, StdError> {
+        let mut router = Router::new();
+        for v in self.routers.iter() {
+            router = router.merge(v.clone().build_router(app)?);
+        }
+        layer_global_middleware(router, app)
+    }
+}
+
+/// Daemon running the public HTTP server, added by [`HttpServerPlugin`].
+///
+/// It becomes ready once its listener is bound, so other daemons can start
+/// after the server with `DaemonWaitFor::new().daemon::<HttpServerDaemon>()`.
+pub struct HttpServerDaemon {
+    addr: BindAddr,
+    layers: ServerLayers,
+    max_concurrency: Option<usize>,
+    http: HttpTuning,
+}
+
+impl HttpServerDaemon {
+    fn build_router(&self, app: &App) -> Result<Router, StdError> {
+        self.layers.build_router(app)
     }
 }
 
-struct ServerDaemon {
-    addr: SocketAddr,
+/// Server-wide layers of the public router, also registered as a component so
+/// that [`serve_in_memory`](crate::testing::serve_in_memory) serves the same
+/// router as the daemon.
+#[derive(Clone)]
+pub(crate) struct ServerLayers {
+    request_id_header: Option<HeaderName>,
+    catch_panic: bool,
+    server_timing: bool,
 }
 
-impl Daemon for ServerDaemon {
-    async fn run(&self, app: &App, shutdown: CancellationToken) -> Result<(), StdError> {
-        let span = tracing::info_span!("http_server", addr = ?self.addr);
-        let router = app
+impl Default for ServerLayers {
+    fn default() -> Self {
+        Self {
+            request_id_header: None,
+            catch_panic: default_catch_panic(),
+            server_timing: false,
+        }
+    }
+}
+
+impl ServerLayers {
+    /// Merges the registered routers and wraps them in the global middleware
+    /// and the server-wide layers.
+    pub(crate) fn build_router(&self, app: &App) -> Result<Router, StdError> {
+        let mut router = app
             .get_component_ref::<RouterRegistry>()
-            .unwrap()
-            .build_router(app)
-            .layer(TracingLayer);
+            .ok_or("RouterRegistry component is missing")?
+            .build_router(app)?;
+        if self.catch_panic {
+            router = router.layer(catch_panic_layer());
+        }
+        Ok(router.layer(
+            TracingLayer::new(self.request_id_header.clone())
+                .with_server_timing(self.server_timing),
+        ))
+    }
+}
+
+impl Daemon for HttpServerDaemon {
+    async fn run(&self, app: &App, shutdown: CancellationToken) -> Result<(), StdError> {
+        let span = tracing::info_span!("http_server", addr = %self.addr);
+        let router = self.build_router(app)?;
+        let (router, reload) = match app.get_component::<RouterReloader>() {
+            Some(reloader) => {
+                let current = Arc::new(RwLock::new(router));
+                let router = Router::new().fallback_service(ReloadableRouter(current.clone()));
+                (router, Some((reloader, current)))
+            }
+            None => (router, None),
+        };
+        // Rebuilds the routers on every reload request, for as long as the
+        // server runs.
+        let reload = async {
+            let Some((reloader, current)) = reload else {
+                return std::future::pending().await;
+            };
+            loop {
+                reloader.reloaded().await;
+                match self.build_router(app) {
+                    Ok(router) => {
+                        *current.write().unwrap() = router;
+                        tracing::info!(parent: &span, "Routers reloaded");
+                    }
+                    Err(err) => {
+                        tracing::error!(parent: &span, error = %err, "Failed to reload routers");
+                    }
+                }
+            }
+        };
         tracing::info!(parent: &span, "Server starting");
         defer! {
             tracing::info!(parent: &span, "Server stopped")
         };
-        let listener = TcpListener::bind(self.addr).await.map_err(Box::new)?;
-        tracing::info!(parent: &span, "Server started");
-        axum::serve(listener, router)
-            .with_graceful_shutdown(shutdown.cancelled_owned())
-            .await
-            .map_err(Box::new)?;
+        match &self.addr {
+            BindAddr::Tcp(addr) => {
+                let listener =
+                    bind_tcp(*addr, ServerListener::<Self>::take(app), &self.http).await?;
+                tracing::info!(parent: &span, "Server started");
+                app.notify_daemon_ready::<Self>();
+                tokio::select! {
+                    result = serve(listener, router, self.max_concurrency, &self.http, shutdown) => result?,
+                    _ = reload => {}
+                }
+            }
+            #[cfg(unix)]
+            BindAddr::Unix(path) => {
+                remove_stale_socket(path)?;
+                let listener = tokio::net::UnixListener::bind(path).map_err(Box::new)?;
+                defer! {
+                    if let Err(err) = std::fs::remove_file(path) {
+                        tracing::warn!(parent: &span, error = %err, "Failed to remove socket file");
+                    }
+                };
+                tracing::info!(parent: &span, "Server started");
+                app.notify_daemon_ready::<Self>();
+                tokio::select! {
+                    result = serve(listener, router, self.max_concurrency, &self.http, shutdown) => result?,
+                    _ = reload => {}
+                }
+            }
+            #[cfg(not(unix))]
+            BindAddr::Unix(_) => {
+                return Err("Unix sockets are not supported on this platform".into());
+            }
+        }
         Ok(())
     }
 }
 
+/// Removes a socket file left behind by a previous run, which would make the
+/// bind fail. Other kinds of files are kept.
+#[cfg(unix)]
+fn remove_stale_socket(path: &std::path::Path) -> Result<(), StdError> {
+    use std::os::unix::fs::FileTypeExt as _;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            std::fs::remove_file(path).map_err(Box::new)?;
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Address the public HTTP server listens on.
+///
+/// Written in config as `"127.0.0.1:8080"` for a TCP socket, or as
+/// `"unix:/run/app.sock"` for a Unix domain socket, e.g. behind a reverse
+/// proxy on the same host. The socket file is removed when the server stops,
+/// and a stale one is replaced on start. Unix sockets are only supported on
+/// Unix platforms.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum BindAddr {
+    /// TCP socket address.
+    Tcp(SocketAddr),
+    /// Path of a Unix domain socket.
+    Unix(PathBuf),
+}
+
+impl From<SocketAddr> for BindAddr {
+    fn from(addr: SocketAddr) -> Self {
+        Self::Tcp(addr)
+    }
+}
+
+impl FromStr for BindAddr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("unix:") {
+            Some("") => Err("Empty Unix socket path".to_string()),
+            Some(path) => Ok(Self::Unix(PathBuf::from(path))),
+            None => s
+                .parse()
+                .map(Self::Tcp)
+                .map_err(|err| format!("Invalid address {s}: {err}")),
+        }
+    }
+}
+
+impl TryFrom<String> for BindAddr {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<BindAddr> for String {
+    fn from(addr: BindAddr) -> Self {
+        addr.to_string()
+    }
+}
+
+impl fmt::Display for BindAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "{addr}"),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
 /// Configuration for the public HTTP server, read from the `http_server`
 /// config section.
 #[derive(Serialize, Deserialize)]
 #[config_section("http_server")]
 pub struct HttpServerConfig {
-    /// Socket address the server binds and listens on.
-    pub addr: SocketAddr,
+    /// Address the server binds and listens on: a TCP socket address or a
+    /// `unix:` socket path, see [`BindAddr`].
+    pub addr: BindAddr,
+    /// Header carrying the request id, e.g. `X-Request-ID`.
+    ///
+    /// When set, the id is read from this header (or generated if it is
+    /// missing), recorded on the request span, exposed to handlers as a
+    /// [`RequestId`](crate::RequestId) extension and echoed on the response.
+    /// Disabled by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id_header: Option<String>,
+    /// Logs the time spent in each middleware, see [`MiddlewareTiming`].
+    ///
+    /// Disabled by default.
+    #[serde(default)]
+    pub middleware_timing: bool,
+    /// Maximum number of requests handled at once.
+    ///
+    /// Requests over the limit are rejected immediately with
+    /// `503 Service Unavailable` instead of queueing. Unlimited by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrency: Option<usize>,
+    /// Answers `500 Internal Server Error` when a handler or middleware
+    /// panics, instead of dropping the connection, and logs the panic message
+    /// on the request span.
+    ///
+    /// Enabled by default.
+    #[serde(default = "default_catch_panic")]
+    pub catch_panic: bool,
+    /// Adds a `Server-Timing: total;dur=..` header with the time spent on
+    /// each request, in milliseconds, for client-side debugging.
+    ///
+    /// Disabled by default, since it reveals server timings to clients.
+    #[serde(default)]
+    pub server_timing: bool,
+    /// TCP and HTTP connection tuning, see [`HttpTuning`].
+    #[serde(default)]
+    pub http: HttpTuning,
+}
+
+impl HttpServerConfig {
+    /// Config listening on `addr`, with the defaults of every other setting.
+    ///
+    /// Prefer it to a struct literal, which breaks when a setting is added,
+    /// and override settings with struct update syntax:
+    /// `HttpServerConfig { server_timing: true, ..HttpServerConfig::new(addr) }`.
+    pub fn new(addr: impl Into<BindAddr>) -> Self {
+        Self {
+            addr: addr.into(),
+            request_id_header: None,
+            middleware_timing: false,
+            max_concurrency: None,
+            catch_panic: default_catch_panic(),
+            server_timing: false,
+            http: HttpTuning::default(),
+        }
+    }
+}
+
+pub(crate) fn default_catch_panic() -> bool {
+    true
 }
 
 /// Plugin that runs the public HTTP server.
@@ -95,22 +378,88 @@ pub struct HttpServerConfig {
 /// [`AddRouterExt`] / [`AddRouterServiceExt`]. The server binds the address from
 /// [`HttpServerConfig`] (config section `http_server`) and runs as a [`Daemon`],
 /// shutting down gracefully when its cancellation token fires.
+///
+/// A missing `http_server` section fails the build, unless the
+/// [`OptionalHttpServer`] component is registered.
+#[derive(Default)]
 pub struct HttpServerPlugin;
 
 impl Plugin for HttpServerPlugin {
     async fn build(&self, ctx: &AppContext) -> Result<(), StdError> {
-        if !ctx.has_component::<RouterRegistry>() {
-            ctx.add_component(RouterRegistry::default());
+        ctx.get_or_insert_component::<RouterRegistry>();
+        let enabled = {
+            let config = ctx
+                .get_component_ref::<Config>()
+                .ok_or_else(|| "Config component is missing".to_string())?;
+            config.contains_key("http_server") || !ctx.has_component::<OptionalHttpServer>()
+        };
+        if !enabled {
+            tracing::warn!("Config section http_server is missing, HTTP server is disabled");
         }
-        let config = ctx
-            .get_component_ref::<Config>()
-            .ok_or_else(|| "Config component is missing".to_string())?
-            .get::<HttpServerConfig>("http_server")?;
-        ctx.add_daemon(ServerDaemon { addr: config.addr });
-        Ok(())
+        ctx.add_daemon_if(enabled, || {
+            let config = ctx
+                .get_component_ref::<Config>()
+                .ok_or_else(|| "Config component is missing".to_string())?
+                .get::<HttpServerConfig>("http_server")
+                .map_err(PluginError::misconfiguration)?;
+            let request_id_header = config
+                .request_id_header
+                .map(|v| {
+                    HeaderName::try_from(v.as_str()).map_err(|_| {
+                        PluginError::misconfiguration(format!("Invalid request id header: {v}"))
+                    })
+                })
+                .transpose()?;
+            if config.middleware_timing && !ctx.has_component::<MiddlewareTiming>() {
+                ctx.add_component(MiddlewareTiming);
+            }
+            if config.max_concurrency == Some(0) {
+                return Err(PluginError::misconfiguration(
+                    "Invalid max_concurrency: must be positive",
+                )
+                .into());
+            }
+            let layers = ServerLayers {
+                request_id_header,
+                catch_panic: config.catch_panic,
+                server_timing: config.server_timing,
+            };
+            ctx.add_component(layers.clone());
+            // Routers are registered by plugins built later, so build the
+            // router once the app is complete: a router that fails to build,
+            // e.g. on a missing middleware, then fails the app build.
+            ctx.add_ready_hook({
+                let layers = layers.clone();
+                move |app| layers.build_router(app).map(|_| ())
+            });
+            Ok(HttpServerDaemon {
+                addr: config.addr,
+                layers,
+                max_concurrency: config.max_concurrency,
+                http: config.http,
+            })
+        })
     }
 }
 
+/// Component that makes the `http_server` config section optional for
+/// [`HttpServerPlugin`].
+///
+/// When it is registered and the section is absent, the plugin logs a warning
+/// and does not start the server; routers can still be registered. A section
+/// that is present but invalid is still an error.
+///
+/// ```rust
+/// use diode::App;
+/// use diode_http::{HttpServerPlugin, OptionalHttpServer};
+///
+/// let mut builder = App::builder();
+/// builder
+///     .add_plugin(HttpServerPlugin)
+///     .add_component(OptionalHttpServer);
+/// ```
+pub struct OptionalHttpServer;
+
 struct RouterProvider<T>(PhantomData<T>);
 
 impl<T> Plugin for RouterProvider<T>
@@ -118,9 +467,11 @@ where
     T: Service<Handle = Arc<T>> + RouterBuilder + 'static,
 {
     async fn build(&self, ctx: &AppContext) -> Result<(), StdError> {
-        let component = ctx.get_component::<T::Handle>().unwrap();
+        let component = ctx
+            .get_component::<T::Handle>()
+            .ok_or_else(|| format!("Missing component: {}", type_name::<T::Handle>()))?;
         ctx.get_component_mut::<RouterRegistry>()
-            .unwrap()
+            .ok_or("RouterRegistry component is missing")?
             .add_router(component);
         Ok(())
     }
@@ -168,11 +519,7 @@ impl AddRouterExt for AppContext {
     where
         T: RouterBuilder + 'static,
     {
-        if !self.has_component::<RouterRegistry>() {
-            self.add_component(RouterRegistry::default());
-        }
-        self.get_component_mut::<RouterRegistry>()
-            .unwrap()
+        self.get_or_insert_component::<RouterRegistry>()
             .add_router(router.into());
     }
 
@@ -233,3 +580,89 @@ impl AddRouterServiceExt for AppBuilder {
         self.has_plugin::<RouterProvider<T>>()
     }
 }
+
+/// A group of routers, with the middleware and config they need, registered
+/// with a single call.
+///
+/// Large APIs can split their routers across modules, each exposing one group
+/// for [`add_router_group`](AddRouterGroupExt::add_router_group):
+///
+/// ```rust
+/// use diode::AppBuilder;
+/// use diode_http::{AddRouterServiceExt as _, RouterGroup};
+/// # use diode::Service;
+/// # use diode_http::router;
+/// # #[derive(Service)]
+/// # struct UserRouter;
+/// # #[router]
+/// # impl UserRouter {}
+/// # #[derive(Service)]
+/// # struct UserAdminRouter;
+/// # #[router]
+/// # impl UserAdminRouter {}
+///
+/// pub struct UsersApi;
+///
+/// impl RouterGroup for UsersApi {
+///     fn register(builder: &mut AppBuilder) {
+///         builder
+///             .add_router_service::<UserRouter>()
+///             .add_router_service::<UserAdminRouter>();
+///     }
+/// }
+/// ```
+pub trait RouterGroup: 'static {
+    /// Registers the routers of the group on `builder`.
+    fn register(builder: &mut AppBuilder);
+}
+
+/// Registers [`RouterGroup`]s on the application.
+pub trait AddRouterGroupExt {
+    /// Registers the routers of the group `G`.
+    ///
+    /// A group is registered at most once: adding it again, for example from
+    /// two groups that share it, does nothing. Routers are not deduplicated
+    /// across groups, so two groups registering the same router panic as with
+    /// [`add_router_service`](AddRouterServiceExt::add_router_service) unless
+    /// they guard it with
+    /// [`has_router_service`](AddRouterServiceExt::has_router_service).
+    fn add_router_group<G>(&mut self) -> &mut Self
+    where
+        G: RouterGroup;
+
+    /// Returns whether the group `G` is registered.
+    fn has_router_group<G>(&self) -> bool
+    where
+        G: RouterGroup;
+}
+
+impl AddRouterGroupExt for AppBuilder {
+    fn add_router_group<G>(&mut self) -> &mut Self
+    where
+        G: RouterGroup,
+    {
+        if self.ensure_plugin(RouterGroupMarker::<G>(PhantomData)) {
+            G::register(self);
+        }
+        self
+    }
+
+    fn has_router_group<G>(&self) -> bool
+    where
+        G: RouterGroup,
+    {
+        self.has_plugin::<RouterGroupMarker<G>>()
+    }
+}
+
+/// Records that the group `G` is registered.
+struct RouterGroupMarker<G>(PhantomData<fn() -> G>);
+
+impl<G> Plugin for RouterGroupMarker<G>
+where
+    G: RouterGroup,
+{
+    async fn build(&self, _ctx: &AppContext) -> Result<(), StdError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+use axum::Router;
+use axum::extract::MatchedPath;
+use axum::http::Method;
+
+use crate::Request;
+
+/// Authorization scopes a route requires, declared next to the route with
+/// `#[route(get, path = "/admin", scopes = ["admin"])]`.
+///
+/// The router macro adds it to the request extensions of matching requests
+/// before the router-level middleware runs, so one scope-checking middleware
+/// in `#[router(middleware = [..])]` (or on the route) can enforce the scopes
+/// of every route:
+///
+/// ```rust
+/// use axum::http::StatusCode;
+/// use diode_http::{Middleware, Next, Request, RequiredScopes, Response};
+///
+/// struct ScopeMiddleware;
+///
+/// impl Middleware for ScopeMiddleware {
+///     type Error = StatusCode;
+///
+///     async fn call(&self, request: Request, next: impl Next) -> Result<Response, StatusCode> {
+///         if let Some(required) = RequiredScopes::from_request(&request) {
+///             let granted = request
+///                 .headers()
+///                 .get("x-scopes")
+///                 .and_then(|v| v.to_str().ok())
+///                 .unwrap_or_default();
+///             if !required.is_granted(granted.split(' ')) {
+///                 return Err(StatusCode::FORBIDDEN);
+///             }
+///         }
+///         Ok(next.call(request).await)
+///     }
+/// }
+/// ```
+///
+/// Global middleware wraps the routers, so it runs before the extension is
+/// added and never sees it. Routes without `scopes` get no extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RequiredScopes(pub &'static [&'static str]);
+
+impl RequiredScopes {
+    /// Returns the scopes required by the route of `request`, if any.
+    pub fn from_request(request: &Request) -> Option<Self> {
+        request.extensions().get::<Self>().copied()
+    }
+
+    /// Returns the required scopes.
+    pub fn scopes(&self) -> &'static [&'static str] {
+        self.0
+    }
+
+    /// Checks that every required scope is among `granted`.
+    pub fn is_granted<'a>(&self, granted: impl IntoIterator<Item = &'a str>) -> bool {
+        let granted: Vec<_> = granted.into_iter().collect();
+        self.0.iter().all(|scope| granted.contains(scope))
+    }
+}
+
+/// Adds the [`RequiredScopes`] of `routes`, given as `(method, path, scopes)`
+/// with a lowercase method or `any`, to the requests they match. Used by
+/// `#[route(scopes = [..])]`.
+#[doc(hidden)]
+pub fn with_route_scopes(
+    router: Router,
+    routes: &'static [(&'static str, &'static str, &'static [&'static str])],
+) -> Router {
+    if routes.is_empty() {
+        return router;
+    }
+    router.layer(axum::middleware::map_request(
+        move |mut request: Request| async move {
+            if let Some(scopes) = find_route_scopes(routes, &request) {
+                request.extensions_mut().insert(scopes);
+            }
+            request
+        },
+    ))
+}
+
+fn find_route_scopes(
+    routes: &'static [(&'static str, &'static str, &'static [&'static str])],
+    request: &Request,
+) -> Option<RequiredScopes> {
+    let path = request.extensions().get::<MatchedPath>()?.as_str();
+    let method = request.method();
+    let find = |matches: &dyn Fn(&str) -> bool| {
+        routes
+            .iter()
+            .find(|(route_method, route_path, _)| *route_path == path && matches(route_method))
+    };
+    let (_, _, scopes) = find(&|route_method| {
+        route_method == "any" || method.as_str().eq_ignore_ascii_case(route_method)
+    })
+    // `GET` routes also answer `HEAD` requests.
+    .or_else(|| find(&|route_method| method == Method::HEAD && route_method == "get"))?;
+    Some(RequiredScopes(scopes))
+}
@@ -0,0 +1,200 @@
+use std::convert::Infallible;
+use std::marker::PhantomData;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::error_handling::HandleErrorLayer;
+use axum::extract::Request;
+use axum::http::StatusCode;
+use axum::response::Response;
+use axum::serve::{Listener, ListenerExt as _};
+use axum::{BoxError, Router};
+use diode::{App, StdError};
+use diode_base::{CancellationToken, deserialize_env_duration_option};
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
+use hyper_util::server::graceful::GracefulShutdown;
+use hyper_util::service::TowerToHyperService;
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+use tower::{ServiceBuilder, ServiceExt as _};
+
+/// TCP and HTTP connection tuning, read from the optional `http` field of
+/// [`HttpServerConfig`](crate::HttpServerConfig) and
+/// [`ControlServerConfig`](crate::ControlServerConfig).
+///
+/// ```json
+/// {
+///     "addr": "0.0.0.0:8080",
+///     "http": {
+///         "tcp_nodelay": true,
+///         "http2_keep_alive_interval": "20s",
+///         "max_concurrent_streams": 256
+///     }
+/// }
+/// ```
+///
+/// Servers accept HTTP/1 and cleartext HTTP/2 connections; the `http2_*`
+/// settings and `max_concurrent_streams` only apply to the latter.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct HttpTuning {
+    /// Sets `TCP_NODELAY` on accepted connections, disabling Nagle's
+    /// algorithm. Ignored for Unix sockets.
+    ///
+    /// Disabled by default.
+    #[serde(default)]
+    pub tcp_nodelay: bool,
+    /// Interval of HTTP/2 pings that keep idle connections alive and detect
+    /// dead peers.
+    ///
+    /// Disabled by default.
+    #[serde(default, deserialize_with = "deserialize_env_duration_option")]
+    pub http2_keep_alive_interval: Option<Duration>,
+    /// Maximum number of concurrent HTTP/2 streams per connection.
+    ///
+    /// Uses the hyper default when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_streams: Option<u32>,
+}
+
+/// Component holding an already bound TCP listener for the server daemon `D`,
+/// e.g. one passed by systemd socket activation or
+/// [`FreePort::into_listener`](diode_base::testing::FreePort::into_listener).
+///
+/// The daemon adopts the listener instead of binding its configured TCP
+/// `addr`; without the component it binds the address as usual. The listener
+/// is ignored when `addr` is a Unix socket path.
+///
+/// ```rust
+/// use diode::App;
+/// use diode_base::testing::FreePort;
+/// use diode_http::{HttpServerDaemon, HttpServerPlugin, ServerListener};
+///
+/// let (addr, listener) = FreePort::new().into_listener();
+/// let mut builder = App::builder();
+/// builder
+///     .add_plugin(HttpServerPlugin)
+///     .add_component(ServerListener::<HttpServerDaemon>::new(listener));
+/// ```
+pub struct ServerListener<D> {
+    listener: Mutex<Option<std::net::TcpListener>>,
+    _daemon: PhantomData<fn() -> D>,
+}
+
+impl<D: 'static> ServerListener<D> {
+    pub fn new(listener: std::net::TcpListener) -> Self {
+        Self {
+            listener: Mutex::new(Some(listener)),
+            _daemon: PhantomData,
+        }
+    }
+
+    /// Takes the listener registered for `D`, if any. Only the first call
+    /// gets it.
+    pub(crate) fn take(app: &App) -> Option<std::net::TcpListener> {
+        app.get_component_ref::<Self>()?
+            .listener
+            .lock()
+            .unwrap()
+            .take()
+    }
+}
+
+/// Binds a TCP listener, or adopts `prebound` if given, applying the
+/// `tcp_nodelay` setting of `tuning` to accepted connections.
+pub(crate) async fn bind_tcp(
+    addr: SocketAddr,
+    prebound: Option<std::net::TcpListener>,
+    tuning: &HttpTuning,
+) -> Result<impl Listener<Addr = SocketAddr>, StdError> {
+    let listener = match prebound {
+        Some(listener) => {
+            // Tokio requires non-blocking sockets.
+            listener.set_nonblocking(true).map_err(Box::new)?;
+            TcpListener::from_std(listener).map_err(Box::new)?
+        }
+        None => TcpListener::bind(addr).await.map_err(Box::new)?,
+    };
+    let nodelay = tuning.tcp_nodelay;
+    Ok(listener.tap_io(move |tcp| {
+        if let Err(err) = tcp.set_nodelay(nodelay) {
+            tracing::warn!(error = %err, "Failed to set TCP_NODELAY");
+        }
+    }))
+}
+
+/// Serves `router` on `listener` until `shutdown` fires, then waits for open
+/// connections to finish.
+pub(crate) async fn serve<L>(
+    listener: L,
+    router: Router,
+    max_concurrency: Option<usize>,
+    tuning: &HttpTuning,
+    shutdown: CancellationToken,
+) -> Result<(), StdError>
+where
+    L: Listener,
+{
+    match max_concurrency {
+        // Wrap the whole router: `Router::layer` would limit each route
+        // separately.
+        Some(max_concurrency) => {
+            let service = ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(|_: BoxError| async {
+                    StatusCode::SERVICE_UNAVAILABLE
+                }))
+                .load_shed()
+                .concurrency_limit(max_concurrency)
+                .service(router);
+            serve_service(listener, service, tuning, shutdown).await
+        }
+        None => serve_service(listener, router, tuning, shutdown).await,
+    }
+}
+
+async fn serve_service<L, S>(
+    mut listener: L,
+    service: S,
+    tuning: &HttpTuning,
+    shutdown: CancellationToken,
+) -> Result<(), StdError>
+where
+    L: Listener,
+    S: tower::Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    let mut http2 = builder.http2();
+    http2.timer(TokioTimer::new());
+    if let Some(interval) = tuning.http2_keep_alive_interval {
+        http2.keep_alive_interval(interval);
+    }
+    if let Some(max_concurrent_streams) = tuning.max_concurrent_streams {
+        http2.max_concurrent_streams(max_concurrent_streams);
+    }
+    let graceful = GracefulShutdown::new();
+    loop {
+        let (io, _) = tokio::select! {
+            conn = listener.accept() => conn,
+            _ = shutdown.cancelled() => break,
+        };
+        let service = service
+            .clone()
+            .map_request(|request: Request<Incoming>| request.map(Body::new));
+        let connection = builder
+            .serve_connection_with_upgrades(TokioIo::new(io), TowerToHyperService::new(service))
+            .into_owned();
+        let connection = graceful.watch(connection);
+        tokio::spawn(async move {
+            if let Err(err) = connection.await {
+                tracing::trace!(error = %err, "Failed to serve connection");
+            }
+        });
+    }
+    drop(listener);
+    graceful.shutdown().await;
+    Ok(())
+}
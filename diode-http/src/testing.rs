@@ -0,0 +1,79 @@
+//! In-memory transport for testing routers and middleware without binding a
+//! port.
+
+use axum::body::Body;
+use axum::http::Method;
+use diode::{App, StdError};
+use tower::ServiceExt as _;
+
+use crate::router::ServerLayers;
+use crate::{Request, Response, Router};
+
+/// Builds the public router of `app` and returns a client that sends requests
+/// straight to it, bypassing the network.
+///
+/// The router is built like [`HttpServerDaemon`](crate::HttpServerDaemon)
+/// builds it: the registered routers are merged and wrapped in the global
+/// middleware, panic catching and request tracing configured in the
+/// `http_server` section. The app does not need to run its daemons.
+///
+/// ```rust
+/// use diode::App;
+/// use diode_http::testing::serve_in_memory;
+/// use diode_http::{HttpServerPlugin, OptionalHttpServer};
+///
+/// # async fn example() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// let app = App::builder()
+///     .add_component(diode_base::Config::new())
+///     .add_component(OptionalHttpServer)
+///     .add_plugin(HttpServerPlugin)
+///     .build()
+///     .await?;
+/// let client = serve_in_memory(&app)?;
+/// let response = client.get("/missing").await;
+/// assert_eq!(response.status(), 404);
+/// # Ok(())
+/// # }
+/// ```
+pub fn serve_in_memory(app: &App) -> Result<TestClient, StdError> {
+    let layers = app.get_component::<ServerLayers>().unwrap_or_default();
+    Ok(TestClient {
+        router: layers.build_router(app)?,
+    })
+}
+
+/// Client returned by [`serve_in_memory`].
+#[derive(Clone)]
+pub struct TestClient {
+    router: Router,
+}
+
+impl TestClient {
+    /// Sends a `GET` request to `uri`.
+    pub async fn get(&self, uri: &str) -> Response {
+        self.request(build_request(Method::GET, uri, Body::empty()))
+            .await
+    }
+
+    /// Sends a `POST` request with `body` to `uri`.
+    pub async fn post(&self, uri: &str, body: impl Into<Body>) -> Response {
+        self.request(build_request(Method::POST, uri, body.into()))
+            .await
+    }
+
+    /// Sends `request`, e.g. one with custom headers.
+    pub async fn request(&self, request: Request) -> Response {
+        match self.router.clone().oneshot(request).await {
+            Ok(response) => response,
+            Err(err) => match err {},
+        }
+    }
+}
+
+fn build_request(method: Method, uri: &str, body: Body) -> Request {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .body(body)
+        .expect("Invalid request URI")
+}
@@ -1,8 +1,13 @@
+use std::any::Any;
+use std::convert::Infallible;
 use std::pin::Pin;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-use axum::extract::MatchedPath;
+use axum::extract::{FromRequestParts, MatchedPath};
+use axum::http::request::Parts;
+use axum::http::{HeaderName, HeaderValue, StatusCode};
+use axum::response::IntoResponse as _;
 use diode::StdError;
 use opentelemetry::propagation::TextMapPropagator;
 use opentelemetry::{
@@ -11,36 +16,163 @@ use opentelemetry::{
 };
 use opentelemetry_sdk::propagation::TraceContextPropagator;
 use tower::{Layer, Service};
+use tower_http::catch_panic::CatchPanicLayer;
 use tracing::Instrument as _;
 use tracing_opentelemetry::OpenTelemetrySpanExt as _;
 
 use crate::{Request, Response};
 
-#[derive(Clone, Copy)]
-pub(crate) struct TracingLayer;
+/// Request id of the current request, propagated through the header configured
+/// in [`HttpServerConfig::request_id_header`](crate::HttpServerConfig::request_id_header).
+///
+/// Taken from the incoming header when present and generated otherwise. It is
+/// available to handlers as a request extension.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    fn generate() -> Self {
+        Self(format!("{:032x}", rand::random::<u128>()))
+    }
+}
+
+/// Time at which the server started handling the current request.
+///
+/// Inserted as a request extension before any middleware runs, so handlers
+/// can measure their own elapsed time, e.g. to fill a `Server-Timing` header.
+/// Take it as a handler parameter; outside of the server (in tests calling a
+/// router directly) it falls back to the time of extraction.
+///
+/// ```rust
+/// use diode_http::{RequestStart, router};
+///
+/// struct SlowRouter;
+///
+/// #[router]
+/// impl SlowRouter {
+///     #[route(get, path = "/slow")]
+///     async fn slow(&self, start: RequestStart) -> String {
+///         format!("took {:?}", start.elapsed())
+///     }
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RequestStart(pub Instant);
+
+impl RequestStart {
+    /// Returns the time elapsed since the request started.
+    pub fn elapsed(&self) -> Duration {
+        self.0.elapsed()
+    }
+}
+
+impl<S> FromRequestParts<S> for RequestStart
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Infallible> {
+        Ok(parts
+            .extensions
+            .get::<RequestStart>()
+            .copied()
+            .unwrap_or_else(|| RequestStart(Instant::now())))
+    }
+}
+
+/// Turns a panic in a handler or middleware into a `500 Internal Server Error`.
+///
+/// Applied inside [`TracingLayer`], so the panic message is recorded on the
+/// request span and the response is logged like any other error.
+pub(crate) fn catch_panic_layer() -> CatchPanicLayer<fn(Box<dyn Any + Send>) -> Response> {
+    CatchPanicLayer::custom(panic_response)
+}
+
+fn panic_response(err: Box<dyn Any + Send>) -> Response {
+    let message = err
+        .downcast_ref::<String>()
+        .map(String::as_str)
+        .or_else(|| err.downcast_ref::<&str>().copied())
+        .unwrap_or("<unknown panic>");
+    let span = tracing::Span::current();
+    span.set_attribute("exception.message", message.to_string());
+    tracing::error!(panic = message, "Request handler panicked");
+    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct TracingLayer {
+    request_id_header: Option<HeaderName>,
+    server_timing: bool,
+}
+
+impl TracingLayer {
+    pub(crate) fn new(request_id_header: Option<HeaderName>) -> Self {
+        Self {
+            request_id_header,
+            server_timing: false,
+        }
+    }
+
+    /// Adds a `Server-Timing: total;dur=..` header to every response.
+    pub(crate) fn with_server_timing(mut self, server_timing: bool) -> Self {
+        self.server_timing = server_timing;
+        self
+    }
+}
 
 impl<S> Layer<S> for TracingLayer {
     type Service = TracingMiddleware<S>;
 
     fn layer(&self, inner: S) -> Self::Service {
-        TracingMiddleware { inner }
+        TracingMiddleware {
+            inner,
+            request_id_header: self.request_id_header.clone(),
+            server_timing: self.server_timing,
+        }
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub(crate) struct TracingMiddleware<S> {
     inner: S,
+    request_id_header: Option<HeaderName>,
+    server_timing: bool,
 }
 
 impl<S> TracingMiddleware<S>
 where
     S: Service<Request, Response = Response> + Send + Clone + 'static,
 {
-    async fn request(request: Request, mut inner: S) -> Result<S::Response, S::Error> {
+    async fn request(
+        mut request: Request,
+        mut inner: S,
+        request_id_header: Option<HeaderName>,
+        server_timing: bool,
+    ) -> Result<S::Response, S::Error> {
+        let start = Instant::now();
+        request.extensions_mut().insert(RequestStart(start));
         let headers = request.headers();
         let propagator = TraceContextPropagator::new();
         let parent_context = propagator.extract(&HeaderExtractor(headers));
-        let span = tracing::info_span!("request", trace_id = tracing::field::Empty);
+        let span = tracing::info_span!(
+            "request",
+            trace_id = tracing::field::Empty,
+            request_id = tracing::field::Empty,
+        );
+        let request_id = request_id_header.as_ref().map(|header| {
+            let request_id = request
+                .headers()
+                .get(header)
+                .and_then(|v| v.to_str().ok())
+                .filter(|v| !v.is_empty())
+                .map(|v| RequestId(v.to_string()))
+                .unwrap_or_else(RequestId::generate);
+            span.record("request_id", &request_id.0);
+            request.extensions_mut().insert(request_id.clone());
+            request_id
+        });
         span.set_parent(parent_context);
         span.set_attribute("otel.kind", "server");
         if let Some(path) = request.extensions().get::<MatchedPath>() {
@@ -60,9 +192,9 @@ where
         let trace_id = span.context().span().span_context().trace_id();
         span.record("trace_id", trace_id.to_string());
         tracing::info!(parent: &span, method = ?request.method(), uri = ?request.uri(), "Request");
-        let now = Instant::now();
         let mut response = inner.call(request).instrument(span.clone()).await?;
-        let latency = now.elapsed().as_micros();
+        let elapsed = start.elapsed();
+        let latency = elapsed.as_micros();
         let status = response.status();
         span.set_attribute("http.status_code", status.as_u16() as i64);
         if let Some(error) = response.extensions().get::<Arc<StdError>>() {
@@ -79,6 +211,17 @@ where
         response
             .headers_mut()
             .insert("X-Trace-Id", trace_id.to_string().parse().unwrap());
+        if let (Some(header), Some(request_id)) = (request_id_header, request_id)
+            && let Ok(value) = HeaderValue::from_str(&request_id.0)
+        {
+            response.headers_mut().insert(header, value);
+        }
+        if server_timing
+            && let Ok(value) =
+                HeaderValue::from_str(&format!("total;dur={:.3}", elapsed.as_secs_f64() * 1000.0))
+        {
+            response.headers_mut().append("Server-Timing", value);
+        }
         Ok(response)
     }
 }
@@ -103,7 +246,11 @@ where
     fn call(&mut self, request: Request) -> Self::Future {
         let clone = self.inner.clone();
         let inner = std::mem::replace(&mut self.inner, clone);
-        Box::pin(async move { Self::request(request, inner).await })
+        let request_id_header = self.request_id_header.clone();
+        let server_timing = self.server_timing;
+        Box::pin(
+            async move { Self::request(request, inner, request_id_header, server_timing).await },
+        )
     }
 }
 
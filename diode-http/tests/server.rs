@@ -1,20 +1,27 @@
+use std::collections::BTreeMap;
 use std::convert::Infallible;
 use std::sync::Arc;
 
+use axum::Extension;
 use axum::http::status::StatusCode;
 use axum::response::IntoResponse;
 use diode_base::testing::FreePort;
 use reqwest_middleware::ClientBuilder;
 use reqwest_retry::{RetryTransientMiddleware, policies::ExponentialBackoff};
+use serde_json::json;
 
 use diode::{App, Service};
-use diode_base::{CancellationToken, Config, RunDaemonsExt as _};
+use diode_base::{
+    AddDynamicConfigExt as _, CancellationToken, Config, DynamicConfig, DynamicConfigConfig,
+    DynamicConfigService, RunDaemonsExt as _,
+};
 use diode_http::{
+    BodyLogMiddleware, AddRouterGroupExt as _, RouterGroup, RequestStart,
     AddControlRouterServiceExt as _, AddHealthCheckExt, AddHealthCheckServiceExt as _,
     AddMiddlewareExt, AddMiddlewareServiceExt as _, AddRouterExt, AddRouterServiceExt as _,
-    ControlServerConfig, ControlServerPlugin, HealthCheck, HealthClient, HealthRouter,
-    HttpServerConfig, HttpServerPlugin, Middleware, Next, Request, Response, Router,
-    RouterBuilder, router, routing,
+    ControlServerConfig, ControlServerPlugin, DynamicConfigAdminRouter, DynamicConfigRouter, HealthCheck, HealthClient, HealthConfig, HealthRouter,
+    HttpServerConfig, HttpServerDaemon, HttpServerPlugin, Middleware, MiddlewareOrder, MiddlewareTiming, Next, OptionalHttpServer, PingHandler, Request,
+    ContentNegotiated, ContentNegotiationMiddleware, RequiredScopes, ResponseFormat, RequestContext, RequestContextMiddleware, RequestId, Response, Router, RouterBuilder, RouterReloader, ServerListener, SseEvent, router, routing,
 };
 
 #[derive(Service)]
@@ -79,12 +86,9 @@ async fn test_example_router_and_middleware() {
         .add_router_service::<ExampleRouter>()
         .add_middleware_service::<AuthMiddleware>()
         .add_middleware_service::<ReqIdMiddleware>()
-        .add_component(Config::new().with(
-            "http_server",
-            HttpServerConfig {
-                addr: server_port.as_addr(),
-            },
-        ))
+        .add_component(
+            Config::new().with("http_server", HttpServerConfig::new(server_port.as_addr())),
+        )
         .build()
         .await
         .unwrap();
@@ -148,9 +152,7 @@ async fn test_service_server() {
         .add_control_router_service::<HealthRouter>()
         .add_component(Config::new().with(
             "control_server",
-            ControlServerConfig {
-                addr: server_port.as_addr(),
-            },
+            ControlServerConfig::new(server_port.as_addr()),
         ))
         .build()
         .await
@@ -205,9 +207,7 @@ async fn test_unhealthy_service() {
         .add_health_check_service::<BadHealthCheckService>()
         .add_component(Config::new().with(
             "control_server",
-            ControlServerConfig {
-                addr: server_port.as_addr(),
-            },
+            ControlServerConfig::new(server_port.as_addr()),
         ))
         .build()
         .await
@@ -247,11 +247,11 @@ struct GreetRouter {
 }
 
 impl RouterBuilder for GreetRouter {
-    fn build_router(self: Arc<Self>, _app: &App) -> Router {
-        Router::new().route(
+    fn build_router(self: Arc<Self>, _app: &App) -> Result<Router, diode::StdError> {
+        Ok(Router::new().route(
             "/greet",
             routing::get(move || async move { self.greeting.clone() }),
-        )
+        ))
     }
 }
 
@@ -260,14 +260,9 @@ async fn test_router_instance() {
     let server_port = FreePort::new();
 
     let mut builder = App::builder();
-    builder
-        .add_plugin(HttpServerPlugin)
-        .add_component(Config::new().with(
-            "http_server",
-            HttpServerConfig {
-                addr: server_port.as_addr(),
-            },
-        ));
+    builder.add_plugin(HttpServerPlugin).add_component(
+        Config::new().with("http_server", HttpServerConfig::new(server_port.as_addr())),
+    );
     builder.add_router(GreetRouter {
         greeting: "hi there".to_string(),
     });
@@ -299,6 +294,94 @@ async fn test_router_instance() {
     let _ = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task).await;
 }
 
+/// Serves `/beta` only while its flag is set.
+struct BetaRouter {
+    enabled: Arc<std::sync::atomic::AtomicBool>,
+}
+
+impl RouterBuilder for BetaRouter {
+    fn build_router(self: Arc<Self>, _app: &App) -> Result<Router, diode::StdError> {
+        let router = Router::new().route("/stable", routing::get(|| async { "stable" }));
+        if !self.enabled.load(std::sync::atomic::Ordering::SeqCst) {
+            return Ok(router);
+        }
+        Ok(router.route("/beta", routing::get(|| async { "beta" })))
+    }
+}
+
+#[tokio::test]
+async fn test_router_reload() {
+    let server_port = FreePort::new();
+    let enabled = Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+    let mut builder = App::builder();
+    builder
+        .add_plugin(HttpServerPlugin)
+        .add_component(RouterReloader::new())
+        .add_component(
+            Config::new().with("http_server", HttpServerConfig::new(server_port.as_addr())),
+        );
+    builder.add_router(BetaRouter {
+        enabled: enabled.clone(),
+    });
+    let app = builder.build().await.unwrap();
+    let reloader = app.get_component::<RouterReloader>().unwrap();
+
+    let shutdown = CancellationToken::new();
+    let shutdown_clone = shutdown.clone();
+    let server_task = tokio::spawn(async move { app.run_daemons(shutdown_clone).await });
+
+    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
+    let client = ClientBuilder::new(reqwest::Client::new())
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .build();
+    let base_url = format!("http://{}", server_port.as_addr());
+
+    let response = client
+        .get(format!("{base_url}/stable"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    let response = client
+        .get(format!("{base_url}/beta"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 404);
+
+    enabled.store(true, std::sync::atomic::Ordering::SeqCst);
+    reloader.reload();
+    // The server rebuilds the routers in the background.
+    let mut status = 0;
+    for _ in 0..50 {
+        let response = client
+            .get(format!("{base_url}/beta"))
+            .send()
+            .await
+            .expect("Failed to send request");
+        status = response.status().as_u16();
+        if status == 200 {
+            assert_eq!(response.text().await.unwrap(), "beta");
+            break;
+        }
+        tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(status, 200);
+    let response = client
+        .get(format!("{base_url}/stable"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+
+    shutdown.cancel();
+    let result = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task)
+        .await
+        .expect("Server did not stop");
+    assert!(result.unwrap().is_ok());
+}
+
 struct FailingHealthCheck {
     name: String,
     message: String,
@@ -324,9 +407,7 @@ async fn test_unhealthy_instance() {
         .add_control_router_service::<HealthRouter>()
         .add_component(Config::new().with(
             "control_server",
-            ControlServerConfig {
-                addr: server_port.as_addr(),
-            },
+            ControlServerConfig::new(server_port.as_addr()),
         ));
     builder.add_health_check(FailingHealthCheck {
         name: "disk".to_string(),
@@ -369,9 +450,7 @@ async fn test_health_client() {
         .add_control_router_service::<HealthRouter>()
         .add_component(Config::new().with(
             "control_server",
-            ControlServerConfig {
-                addr: server_port.as_addr(),
-            },
+            ControlServerConfig::new(server_port.as_addr()),
         ))
         .build()
         .await
@@ -411,6 +490,219 @@ async fn test_health_client() {
     let _ = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task).await;
 }
 
+#[tokio::test]
+async fn test_health_config_paths() {
+    let server_port = FreePort::new();
+
+    let app = App::builder()
+        .add_plugin(ControlServerPlugin)
+        .add_control_router_service::<HealthRouter>()
+        .add_control_router_service::<PingHandler>()
+        .add_component(
+            Config::new()
+                .with(
+                    "control_server",
+                    ControlServerConfig::new(server_port.as_addr()),
+                )
+                .with(
+                    "health",
+                    HealthConfig {
+                        path: "/healthz".to_string(),
+                        ping_path: "/livez".to_string(),
+                    },
+                ),
+        )
+        .build()
+        .await
+        .unwrap();
+    let health_client = app.get_component::<HealthClient>().unwrap();
+
+    let shutdown = CancellationToken::new();
+    let shutdown_clone = shutdown.clone();
+    let server_task = tokio::spawn(async move { app.run_daemons(shutdown_clone).await });
+
+    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
+    let client = ClientBuilder::new(reqwest::Client::new())
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .build();
+    let base_url = format!("http://{}", server_port.as_addr());
+
+    let response = client
+        .get(format!("{base_url}/healthz"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await.unwrap(), "healthy");
+    let response = client
+        .get(format!("{base_url}/livez"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.text().await.unwrap(), "pong");
+    let response = client
+        .get(format!("{base_url}/health"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 404);
+    // The control server's HealthClient follows the configured path.
+    health_client.health_check().await.unwrap();
+
+    shutdown.cancel();
+    let _ = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task).await;
+}
+
+#[tokio::test]
+async fn test_health_config_invalid_path() {
+    let result = App::builder()
+        .add_control_router_service::<PingHandler>()
+        .add_component(Config::new().with("health", json!({"ping_path": "ping"})))
+        .build()
+        .await;
+    let err = result.err().unwrap().to_string();
+    assert!(err.contains("Invalid health endpoint path"), "{err}");
+}
+
+#[tokio::test]
+async fn test_health_client_timeout_and_headers() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    // Hangs unless the request is authorized.
+    let router = Router::new().route(
+        "/health",
+        routing::get(|headers: axum::http::HeaderMap| async move {
+            if headers.get("authorization").is_none() {
+                tokio::time::sleep(std::time::Duration::from_secs(30)).await;
+            }
+            "ok"
+        }),
+    );
+    let server_task = tokio::spawn(async move { axum::serve(listener, router).await });
+    let endpoint = format!("http://{addr}/health");
+
+    let start = std::time::Instant::now();
+    let result = HealthClient::new(endpoint.clone())
+        .with_timeout(std::time::Duration::from_millis(200))
+        .health_check()
+        .await;
+    assert!(result.is_err());
+    assert!(start.elapsed() < std::time::Duration::from_secs(5));
+
+    let start = std::time::Instant::now();
+    let result = HealthClient::new(endpoint.clone())
+        .wait_for_ready(std::time::Duration::from_millis(300))
+        .await;
+    assert!(result.is_err());
+    assert!(start.elapsed() < std::time::Duration::from_secs(5));
+
+    let result = HealthClient::new(endpoint)
+        .with_header(
+            axum::http::header::AUTHORIZATION,
+            axum::http::HeaderValue::from_static("Bearer token"),
+        )
+        .health_check()
+        .await;
+    assert!(result.is_ok(), "{result:?}");
+
+    server_task.abort();
+}
+
+struct SlowRouter;
+
+impl RouterBuilder for SlowRouter {
+    fn build_router(self: Arc<Self>, _app: &App) -> Result<Router, diode::StdError> {
+        Ok(Router::new()
+            .route("/fast", routing::get(|| async { "fast" }))
+            .route(
+                "/slow",
+                routing::get(|| async {
+                    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                    "slow"
+                }),
+            ))
+    }
+}
+
+#[tokio::test]
+async fn test_max_concurrency() {
+    let server_port = FreePort::new();
+
+    let mut builder = App::builder();
+    builder
+        .add_plugin(HttpServerPlugin)
+        .add_component(Config::new().with(
+            "http_server",
+            HttpServerConfig {
+                max_concurrency: Some(2),
+                ..HttpServerConfig::new(server_port.as_addr())
+            },
+        ));
+    builder.add_router(SlowRouter);
+    let app = builder.build().await.unwrap();
+
+    let shutdown = CancellationToken::new();
+    let shutdown_clone = shutdown.clone();
+    let server_task = tokio::spawn(async move { app.run_daemons(shutdown_clone).await });
+
+    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
+    let ready_client = ClientBuilder::new(reqwest::Client::new())
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .build();
+    let base_url = format!("http://{}", server_port.as_addr());
+    let response = ready_client
+        .get(format!("{base_url}/fast"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+
+    // Plain client: a retrying one would retry the 503.
+    let client = reqwest::Client::new();
+    let requests = (0..3).map(|_| client.get(format!("{base_url}/slow")).send());
+    let mut statuses: Vec<u16> = futures::future::join_all(requests)
+        .await
+        .into_iter()
+        .map(|v| v.expect("Failed to send request").status().as_u16())
+        .collect();
+    statuses.sort_unstable();
+    assert_eq!(statuses, [200, 200, 503]);
+
+    // Permits are released once requests finish.
+    let response = client.get(format!("{base_url}/fast")).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+
+    shutdown.cancel();
+    let _ = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task).await;
+}
+
+#[tokio::test]
+async fn test_max_concurrency_zero() {
+    let result = App::builder()
+        .add_plugin(HttpServerPlugin)
+        .add_component(Config::new().with(
+            "http_server",
+            HttpServerConfig {
+                max_concurrency: Some(0),
+                ..HttpServerConfig::new("127.0.0.1:0".parse::<std::net::SocketAddr>().unwrap())
+            },
+        ))
+        .build()
+        .await;
+    let err = result.err().unwrap();
+    assert!(err.to_string().contains("Invalid max_concurrency"), "{err}");
+    assert!(
+        matches!(
+            &err,
+            diode::AppError::PluginFailed {
+                plugin,
+                error: diode::PluginError::Misconfiguration(_),
+            } if plugin.ends_with("HttpServerPlugin")
+        ),
+        "{err:?}"
+    );
+}
+
 #[test]
 fn test_router_unique_by_type() {
     let builder = App::builder();
@@ -435,6 +727,9 @@ fn test_duplicate_router_panics() {
 
 macro_rules! order_middleware {
     ($name:ident, $tag:literal) => {
+        order_middleware!($name, $tag, MiddlewareOrder::new());
+    };
+    ($name:ident, $tag:literal, $order:expr) => {
         #[derive(Service)]
         struct $name;
 
@@ -452,6 +747,10 @@ macro_rules! order_middleware {
                     .append("X-Order", $tag.parse().unwrap());
                 Ok(response)
             }
+
+            fn order() -> MiddlewareOrder {
+                $order
+            }
         }
     };
 }
@@ -484,12 +783,9 @@ async fn test_middleware_order() {
         .add_middleware_service::<MwB>()
         .add_middleware_service::<MwC>()
         .add_middleware_service::<MwD>()
-        .add_component(Config::new().with(
-            "http_server",
-            HttpServerConfig {
-                addr: server_port.as_addr(),
-            },
-        ));
+        .add_component(
+            Config::new().with("http_server", HttpServerConfig::new(server_port.as_addr())),
+        );
     let app = builder.build().await.unwrap();
 
     let shutdown = CancellationToken::new();
@@ -528,6 +824,112 @@ async fn test_middleware_order() {
     let _ = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task).await;
 }
 
+order_middleware!(MwE, "E", MiddlewareOrder::new().before::<MwF>());
+order_middleware!(MwF, "F");
+order_middleware!(MwG, "G", MiddlewareOrder::new().after::<MwH>());
+order_middleware!(MwH, "H", MiddlewareOrder::new().before::<MwA>());
+
+#[derive(Service)]
+struct DeclaredOrderRouter;
+
+// Declared lists are reversed by the ordering constraints. `MwH` asks to run
+// before `MwA`, which is not in its list, so that constraint is ignored.
+#[router(middleware = [MwF, MwE])]
+impl DeclaredOrderRouter {
+    #[route(get, path = "/declared-order", middleware = [MwG, MwH])]
+    async fn declared_order(&self) -> String {
+        "ok".to_string()
+    }
+}
+
+#[tokio::test]
+async fn test_middleware_declared_order() {
+    let server_port = FreePort::new();
+
+    let mut builder = App::builder();
+    builder
+        .add_plugin(HttpServerPlugin)
+        .add_router_service::<DeclaredOrderRouter>()
+        .add_middleware_service::<MwE>()
+        .add_middleware_service::<MwF>()
+        .add_middleware_service::<MwG>()
+        .add_middleware_service::<MwH>()
+        .add_component(
+            Config::new().with("http_server", HttpServerConfig::new(server_port.as_addr())),
+        );
+    let app = builder.build().await.unwrap();
+
+    let shutdown = CancellationToken::new();
+    let shutdown_clone = shutdown.clone();
+    let server_task = tokio::spawn(async move { app.run_daemons(shutdown_clone).await });
+
+    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
+    let client = ClientBuilder::new(reqwest::Client::new())
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .build();
+    let base_url = format!("http://{}", server_port.as_addr());
+
+    let response = client
+        .get(format!("{base_url}/declared-order"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+
+    // Nesting (outer -> inner) is MwE -> MwF -> MwH -> MwG -> handler.
+    let order: Vec<String> = response
+        .headers()
+        .get_all("x-order")
+        .iter()
+        .map(|v| v.to_str().unwrap().to_string())
+        .collect();
+    assert_eq!(order, ["G", "H", "F", "E"]);
+
+    shutdown.cancel();
+    let _ = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task).await;
+}
+
+order_middleware!(MwI, "I", MiddlewareOrder::new().before::<MwJ>());
+order_middleware!(MwJ, "J", MiddlewareOrder::new().before::<MwI>());
+
+#[derive(Service)]
+struct CycleOrderRouter;
+
+#[router(middleware = [MwI, MwJ])]
+impl CycleOrderRouter {
+    #[route(get, path = "/cycle-order")]
+    async fn cycle_order(&self) -> &'static str {
+        "unreachable"
+    }
+}
+
+#[tokio::test]
+async fn test_middleware_order_cycle() {
+    let server_port = FreePort::new();
+
+    // The app build fails instead of the server daemon.
+    let err = App::builder()
+        .add_plugin(HttpServerPlugin)
+        .add_router_service::<CycleOrderRouter>()
+        .add_middleware_service::<MwI>()
+        .add_middleware_service::<MwJ>()
+        .add_component(
+            Config::new().with("http_server", HttpServerConfig::new(server_port.as_addr())),
+        )
+        .build()
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "Plugin error: Middleware ordering cycle between {}, {}",
+            std::any::type_name::<MwI>(),
+            std::any::type_name::<MwJ>()
+        )
+    );
+}
+
 // A stateful middleware that is NOT a `Service` - it is registered as a concrete
 // instance, exercising the instance form of `add_middleware`.
 struct ValueHeaderMiddleware {
@@ -565,12 +967,9 @@ async fn test_middleware_instance() {
     builder
         .add_plugin(HttpServerPlugin)
         .add_router_service::<InstanceMwRouter>()
-        .add_component(Config::new().with(
-            "http_server",
-            HttpServerConfig {
-                addr: server_port.as_addr(),
-            },
-        ));
+        .add_component(
+            Config::new().with("http_server", HttpServerConfig::new(server_port.as_addr())),
+        );
     builder.add_middleware(ValueHeaderMiddleware {
         value: "from-instance".to_string(),
     });
@@ -605,3 +1004,1755 @@ async fn test_middleware_instance() {
     shutdown.cancel();
     let _ = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task).await;
 }
+
+#[tokio::test]
+async fn test_global_middleware() {
+    let server_port = FreePort::new();
+    let control_port = FreePort::new();
+
+    let mut builder = App::builder();
+    builder
+        .add_plugin(HttpServerPlugin)
+        .add_plugin(ControlServerPlugin)
+        .add_router_service::<OrderRouter>()
+        .add_control_router_service::<HealthRouter>()
+        .add_middleware_service::<MwA>()
+        .add_middleware_service::<MwB>()
+        .add_middleware_service::<MwC>()
+        .add_middleware_service::<MwD>()
+        .add_component(
+            Config::new()
+                .with("http_server", HttpServerConfig::new(server_port.as_addr()))
+                .with(
+                    "control_server",
+                    ControlServerConfig::new(control_port.as_addr()),
+                ),
+        );
+    builder.add_middleware(ValueHeaderMiddleware {
+        value: "global".to_string(),
+    });
+    builder.add_global_middleware::<ValueHeaderMiddleware>();
+    assert!(builder.has_global_middleware::<ValueHeaderMiddleware>());
+    assert!(!builder.has_global_middleware::<MwA>());
+    let app = builder.build().await.unwrap();
+
+    let shutdown = CancellationToken::new();
+    let shutdown_clone = shutdown.clone();
+    let server_task = tokio::spawn(async move { app.run_daemons(shutdown_clone).await });
+
+    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
+    let client = ClientBuilder::new(reqwest::Client::new())
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .build();
+
+    let response = client
+        .get(format!("http://{}/order", server_port.as_addr()))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers().get("X-Custom").unwrap(), "global");
+    // Router-level middleware still applies, inside the global one.
+    let order: Vec<String> = response
+        .headers()
+        .get_all("x-order")
+        .iter()
+        .map(|v| v.to_str().unwrap().to_string())
+        .collect();
+    assert_eq!(order, ["D", "C", "B", "A"]);
+
+    let response = client
+        .get(format!("http://{}/health", control_port.as_addr()))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers().get("X-Custom").unwrap(), "global");
+
+    shutdown.cancel();
+    let _ = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task).await;
+}
+
+#[tokio::test]
+async fn test_control_middleware() {
+    let server_port = FreePort::new();
+    let control_port = FreePort::new();
+
+    let mut builder = App::builder();
+    builder
+        .add_plugin(HttpServerPlugin)
+        .add_plugin(ControlServerPlugin)
+        .add_router_service::<OrderRouter>()
+        .add_control_router_service::<HealthRouter>()
+        .add_middleware_service::<MwA>()
+        .add_middleware_service::<MwB>()
+        .add_middleware_service::<MwC>()
+        .add_middleware_service::<MwD>()
+        .add_middleware_service::<AuthMiddleware>()
+        .add_component(
+            Config::new()
+                .with("http_server", HttpServerConfig::new(server_port.as_addr()))
+                .with(
+                    "control_server",
+                    ControlServerConfig::new(control_port.as_addr()),
+                ),
+        );
+    builder.add_control_middleware::<AuthMiddleware>();
+    assert!(builder.has_control_middleware::<AuthMiddleware>());
+    assert!(!builder.has_global_middleware::<AuthMiddleware>());
+    let app = builder.build().await.unwrap();
+
+    let shutdown = CancellationToken::new();
+    let shutdown_clone = shutdown.clone();
+    let server_task = tokio::spawn(async move { app.run_daemons(shutdown_clone).await });
+
+    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
+    let client = ClientBuilder::new(reqwest::Client::new())
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .build();
+
+    // The public server is not affected.
+    let response = client
+        .get(format!("http://{}/order", server_port.as_addr()))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+
+    let response = client
+        .get(format!("http://{}/health", control_port.as_addr()))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 401);
+
+    let response = client
+        .get(format!("http://{}/health", control_port.as_addr()))
+        .header("Authorization", "password")
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+
+    shutdown.cancel();
+    let _ = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task).await;
+}
+
+/// Collects the `middleware` field of every "Middleware finished" event.
+#[derive(Clone, Default)]
+struct MiddlewareTimingCollector(Arc<std::sync::Mutex<Vec<String>>>);
+
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for MiddlewareTimingCollector {
+    fn on_event(
+        &self,
+        event: &tracing::Event<'_>,
+        _ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        struct Visitor(Option<String>);
+
+        impl tracing::field::Visit for Visitor {
+            fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+                if field.name() == "middleware" {
+                    self.0 = Some(value.to_string());
+                }
+            }
+
+            fn record_debug(&mut self, _: &tracing::field::Field, _: &dyn std::fmt::Debug) {}
+        }
+
+        let mut visitor = Visitor(None);
+        event.record(&mut visitor);
+        if let Some(name) = visitor.0 {
+            self.0.lock().unwrap().push(name);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_middleware_timing() {
+    use tracing_subscriber::layer::SubscriberExt as _;
+
+    let collector = MiddlewareTimingCollector::default();
+    let _guard =
+        tracing::subscriber::set_default(tracing_subscriber::registry().with(collector.clone()));
+    let server_port = FreePort::new();
+
+    let mut builder = App::builder();
+    builder
+        .add_plugin(HttpServerPlugin)
+        .add_router_service::<OrderRouter>()
+        .add_middleware_service::<MwA>()
+        .add_middleware_service::<MwB>()
+        .add_middleware_service::<MwC>()
+        .add_middleware_service::<MwD>()
+        .add_component(Config::new().with(
+            "http_server",
+            HttpServerConfig {
+                middleware_timing: true,
+                ..HttpServerConfig::new(server_port.as_addr())
+            },
+        ));
+    let app = builder.build().await.unwrap();
+    assert!(app.has_component::<MiddlewareTiming>());
+
+    let shutdown = CancellationToken::new();
+    let shutdown_clone = shutdown.clone();
+    let server_task = tokio::spawn(async move { app.run_daemons(shutdown_clone).await });
+
+    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
+    let client = ClientBuilder::new(reqwest::Client::new())
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .build();
+    let response = client
+        .get(format!("http://{}/order", server_port.as_addr()))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+
+    // Inner middleware finishes first.
+    let names = collector.0.lock().unwrap().clone();
+    let expected = [
+        std::any::type_name::<MwD>(),
+        std::any::type_name::<MwC>(),
+        std::any::type_name::<MwB>(),
+        std::any::type_name::<MwA>(),
+    ];
+    assert_eq!(names, expected);
+
+    shutdown.cancel();
+    let _ = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task).await;
+}
+
+#[derive(Service)]
+pub struct RejectingMiddleware;
+
+impl Middleware for RejectingMiddleware {
+    type Error = StatusCode;
+
+    async fn call(&self, request: Request, _next: impl Next) -> Result<Response, StatusCode> {
+        let status = request
+            .headers()
+            .get("X-Reject-Status")
+            .and_then(|v| StatusCode::from_bytes(v.as_bytes()).ok())
+            .unwrap_or(StatusCode::FORBIDDEN);
+        Err(status)
+    }
+}
+
+struct RejectingRouter;
+
+#[router(middleware = [RejectingMiddleware])]
+impl RejectingRouter {
+    #[route(get, path = "/rejected")]
+    async fn rejected(&self) -> String {
+        "unreachable".to_string()
+    }
+}
+
+impl Service for RejectingRouter {
+    type Handle = Arc<Self>;
+
+    async fn build(_ctx: &diode::AppContext) -> Result<Self::Handle, diode::StdError> {
+        Ok(Arc::new(Self))
+    }
+}
+
+/// Collects the `error` field of every "Response error" event.
+#[derive(Clone, Default)]
+struct ResponseErrorCollector(Arc<std::sync::Mutex<Vec<String>>>);
+
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for ResponseErrorCollector {
+    fn on_event(
+        &self,
+        event: &tracing::Event<'_>,
+        _ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        #[derive(Default)]
+        struct Visitor {
+            message: String,
+            error: Option<String>,
+        }
+
+        impl tracing::field::Visit for Visitor {
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                match field.name() {
+                    "message" => self.message = format!("{value:?}"),
+                    "error" => self.error = Some(format!("{value:?}")),
+                    _ => {}
+                }
+            }
+        }
+
+        let mut visitor = Visitor::default();
+        event.record(&mut visitor);
+        if visitor.message == "Response error" {
+            self.0.lock().unwrap().extend(visitor.error);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_middleware_error_logged() {
+    use tracing_subscriber::layer::SubscriberExt as _;
+
+    let collector = ResponseErrorCollector::default();
+    let _guard =
+        tracing::subscriber::set_default(tracing_subscriber::registry().with(collector.clone()));
+    let server_port = FreePort::new();
+
+    let app = App::builder()
+        .add_plugin(HttpServerPlugin)
+        .add_router_service::<RejectingRouter>()
+        .add_middleware_service::<RejectingMiddleware>()
+        .add_component(
+            Config::new().with("http_server", HttpServerConfig::new(server_port.as_addr())),
+        )
+        .build()
+        .await
+        .unwrap();
+
+    let shutdown = CancellationToken::new();
+    let shutdown_clone = shutdown.clone();
+    let server_task = tokio::spawn(async move { app.run_daemons(shutdown_clone).await });
+
+    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
+    let client = ClientBuilder::new(reqwest::Client::new())
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .build();
+    // Wait for the server with a 4xx, which is neither retried nor logged.
+    let response = client
+        .get(format!("http://{}/rejected", server_port.as_addr()))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 403);
+    let response = reqwest::Client::new()
+        .get(format!("http://{}/rejected", server_port.as_addr()))
+        .header("X-Reject-Status", "503")
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 503);
+
+    let errors = collector.0.lock().unwrap().clone();
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].contains(std::any::type_name::<RejectingMiddleware>()));
+    assert!(errors[0].contains("503"));
+
+    shutdown.cancel();
+    let _ = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task).await;
+}
+
+/// Collects the message of every error-level event.
+#[derive(Clone, Default)]
+struct ErrorEventCollector(Arc<std::sync::Mutex<Vec<String>>>);
+
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for ErrorEventCollector {
+    fn on_event(
+        &self,
+        event: &tracing::Event<'_>,
+        _ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        #[derive(Default)]
+        struct Visitor(String);
+
+        impl tracing::field::Visit for Visitor {
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                if field.name() == "message" {
+                    self.0 = format!("{value:?}");
+                }
+            }
+        }
+
+        if *event.metadata().level() == tracing::Level::ERROR {
+            let mut visitor = Visitor::default();
+            event.record(&mut visitor);
+            self.0.lock().unwrap().push(visitor.0);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_middleware_rejection_not_logged_as_error() {
+    use tracing_subscriber::layer::SubscriberExt as _;
+
+    let collector = ErrorEventCollector::default();
+    let _guard =
+        tracing::subscriber::set_default(tracing_subscriber::registry().with(collector.clone()));
+    let server_port = FreePort::new();
+
+    let app = App::builder()
+        .add_plugin(HttpServerPlugin)
+        .add_router_service::<RejectingRouter>()
+        .add_middleware_service::<RejectingMiddleware>()
+        .add_component(
+            Config::new().with("http_server", HttpServerConfig::new(server_port.as_addr())),
+        )
+        .build()
+        .await
+        .unwrap();
+
+    let shutdown = CancellationToken::new();
+    let shutdown_clone = shutdown.clone();
+    let server_task = tokio::spawn(async move { app.run_daemons(shutdown_clone).await });
+
+    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
+    let client = ClientBuilder::new(reqwest::Client::new())
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .build();
+    let response = client
+        .get(format!("http://{}/rejected", server_port.as_addr()))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 403);
+
+    // A routine 4xx rejection is logged as a warning, not as an error.
+    let errors = collector.0.lock().unwrap().clone();
+    assert!(errors.is_empty(), "unexpected error events: {errors:?}");
+
+    shutdown.cancel();
+    let _ = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task).await;
+}
+
+#[derive(Service)]
+struct StaticDynamicConfig;
+
+impl DynamicConfigService for StaticDynamicConfig {
+    async fn get_snapshot(
+        &self,
+    ) -> Result<BTreeMap<String, serde_json::Value>, diode::StdError> {
+        Ok(BTreeMap::from([("feature".to_string(), json!(true))]))
+    }
+}
+
+#[tokio::test]
+async fn test_dynamic_config_router() {
+    let server_port = FreePort::new();
+    let fallback_path = std::env::temp_dir().join(format!(
+        "diode-http-dynamic-config-{}.json",
+        server_port.as_addr().port()
+    ));
+    std::fs::write(&fallback_path, r#"{"feature": false, "limit": 10}"#).unwrap();
+
+    let app = App::builder()
+        .add_plugin(ControlServerPlugin)
+        .add_control_router_service::<DynamicConfigRouter>()
+        .add_dynamic_config::<StaticDynamicConfig>()
+        .add_component(
+            Config::new()
+                .with(
+                    "control_server",
+                    ControlServerConfig::new(server_port.as_addr()),
+                )
+                .with(
+                    "dynamic_config",
+                    DynamicConfigConfig {
+                        fallback_path: Some(fallback_path.clone()),
+                        ..Default::default()
+                    },
+                ),
+        )
+        .build()
+        .await
+        .unwrap();
+    std::fs::remove_file(&fallback_path).unwrap();
+
+    let shutdown = CancellationToken::new();
+    let shutdown_clone = shutdown.clone();
+    let server_task = tokio::spawn(async move { app.run_daemons(shutdown_clone).await });
+
+    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
+    let client = ClientBuilder::new(reqwest::Client::new())
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .build();
+    let base_url = format!("http://{}", server_port.as_addr());
+
+    let response = client
+        .get(format!("{base_url}/debug/dynamic-config"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body,
+        json!({
+            "values": {"feature": true, "limit": 10},
+            "sources": {"feature": "cache", "limit": "fallback"},
+        })
+    );
+
+    shutdown.cancel();
+    let _ = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task).await;
+}
+
+#[tokio::test]
+async fn test_dynamic_config_admin_router() {
+    let server_port = FreePort::new();
+    let app = App::builder()
+        .add_plugin(ControlServerPlugin)
+        .add_middleware_service::<AuthMiddleware>()
+        .add_control_router_service::<DynamicConfigAdminRouter<AuthMiddleware>>()
+        .add_dynamic_config::<StaticDynamicConfig>()
+        .add_component(Config::new().with(
+            "control_server",
+            ControlServerConfig::new(server_port.as_addr()),
+        ))
+        .build()
+        .await
+        .unwrap();
+    let dynamic_config = app.get_component::<Arc<DynamicConfig>>().unwrap();
+
+    let shutdown = CancellationToken::new();
+    let shutdown_clone = shutdown.clone();
+    let server_task = tokio::spawn(async move { app.run_daemons(shutdown_clone).await });
+
+    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
+    let client = ClientBuilder::new(reqwest::Client::new())
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .build();
+    let base_url = format!("http://{}", server_port.as_addr());
+
+    let response = client
+        .put(format!("{base_url}/admin/config/limit"))
+        .header("Content-Type", "application/json")
+        .body("20")
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 401);
+    assert_eq!(dynamic_config.get::<i64>("limit"), None);
+
+    let response = client
+        .put(format!("{base_url}/admin/config/limit"))
+        .header("Authorization", "password")
+        .header("Content-Type", "application/json")
+        .body("20")
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 204);
+    assert_eq!(dynamic_config.get::<i64>("limit"), Some(20));
+
+    let response = client
+        .delete(format!("{base_url}/admin/config/feature"))
+        .header("Authorization", "password")
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 204);
+    assert_eq!(
+        dynamic_config.snapshot(),
+        BTreeMap::from([("limit".to_string(), json!(20))])
+    );
+
+    shutdown.cancel();
+    let _ = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task).await;
+}
+
+#[derive(serde::Deserialize)]
+struct Filters {
+    prefix: String,
+}
+
+#[derive(serde::Deserialize)]
+struct CreateItem {
+    name: String,
+}
+
+#[derive(Service)]
+struct ExtractRouter;
+
+#[router]
+impl ExtractRouter {
+    #[route(get, path = "/items")]
+    async fn list(&self, #[query] filters: Filters) -> String {
+        format!("{}-list", filters.prefix)
+    }
+
+    #[route(post, path = "/items")]
+    async fn create(&self, #[query] filters: Filters, #[json] item: CreateItem) -> String {
+        format!("{}-{}", filters.prefix, item.name)
+    }
+}
+
+#[tokio::test]
+async fn test_route_query_and_json_params() {
+    let server_port = FreePort::new();
+
+    let app = App::builder()
+        .add_plugin(HttpServerPlugin)
+        .add_router_service::<ExtractRouter>()
+        .add_component(
+            Config::new().with("http_server", HttpServerConfig::new(server_port.as_addr())),
+        )
+        .build()
+        .await
+        .unwrap();
+
+    let shutdown = CancellationToken::new();
+    let shutdown_clone = shutdown.clone();
+    let server_task = tokio::spawn(async move { app.run_daemons(shutdown_clone).await });
+
+    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
+    let client = ClientBuilder::new(reqwest::Client::new())
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .build();
+    let base_url = format!("http://{}", server_port.as_addr());
+
+    let response = client
+        .get(format!("{base_url}/items?prefix=a"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await.unwrap(), "a-list");
+
+    let response = client
+        .post(format!("{base_url}/items?prefix=b"))
+        .header("Content-Type", "application/json")
+        .body(json!({"name": "item"}).to_string())
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await.unwrap(), "b-item");
+
+    // Missing query parameters and malformed bodies are rejected by the extractors.
+    let response = client
+        .get(format!("{base_url}/items"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 400);
+
+    let response = client
+        .post(format!("{base_url}/items?prefix=b"))
+        .header("Content-Type", "application/json")
+        .body("{}")
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 422);
+
+    shutdown.cancel();
+    let _ = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task).await;
+}
+
+#[derive(Service)]
+struct StatusRouter;
+
+#[router]
+impl StatusRouter {
+    #[route(post, path = "/created", status = 201)]
+    async fn create(&self) -> String {
+        "created".to_string()
+    }
+
+    #[route(post, path = "/conflict", status = 201)]
+    async fn conflict(&self) -> (StatusCode, String) {
+        (StatusCode::CONFLICT, "exists".to_string())
+    }
+
+    #[route(get, path = "/found")]
+    async fn found(&self) -> Result<String, StatusCode> {
+        Ok("found".to_string())
+    }
+
+    #[route(post, path = "/missing", status = 201)]
+    async fn missing(&self) -> Result<String, StatusCode> {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
+
+#[tokio::test]
+async fn test_route_status() {
+    let server_port = FreePort::new();
+
+    let app = App::builder()
+        .add_plugin(HttpServerPlugin)
+        .add_router_service::<StatusRouter>()
+        .add_component(
+            Config::new().with("http_server", HttpServerConfig::new(server_port.as_addr())),
+        )
+        .build()
+        .await
+        .unwrap();
+
+    let shutdown = CancellationToken::new();
+    let shutdown_clone = shutdown.clone();
+    let server_task = tokio::spawn(async move { app.run_daemons(shutdown_clone).await });
+
+    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
+    let client = ClientBuilder::new(reqwest::Client::new())
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .build();
+    let base_url = format!("http://{}", server_port.as_addr());
+
+    let response = client
+        .post(format!("{base_url}/created"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 201);
+    assert_eq!(response.text().await.unwrap(), "created");
+
+    // A status set by the handler itself takes precedence.
+    let response = client
+        .post(format!("{base_url}/conflict"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 409);
+
+    // Handlers can return a `Result` of two responses.
+    let response = client
+        .get(format!("{base_url}/found"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await.unwrap(), "found");
+    let response = client
+        .post(format!("{base_url}/missing"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 404);
+
+    shutdown.cancel();
+    let _ = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task).await;
+}
+
+#[tokio::test]
+async fn test_http_tuning() {
+    let server_port = FreePort::new();
+
+    let app = App::builder()
+        .add_plugin(HttpServerPlugin)
+        .add_router_service::<ExampleRouter>()
+        .add_middleware_service::<AuthMiddleware>()
+        .add_middleware_service::<ReqIdMiddleware>()
+        .add_component(Config::new().with(
+            "http_server",
+            json!({
+                "addr": server_port.as_addr().to_string(),
+                "http": {
+                    "tcp_nodelay": true,
+                    "http2_keep_alive_interval": "20s",
+                    "max_concurrent_streams": 8,
+                },
+            }),
+        ))
+        .build()
+        .await
+        .unwrap();
+
+    let shutdown = CancellationToken::new();
+    let shutdown_clone = shutdown.clone();
+    let server_task = tokio::spawn(async move { app.run_daemons(shutdown_clone).await });
+
+    let base_url = format!("http://{}", server_port.as_addr());
+    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
+    for http2 in [false, true] {
+        let client = if http2 {
+            reqwest::Client::builder().http2_prior_knowledge()
+        } else {
+            reqwest::Client::builder().http1_only()
+        };
+        let client = ClientBuilder::new(client.build().unwrap())
+            .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+            .build();
+        let response = client
+            .get(format!("{base_url}/public"))
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), 200);
+        let expected_version = if http2 {
+            reqwest::Version::HTTP_2
+        } else {
+            reqwest::Version::HTTP_11
+        };
+        assert_eq!(response.version(), expected_version);
+        assert_eq!(response.text().await.unwrap(), "public value");
+    }
+
+    shutdown.cancel();
+    let result = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task)
+        .await
+        .expect("Server did not stop");
+    assert!(result.unwrap().is_ok());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_unix_socket() {
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+    let path = std::env::temp_dir().join(format!("diode-http-{}.sock", std::process::id()));
+    // A stale socket file from a previous run is replaced.
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    let app = App::builder()
+        .add_plugin(HttpServerPlugin)
+        .add_router_service::<StatusRouter>()
+        .add_component(Config::new().with(
+            "http_server",
+            json!({"addr": format!("unix:{}", path.display())}),
+        ))
+        .build()
+        .await
+        .unwrap();
+
+    let shutdown = CancellationToken::new();
+    let shutdown_clone = shutdown.clone();
+    let server_task = tokio::spawn(async move { app.run_daemons(shutdown_clone).await });
+
+    let mut stream = None;
+    for _ in 0..50 {
+        if let Ok(v) = tokio::net::UnixStream::connect(&path).await {
+            stream = Some(v);
+            break;
+        }
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+    }
+    let mut stream = stream.expect("Failed to connect to the socket");
+    stream
+        .write_all(b"GET /found HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+    assert!(response.ends_with("found"), "{response}");
+
+    shutdown.cancel();
+    tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert!(!path.exists());
+}
+
+#[derive(Service)]
+struct RequestIdRouter;
+
+#[router]
+impl RequestIdRouter {
+    #[route(get, path = "/request-id")]
+    async fn request_id(&self, Extension(request_id): Extension<RequestId>) -> String {
+        request_id.0
+    }
+}
+
+#[tokio::test]
+async fn test_request_id_header() {
+    let server_port = FreePort::new();
+
+    let app = App::builder()
+        .add_plugin(HttpServerPlugin)
+        .add_router_service::<RequestIdRouter>()
+        .add_component(Config::new().with(
+            "http_server",
+            HttpServerConfig {
+                request_id_header: Some("X-Request-ID".to_string()),
+                ..HttpServerConfig::new(server_port.as_addr())
+            },
+        ))
+        .build()
+        .await
+        .unwrap();
+
+    let shutdown = CancellationToken::new();
+    let shutdown_clone = shutdown.clone();
+    let server_task = tokio::spawn(async move { app.run_daemons(shutdown_clone).await });
+
+    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
+    let client = ClientBuilder::new(reqwest::Client::new())
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .build();
+    let base_url = format!("http://{}", server_port.as_addr());
+
+    // An incoming request id is kept and echoed.
+    let response = client
+        .get(format!("{base_url}/request-id"))
+        .header("X-Request-ID", "req-123")
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers().get("X-Request-ID").unwrap(), "req-123");
+    assert_eq!(response.text().await.unwrap(), "req-123");
+
+    // A missing request id is generated.
+    let response = client
+        .get(format!("{base_url}/request-id"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    let header = response
+        .headers()
+        .get("X-Request-ID")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(header.len(), 32);
+    assert_eq!(response.text().await.unwrap(), header);
+
+    shutdown.cancel();
+    let _ = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task).await;
+}
+
+#[tokio::test]
+async fn test_request_id_header_invalid() {
+    let result = App::builder()
+        .add_plugin(HttpServerPlugin)
+        .add_component(Config::new().with(
+            "http_server",
+            HttpServerConfig {
+                request_id_header: Some("Bad Header".to_string()),
+                ..HttpServerConfig::new(FreePort::new().as_addr())
+            },
+        ))
+        .build()
+        .await;
+    let err = result.err().unwrap().to_string();
+    assert!(err.contains("Invalid request id header"), "{err}");
+}
+
+#[tokio::test]
+async fn test_optional_http_server() {
+    // Without the marker a missing section is an error.
+    let result = App::builder()
+        .add_plugin(HttpServerPlugin)
+        .add_component(Config::new())
+        .build()
+        .await;
+    assert!(result.is_err());
+
+    let app = App::builder()
+        .add_plugin(HttpServerPlugin)
+        .add_router_service::<StatusRouter>()
+        .add_component(OptionalHttpServer)
+        .add_component(Config::new())
+        .build()
+        .await
+        .unwrap();
+    // No server daemon is registered, so this returns immediately.
+    app.run_daemons(CancellationToken::new()).await.unwrap();
+
+    // A present but invalid section is still an error.
+    let result = App::builder()
+        .add_plugin(HttpServerPlugin)
+        .add_component(OptionalHttpServer)
+        .add_component(Config::new().with("http_server", json!({"addr": "invalid"})))
+        .build()
+        .await;
+    assert!(result.is_err());
+}
+
+#[derive(Service)]
+struct EventsRouter;
+
+#[router]
+impl EventsRouter {
+    #[route(sse, path = "/events", middleware = [ValueHeaderMiddleware])]
+    async fn events(
+        &self,
+        #[query] filters: BTreeMap<String, String>,
+    ) -> impl futures::Stream<Item = Result<SseEvent, Infallible>> + use<> {
+        let topic = filters.get("topic").cloned().unwrap_or_default();
+        futures::stream::iter(["first", "second"].map(move |data| {
+            Ok(SseEvent::default().event(topic.clone()).data(data))
+        }))
+    }
+}
+
+#[tokio::test]
+async fn test_route_sse() {
+    let server_port = FreePort::new();
+
+    let mut builder = App::builder();
+    builder
+        .add_plugin(HttpServerPlugin)
+        .add_router_service::<EventsRouter>()
+        .add_component(
+            Config::new().with("http_server", HttpServerConfig::new(server_port.as_addr())),
+        );
+    builder.add_middleware(ValueHeaderMiddleware {
+        value: "sse".to_string(),
+    });
+    let app = builder.build().await.unwrap();
+
+    let shutdown = CancellationToken::new();
+    let shutdown_clone = shutdown.clone();
+    let server_task = tokio::spawn(async move { app.run_daemons(shutdown_clone).await });
+
+    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
+    let client = ClientBuilder::new(reqwest::Client::new())
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .build();
+    let base_url = format!("http://{}", server_port.as_addr());
+
+    let response = client
+        .get(format!("{base_url}/events?topic=news"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    // Route middleware still applies to the streaming response.
+    assert_eq!(response.headers()["X-Custom"], "sse");
+    assert_eq!(
+        response.text().await.unwrap(),
+        "event: news\ndata: first\n\nevent: news\ndata: second\n\n"
+    );
+
+    shutdown.cancel();
+    let _ = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task).await;
+}
+
+// Auth that leaves `/public` open even though it wraps the whole router.
+#[derive(Service)]
+struct SkippingAuthMiddleware;
+
+impl Middleware for SkippingAuthMiddleware {
+    type Error = Infallible;
+
+    async fn call(&self, _request: Request, _next: impl Next) -> Result<Response, Infallible> {
+        Ok(StatusCode::UNAUTHORIZED.into_response())
+    }
+
+    fn should_apply(&self, request: &Request) -> bool {
+        request.uri().path() != "/public"
+    }
+}
+
+#[derive(Service)]
+struct SkippingRouter;
+
+#[router(middleware = [SkippingAuthMiddleware])]
+impl SkippingRouter {
+    #[route(get, path = "/public")]
+    async fn public(&self) -> String {
+        "public value".to_string()
+    }
+
+    #[route(get, path = "/private")]
+    async fn private(&self) -> String {
+        "private value".to_string()
+    }
+}
+
+#[tokio::test]
+async fn test_middleware_should_apply() {
+    let server_port = FreePort::new();
+
+    let app = App::builder()
+        .add_plugin(HttpServerPlugin)
+        .add_router_service::<SkippingRouter>()
+        .add_middleware_service::<SkippingAuthMiddleware>()
+        .add_component(
+            Config::new().with("http_server", HttpServerConfig::new(server_port.as_addr())),
+        )
+        .build()
+        .await
+        .unwrap();
+
+    let shutdown = CancellationToken::new();
+    let shutdown_clone = shutdown.clone();
+    let server_task = tokio::spawn(async move { app.run_daemons(shutdown_clone).await });
+
+    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
+    let client = ClientBuilder::new(reqwest::Client::new())
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .build();
+    let base_url = format!("http://{}", server_port.as_addr());
+
+    let response = client
+        .get(format!("{base_url}/public"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await.unwrap(), "public value");
+
+    let response = client
+        .get(format!("{base_url}/private"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 401);
+
+    shutdown.cancel();
+    let _ = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task).await;
+}
+
+// Grants the space-separated scopes of the `X-Scopes` header.
+#[derive(Service)]
+struct ScopeMiddleware;
+
+impl Middleware for ScopeMiddleware {
+    type Error = StatusCode;
+
+    async fn call(&self, request: Request, next: impl Next) -> Result<Response, StatusCode> {
+        if let Some(required) = RequiredScopes::from_request(&request) {
+            let granted = request
+                .headers()
+                .get("X-Scopes")
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default();
+            if !required.is_granted(granted.split(' ')) {
+                return Err(StatusCode::FORBIDDEN);
+            }
+        }
+        Ok(next.call(request).await)
+    }
+}
+
+#[derive(Service)]
+struct ScopedRouter;
+
+#[router(middleware = [ScopeMiddleware])]
+impl ScopedRouter {
+    #[route(get, path = "/scoped/open")]
+    async fn open(&self, scopes: Option<Extension<RequiredScopes>>) -> String {
+        format!("{:?}", scopes.map(|v| v.0.scopes()))
+    }
+
+    #[route(get, path = "/scoped/admin", scopes = ["admin"])]
+    async fn admin(&self) -> String {
+        "admin".to_string()
+    }
+
+    #[route(post, path = "/scoped/admin", scopes = ["admin", "write"])]
+    async fn admin_write(&self) -> String {
+        "admin write".to_string()
+    }
+}
+
+#[tokio::test]
+async fn test_route_scopes() {
+    let server_port = FreePort::new();
+
+    let app = App::builder()
+        .add_plugin(HttpServerPlugin)
+        .add_router_service::<ScopedRouter>()
+        .add_middleware_service::<ScopeMiddleware>()
+        .add_component(
+            Config::new().with("http_server", HttpServerConfig::new(server_port.as_addr())),
+        )
+        .build()
+        .await
+        .unwrap();
+
+    let shutdown = CancellationToken::new();
+    let shutdown_clone = shutdown.clone();
+    let server_task = tokio::spawn(async move { app.run_daemons(shutdown_clone).await });
+
+    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
+    let client = ClientBuilder::new(reqwest::Client::new())
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .build();
+    let base_url = format!("http://{}", server_port.as_addr());
+
+    let response = client
+        .get(format!("{base_url}/scoped/open"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await.unwrap(), "None");
+
+    let cases = [
+        (reqwest::Method::GET, "", 403),
+        (reqwest::Method::GET, "read admin", 200),
+        (reqwest::Method::POST, "admin", 403),
+        (reqwest::Method::POST, "write admin", 200),
+    ];
+    for (method, scopes, status) in cases {
+        let response = client
+            .request(method.clone(), format!("{base_url}/scoped/admin"))
+            .header("X-Scopes", scopes)
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), status, "{method} with {scopes:?}");
+    }
+
+    shutdown.cancel();
+    let _ = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task).await;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Tenant(String);
+
+#[derive(Service)]
+struct TenantMiddleware;
+
+impl Middleware for TenantMiddleware {
+    type Error = Infallible;
+
+    async fn call(&self, request: Request, next: impl Next) -> Result<Response, Infallible> {
+        let tenant = request.headers()["X-Tenant"].to_str().unwrap().to_string();
+        RequestContext::current().unwrap().insert(Tenant(tenant));
+        Ok(next.call(request).await)
+    }
+}
+
+// Reads the context without any parameter, as a deeply nested service would.
+fn current_tenant() -> Option<String> {
+    RequestContext::current()?.get::<Tenant>().map(|tenant| tenant.0)
+}
+
+#[derive(Service)]
+struct ContextRouter;
+
+#[router]
+impl ContextRouter {
+    #[route(get, path = "/context", middleware = [TenantMiddleware])]
+    async fn context(&self) -> String {
+        let context = RequestContext::current().unwrap();
+        let request_id = context.get::<RequestId>().unwrap();
+        // Spawned tasks do not inherit the context unless it is scoped.
+        let spawned = tokio::spawn(async { current_tenant() }).await.unwrap();
+        let scoped = tokio::spawn(context.scope(async { current_tenant() }))
+            .await
+            .unwrap();
+        format!(
+            "{} {} {spawned:?} {scoped:?}",
+            request_id.0,
+            current_tenant().unwrap(),
+        )
+    }
+}
+
+#[tokio::test]
+async fn test_request_context() {
+    let server_port = FreePort::new();
+
+    let mut builder = App::builder();
+    builder
+        .add_plugin(HttpServerPlugin)
+        .add_router_service::<ContextRouter>()
+        .add_middleware_service::<TenantMiddleware>()
+        .add_component(Config::new().with(
+            "http_server",
+            HttpServerConfig {
+                request_id_header: Some("X-Request-ID".to_string()),
+                ..HttpServerConfig::new(server_port.as_addr())
+            },
+        ));
+    builder.add_middleware(RequestContextMiddleware);
+    builder.add_global_middleware::<RequestContextMiddleware>();
+    let app = builder.build().await.unwrap();
+
+    let shutdown = CancellationToken::new();
+    let shutdown_clone = shutdown.clone();
+    let server_task = tokio::spawn(async move { app.run_daemons(shutdown_clone).await });
+
+    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
+    let client = ClientBuilder::new(reqwest::Client::new())
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .build();
+    let base_url = format!("http://{}", server_port.as_addr());
+
+    let response = client
+        .get(format!("{base_url}/context"))
+        .header("X-Request-ID", "req-1")
+        .header("X-Tenant", "acme")
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.text().await.unwrap(),
+        r#"req-1 acme None Some("acme")"#
+    );
+
+    shutdown.cancel();
+    let _ = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task).await;
+}
+
+#[derive(serde::Serialize)]
+struct Greeting {
+    name: &'static str,
+    count: u32,
+}
+
+#[derive(Service)]
+struct NegotiatedRouter;
+
+#[router(middleware = [ContentNegotiationMiddleware])]
+impl NegotiatedRouter {
+    #[route(get, path = "/greeting")]
+    async fn greeting(&self) -> ContentNegotiated<Greeting> {
+        ContentNegotiated(Greeting {
+            name: "diode",
+            count: 2,
+        })
+    }
+}
+
+#[test]
+fn test_response_format_from_accept() {
+    assert_eq!(ResponseFormat::from_accept("*/*"), ResponseFormat::Json);
+    assert_eq!(ResponseFormat::from_accept("text/html"), ResponseFormat::Json);
+    assert_eq!(
+        ResponseFormat::from_accept("application/x-www-form-urlencoded, application/json"),
+        ResponseFormat::Form
+    );
+    assert_eq!(
+        ResponseFormat::from_accept("application/json;q=0.5, application/x-www-form-urlencoded"),
+        ResponseFormat::Form
+    );
+    assert_eq!(
+        ResponseFormat::from_accept("application/x-www-form-urlencoded;q=0, */*"),
+        ResponseFormat::Json
+    );
+}
+
+#[tokio::test]
+async fn test_content_negotiation() {
+    let server_port = FreePort::new();
+
+    let mut builder = App::builder();
+    builder
+        .add_plugin(HttpServerPlugin)
+        .add_router_service::<NegotiatedRouter>()
+        .add_component(
+            Config::new().with("http_server", HttpServerConfig::new(server_port.as_addr())),
+        );
+    builder.add_middleware(ContentNegotiationMiddleware);
+    let app = builder.build().await.unwrap();
+
+    let shutdown = CancellationToken::new();
+    let shutdown_clone = shutdown.clone();
+    let server_task = tokio::spawn(async move { app.run_daemons(shutdown_clone).await });
+
+    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
+    let client = ClientBuilder::new(reqwest::Client::new())
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .build();
+    let url = format!("http://{}/greeting", server_port.as_addr());
+
+    let response = client.get(&url).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "application/json");
+    assert_eq!(response.headers()["vary"], "accept");
+    assert_eq!(
+        response.json::<serde_json::Value>().await.unwrap(),
+        json!({"name": "diode", "count": 2})
+    );
+
+    let response = client
+        .get(&url)
+        .header("Accept", "application/x-www-form-urlencoded")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers()["content-type"],
+        "application/x-www-form-urlencoded"
+    );
+    assert_eq!(response.text().await.unwrap(), "name=diode&count=2");
+
+    shutdown.cancel();
+    let _ = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task).await;
+}
+
+/// Collects the `body` field of every logged event.
+#[derive(Clone, Default)]
+struct BodyLogCollector(Arc<std::sync::Mutex<Vec<String>>>);
+
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for BodyLogCollector {
+    fn on_event(
+        &self,
+        event: &tracing::Event<'_>,
+        _ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        struct Visitor(Option<String>);
+
+        impl tracing::field::Visit for Visitor {
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                if field.name() == "body" {
+                    self.0 = Some(format!("{value:?}"));
+                }
+            }
+        }
+
+        let mut visitor = Visitor(None);
+        event.record(&mut visitor);
+        if let Some(body) = visitor.0 {
+            self.0.lock().unwrap().push(body);
+        }
+    }
+}
+
+#[derive(Service)]
+struct EchoRouter;
+
+#[router]
+impl EchoRouter {
+    #[route(post, path = "/echo")]
+    async fn echo(&self, #[json] item: CreateItem) -> String {
+        format!("hello {}", item.name)
+    }
+}
+
+#[tokio::test]
+async fn test_body_log_middleware() {
+    use tracing_subscriber::layer::SubscriberExt as _;
+
+    let collector = BodyLogCollector::default();
+    let _guard =
+        tracing::subscriber::set_default(tracing_subscriber::registry().with(collector.clone()));
+    let server_port = FreePort::new();
+
+    let mut builder = App::builder();
+    builder
+        .add_plugin(HttpServerPlugin)
+        .add_router_service::<EchoRouter>()
+        .add_middleware_service::<BodyLogMiddleware>()
+        .add_component(
+            Config::new()
+                .with("http_server", HttpServerConfig::new(server_port.as_addr()))
+                .with("http_body_log", json!({"max_bytes": 12})),
+        );
+    builder.add_global_middleware::<BodyLogMiddleware>();
+    let app = builder.build().await.unwrap();
+
+    let shutdown = CancellationToken::new();
+    let shutdown_clone = shutdown.clone();
+    let server_task = tokio::spawn(async move { app.run_daemons(shutdown_clone).await });
+
+    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
+    let client = ClientBuilder::new(reqwest::Client::new())
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .build();
+    let response = client
+        .post(format!("http://{}/echo", server_port.as_addr()))
+        .header("Content-Type", "application/json")
+        .body(r#"{"name":"diode"}"#)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    // Bodies are truncated in the log only.
+    assert_eq!(response.text().await.unwrap(), "hello diode");
+    let bodies = collector.0.lock().unwrap().clone();
+    assert_eq!(bodies, [r#"{"name":"dio"#, "hello diode"]);
+
+    shutdown.cancel();
+    let _ = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task).await;
+}
+
+#[derive(Service)]
+struct UnregisteredMiddlewareRouter;
+
+#[router(middleware = [TenantMiddleware])]
+impl UnregisteredMiddlewareRouter {
+    #[route(get, path = "/unregistered")]
+    async fn unregistered(&self) -> &'static str {
+        "unreachable"
+    }
+}
+
+#[tokio::test]
+async fn test_router_missing_middleware() {
+    let server_port = FreePort::new();
+
+    // The app build fails instead of the server daemon.
+    let err = App::builder()
+        .add_plugin(HttpServerPlugin)
+        .add_router_service::<UnregisteredMiddlewareRouter>()
+        .add_component(
+            Config::new().with("http_server", HttpServerConfig::new(server_port.as_addr())),
+        )
+        .build()
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "Plugin error: Middleware {} is not registered",
+            std::any::type_name::<TenantMiddleware>()
+        )
+    );
+}
+
+#[derive(Service)]
+struct PanicRouter;
+
+#[router]
+impl PanicRouter {
+    #[route(get, path = "/panic")]
+    async fn panic(&self) -> &'static str {
+        panic!("handler failed")
+    }
+
+    #[route(get, path = "/ok")]
+    async fn ok(&self) -> &'static str {
+        "ok"
+    }
+}
+
+#[tokio::test]
+async fn test_handler_panic() {
+    let server_port = FreePort::new();
+
+    let app = App::builder()
+        .add_plugin(HttpServerPlugin)
+        .add_router_service::<PanicRouter>()
+        .add_component(
+            Config::new().with("http_server", HttpServerConfig::new(server_port.as_addr())),
+        )
+        .build()
+        .await
+        .unwrap();
+
+    let shutdown = CancellationToken::new();
+    let shutdown_clone = shutdown.clone();
+    let server_task = tokio::spawn(async move { app.run_daemons(shutdown_clone).await });
+
+    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
+    let client = ClientBuilder::new(reqwest::Client::new())
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .build();
+    let base_url = format!("http://{}", server_port.as_addr());
+    let response = client
+        .get(format!("{base_url}/ok"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    // Without retries: a 500 counts as transient.
+    let response = reqwest::get(format!("{base_url}/panic"))
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 500);
+    // The server keeps serving after the panic.
+    let response = reqwest::get(format!("{base_url}/ok"))
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+
+    shutdown.cancel();
+    let _ = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task).await;
+}
+
+#[tokio::test]
+async fn test_control_server_catch_panic_disabled() {
+    let control_port = FreePort::new();
+
+    let app = App::builder()
+        .add_plugin(ControlServerPlugin)
+        .add_control_router_service::<PanicRouter>()
+        .add_component(Config::new().with(
+            "control_server",
+            ControlServerConfig {
+                catch_panic: false,
+                ..ControlServerConfig::new(control_port.as_addr())
+            },
+        ))
+        .build()
+        .await
+        .unwrap();
+
+    let shutdown = CancellationToken::new();
+    let shutdown_clone = shutdown.clone();
+    let server_task = tokio::spawn(async move { app.run_daemons(shutdown_clone).await });
+
+    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
+    let client = ClientBuilder::new(reqwest::Client::new())
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .build();
+    let base_url = format!("http://{}", control_port.as_addr());
+    let response = client
+        .get(format!("{base_url}/ok"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    // The panic drops the connection instead of answering 500.
+    let result = reqwest::get(format!("{base_url}/panic")).await;
+    assert!(result.is_err());
+
+    shutdown.cancel();
+    let _ = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task).await;
+}
+
+struct StatusApi;
+
+impl RouterGroup for StatusApi {
+    fn register(builder: &mut diode::AppBuilder) {
+        builder
+            .add_router_service::<StatusRouter>()
+            .add_router_service::<PanicRouter>();
+    }
+}
+
+struct Api;
+
+impl RouterGroup for Api {
+    fn register(builder: &mut diode::AppBuilder) {
+        builder.add_router_group::<StatusApi>();
+    }
+}
+
+#[tokio::test]
+async fn test_router_group() {
+    let mut builder = App::builder();
+    // Groups are registered once, however many times they are added.
+    builder
+        .add_router_group::<StatusApi>()
+        .add_router_group::<Api>();
+    assert!(builder.has_router_group::<StatusApi>());
+    assert!(builder.has_router_group::<Api>());
+    assert!(builder.has_router_service::<StatusRouter>());
+    assert!(builder.has_router_service::<PanicRouter>());
+    builder
+        .add_plugin(HttpServerPlugin)
+        .add_component(OptionalHttpServer)
+        .add_component(Config::new());
+    builder.build().await.unwrap();
+}
+
+#[derive(Service)]
+struct TimingRouter;
+
+#[router]
+impl TimingRouter {
+    #[route(get, path = "/timing")]
+    async fn timing(&self, start: RequestStart) -> String {
+        tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+        start.elapsed().as_millis().to_string()
+    }
+}
+
+#[tokio::test]
+async fn test_request_start_and_server_timing() {
+    let server_port = FreePort::new();
+
+    let app = App::builder()
+        .add_plugin(HttpServerPlugin)
+        .add_router_service::<TimingRouter>()
+        .add_component(Config::new().with(
+            "http_server",
+            HttpServerConfig {
+                server_timing: true,
+                ..HttpServerConfig::new(server_port.as_addr())
+            },
+        ))
+        .build()
+        .await
+        .unwrap();
+
+    let shutdown = CancellationToken::new();
+    let shutdown_clone = shutdown.clone();
+    let server_task = tokio::spawn(async move { app.run_daemons(shutdown_clone).await });
+
+    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
+    let client = ClientBuilder::new(reqwest::Client::new())
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .build();
+    let response = client
+        .get(format!("http://{}/timing", server_port.as_addr()))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    let server_timing = response.headers()["server-timing"].to_str().unwrap().to_string();
+    let total: f64 = server_timing
+        .strip_prefix("total;dur=")
+        .unwrap()
+        .parse()
+        .unwrap();
+    let elapsed: f64 = response.text().await.unwrap().parse().unwrap();
+    assert!(elapsed >= 20.0, "{elapsed}");
+    assert!(total >= elapsed, "{total} < {elapsed}");
+
+    shutdown.cancel();
+    let _ = tokio::time::timeout(tokio::time::Duration::from_secs(5), server_task).await;
+}
+
+#[tokio::test]
+async fn test_serve_in_memory() {
+    let app = App::builder()
+        .add_plugin(HttpServerPlugin)
+        .add_router_service::<ExampleRouter>()
+        .add_middleware_service::<AuthMiddleware>()
+        .add_middleware_service::<ReqIdMiddleware>()
+        .add_component(Config::new().with(
+            "http_server",
+            HttpServerConfig {
+                request_id_header: Some("X-Request-ID".to_string()),
+                ..HttpServerConfig::new(FreePort::new().as_addr())
+            },
+        ))
+        .build()
+        .await
+        .unwrap();
+    let client = diode_http::testing::serve_in_memory(&app).unwrap();
+
+    let response = client.get("/public").await;
+    assert_eq!(response.status(), 200);
+    assert!(response.headers().contains_key("X-Req-Id"));
+    assert!(response.headers().contains_key("X-Request-ID"));
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(body, "public value");
+
+    let response = client.get("/private").await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let request = Request::builder()
+        .uri("/private")
+        .header("Authorization", "password")
+        .body(axum::body::Body::empty())
+        .unwrap();
+    let response = client.request(request).await;
+    assert_eq!(response.status(), 200);
+
+    let response = client.post("/public", "ignored").await;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+}
+
+#[tokio::test]
+async fn test_server_listener() {
+    let (addr, listener) = FreePort::new().into_listener();
+    // The configured address is never bound when a listener is given.
+    let unused_port = FreePort::new();
+
+    let app = App::builder()
+        .add_plugin(HttpServerPlugin)
+        .add_router_service::<ExampleRouter>()
+        .add_middleware_service::<AuthMiddleware>()
+        .add_middleware_service::<ReqIdMiddleware>()
+        .add_component(ServerListener::<HttpServerDaemon>::new(listener))
+        .add_component(
+            Config::new().with("http_server", HttpServerConfig::new(unused_port.as_addr())),
+        )
+        .build()
+        .await
+        .unwrap();
+
+    let shutdown = CancellationToken::new();
+    let shutdown_clone = shutdown.clone();
+    let server_task = tokio::spawn(async move { app.run_daemons(shutdown_clone).await });
+
+    // The listener is bound already, so requests need no retries.
+    let response = reqwest::get(format!("http://{addr}/public")).await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await.unwrap(), "public value");
+    assert!(std::net::TcpListener::bind(unused_port.as_addr()).is_ok());
+
+    shutdown.cancel();
+    server_task.await.unwrap().unwrap();
+}
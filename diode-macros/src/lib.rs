@@ -1,10 +1,11 @@
 use proc_macro::TokenStream;
 use quote::quote;
 
+use syn::punctuated::Punctuated;
 use syn::spanned::Spanned as _;
 use syn::{
-    Attribute, Data, DeriveInput, Error, FnArg, GenericArgument, ImplItem, ItemImpl, Pat,
-    PathArguments, Type,
+    Attribute, Data, DeriveInput, Error, Expr, FnArg, GenericArgument, ImplItem, ImplItemFn,
+    ItemImpl, LitStr, Meta, Pat, PathArguments, Token, Type,
 };
 
 fn extract_arc_type(ty: &Type) -> Option<Type> {
@@ -20,8 +21,12 @@ fn extract_arc_type(ty: &Type) -> Option<Type> {
 }
 
 fn extract_extract_type(attrs: &[Attribute]) -> Option<Type> {
+    extract_attr_type(attrs, EXTRACT_ATTR)
+}
+
+fn extract_attr_type(attrs: &[Attribute], name: &str) -> Option<Type> {
     for attr in attrs {
-        if attr.path().is_ident(EXTRACT_ATTR)
+        if attr.path().is_ident(name)
             && let Ok(meta_list) = attr.meta.require_list()
             && let Ok(ty) = syn::parse2::<Type>(meta_list.tokens.clone())
         {
@@ -32,16 +37,56 @@ fn extract_extract_type(attrs: &[Attribute]) -> Option<Type> {
 }
 
 const EXTRACT_ATTR: &str = "inject";
+const EXTRACT_REF_ATTR: &str = "inject_ref";
+const EXTRACT_ASYNC_ATTR: &str = "inject_async";
 const FACTORY_ATTR: &str = "factory";
+const SERVICE_ATTR: &str = "service";
+const PROVIDE_ATTR: &str = "provide";
+const DEPENDS_ATTR: &str = "depends";
 
 /// Derive macro for Service trait
-#[proc_macro_derive(Service, attributes(inject))]
+///
+/// The handle defaults to `Arc<Self>`. Use `#[service(handle = MyHandle)]` on
+/// the struct to expose another handle type, built with `From<Self>`, or add
+/// `wrap = path::to::fn` to build it with a `fn(Self) -> MyHandle` instead.
+/// `#[service(no_component)]` drops the handle after the build instead of
+/// storing it, see `Service::REGISTER_COMPONENT`. `#[service(after = MyPlugin)]`,
+/// repeatable, builds the service after a plain plugin.
+///
+/// `#[inject_ref(Extractor)] field: T` fills a field from an `ExtractRef<T>`
+/// extractor, like a `&T` factory parameter, storing a clone of the value; `T`
+/// must be `Clone`. The reference is released before the next field is
+/// extracted.
+#[proc_macro_derive(Service, attributes(inject, inject_ref, service))]
 pub fn derive_service(input: TokenStream) -> TokenStream {
     let input = syn::parse_macro_input!(input as DeriveInput);
     handle_derive_service(input)
 }
 
+/// Derive macro for Plugin trait
+///
+/// `build` adds a clone of every `#[provide]` field as a component, so those
+/// fields must be `Clone`; other fields are left alone. `#[depends(PluginA,
+/// PluginB)]` on the struct, repeatable, makes the plugin build after the
+/// listed plugins.
+#[proc_macro_derive(Plugin, attributes(provide, depends))]
+pub fn derive_plugin(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    handle_derive_plugin(input)
+}
+
 /// Attribute macro for impl blocks with factory methods
+///
+/// The `#[factory]` method builds the service. To choose between construction
+/// variants at build time, mark several methods `#[factory(when = predicate)]`,
+/// where `predicate` is a `fn(&AppContext) -> bool`. Predicates are tried in
+/// declaration order and the first match is used; a single plain `#[factory]`
+/// acts as the fallback. All variants must return the same handle type, and the
+/// service depends on the dependencies of every variant.
+///
+/// `#[inject_async(Extractor)] arg: T` awaits an `AsyncExtract<T>` extractor.
+/// Such parameters are extracted before all others, so no component guard is
+/// held across the `.await`.
 #[proc_macro_attribute]
 pub fn service(_attr: TokenStream, item: TokenStream) -> TokenStream {
     if let Ok(item_impl) = syn::parse::<ItemImpl>(item) {
@@ -56,8 +101,65 @@ pub fn service(_attr: TokenStream, item: TokenStream) -> TokenStream {
     )
 }
 
+struct ServiceAttribute {
+    handle: Option<Type>,
+    wrap: Option<Expr>,
+    no_component: bool,
+    after: Vec<Type>,
+}
+
+fn parse_service_attribute(attrs: &[Attribute]) -> Result<ServiceAttribute, Error> {
+    let mut handle = None;
+    let mut wrap = None;
+    let mut no_component = false;
+    let mut after = Vec::new();
+    for attr in attrs {
+        if !attr.path().is_ident(SERVICE_ATTR) {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("handle") {
+                let value = meta.value()?;
+                handle = Some(if value.peek(LitStr) {
+                    value.parse::<LitStr>()?.parse::<Type>()?
+                } else {
+                    value.parse::<Type>()?
+                });
+                Ok(())
+            } else if meta.path.is_ident("wrap") {
+                wrap = Some(meta.value()?.parse::<Expr>()?);
+                Ok(())
+            } else if meta.path.is_ident("no_component") {
+                no_component = true;
+                Ok(())
+            } else if meta.path.is_ident("after") {
+                after.push(meta.value()?.parse::<Type>()?);
+                Ok(())
+            } else {
+                Err(meta.error("Unsupported attribute format in #[service]"))
+            }
+        })?;
+    }
+    if let (None, Some(wrap)) = (&handle, &wrap) {
+        return Err(Error::new(
+            wrap.span(),
+            "`wrap` requires a `handle` type in #[service]",
+        ));
+    }
+    Ok(ServiceAttribute {
+        handle,
+        wrap,
+        no_component,
+        after,
+    })
+}
+
 fn handle_derive_service(input: DeriveInput) -> TokenStream {
     let name = &input.ident;
+    let service_attr = match parse_service_attribute(&input.attrs) {
+        Ok(v) => v,
+        Err(err) => return TokenStream::from(err.to_compile_error()),
+    };
     let fields = match &input.data {
         Data::Struct(s) => &s.fields,
         _ => {
@@ -71,13 +173,43 @@ fn handle_derive_service(input: DeriveInput) -> TokenStream {
     let mut field_inits = Vec::new();
     let mut field_lets = Vec::new();
 
+    for plugin in &service_attr.after {
+        dependency_stmts.push(quote! {
+            deps = deps.plugin::<#plugin>();
+        });
+    }
+
     match fields {
         syn::Fields::Named(fields) => {
             for field in &fields.named {
                 let field_ident = field.ident.as_ref().unwrap();
                 let field_ty = &field.ty;
 
-                if let Some(extract_type) = extract_extract_type(&field.attrs) {
+                let extract_ref_type = extract_attr_type(&field.attrs, EXTRACT_REF_ATTR);
+                if let Some(extract_type) = extract_ref_type {
+                    if extract_extract_type(&field.attrs).is_some() {
+                        return TokenStream::from(
+                            Error::new(
+                                field_ty.span(),
+                                format!(
+                                    "#[{EXTRACT_ATTR}] and #[{EXTRACT_REF_ATTR}] cannot be combined"
+                                ),
+                            )
+                            .to_compile_error(),
+                        );
+                    }
+                    field_lets.push(quote! {
+                        let #field_ident: #field_ty = ::std::clone::Clone::clone(
+                            &*<#extract_type as ::diode::ExtractRef<#field_ty>>::extract_ref(ctx)?,
+                        );
+                    });
+
+                    dependency_stmts.push(quote! {
+                        deps = deps.merge(<#extract_type as ::diode::ExtractRef<#field_ty>>::dependencies());
+                    });
+
+                    field_inits.push(quote! { #field_ident: #field_ident });
+                } else if let Some(extract_type) = extract_extract_type(&field.attrs) {
                     field_lets.push(quote! {
                         let #field_ident = <#extract_type as ::diode::Extract<#field_ty>>::extract(ctx)?;
                     });
@@ -108,7 +240,9 @@ fn handle_derive_service(input: DeriveInput) -> TokenStream {
                     return TokenStream::from(
                         Error::new(
                             field_ty.span(),
-                            format!("Service dependencies must be of type Arc<T> or use #[{EXTRACT_ATTR}]",),
+                            format!(
+                                "Service dependencies must be of type Arc<T> or use #[{EXTRACT_ATTR}] or #[{EXTRACT_REF_ATTR}]",
+                            ),
                         )
                         .to_compile_error(),
                     );
@@ -123,17 +257,39 @@ fn handle_derive_service(input: DeriveInput) -> TokenStream {
         syn::Fields::Unit => {}
     }
 
+    let service = quote! {
+        Self {
+            #(#field_inits,)*
+        }
+    };
+    let register_component = if service_attr.no_component {
+        quote! { const REGISTER_COMPONENT: bool = false; }
+    } else {
+        quote! {}
+    };
+    let (handle_type, handle) = match (service_attr.handle, service_attr.wrap) {
+        (Some(handle_type), Some(wrap)) => (quote! { #handle_type }, quote! { (#wrap)(#service) }),
+        (Some(handle_type), None) => (
+            quote! { #handle_type },
+            quote! { <#handle_type as ::std::convert::From<Self>>::from(#service) },
+        ),
+        _ => (
+            quote! { ::std::sync::Arc<Self> },
+            quote! { ::std::sync::Arc::new(#service) },
+        ),
+    };
+
     quote! {
         impl ::diode::Service for #name {
-            type Handle = ::std::sync::Arc<Self>;
+            type Handle = #handle_type;
+
+            #register_component
 
             async fn build(
                 ctx: &::diode::AppContext
             ) -> Result<Self::Handle, ::diode::StdError> {
                 #(#field_lets)*
-                Ok(::std::sync::Arc::new(Self {
-                    #(#field_inits,)*
-                }))
+                Ok(#handle)
             }
 
             fn dependencies() -> ::diode::Dependencies {
@@ -147,6 +303,96 @@ fn handle_derive_service(input: DeriveInput) -> TokenStream {
     .into()
 }
 
+fn handle_derive_plugin(input: DeriveInput) -> TokenStream {
+    let name = &input.ident;
+    let fields = match &input.data {
+        Data::Struct(s) => &s.fields,
+        _ => {
+            return TokenStream::from(
+                Error::new(name.span(), "Only structs are supported").to_compile_error(),
+            );
+        }
+    };
+
+    let mut dependencies = Vec::new();
+    for attr in &input.attrs {
+        if !attr.path().is_ident(DEPENDS_ATTR) {
+            continue;
+        }
+        match attr.parse_args_with(Punctuated::<Type, Token![,]>::parse_terminated) {
+            Ok(plugins) => dependencies.extend(plugins),
+            Err(err) => return TokenStream::from(err.to_compile_error()),
+        }
+    }
+
+    let mut provides = Vec::new();
+    for (index, field) in fields.iter().enumerate() {
+        let Some(attr) = field
+            .attrs
+            .iter()
+            .find(|attr| attr.path().is_ident(PROVIDE_ATTR))
+        else {
+            continue;
+        };
+        if let Err(err) = attr.meta.require_path_only() {
+            return TokenStream::from(err.to_compile_error());
+        }
+        let member = match &field.ident {
+            Some(ident) => syn::Member::Named(ident.clone()),
+            None => syn::Member::Unnamed(index.into()),
+        };
+        provides.push(quote! {
+            ctx.add_component(::std::clone::Clone::clone(&self.#member));
+        });
+    }
+
+    quote! {
+        impl ::diode::Plugin for #name {
+            async fn build(
+                &self,
+                ctx: &::diode::AppContext,
+            ) -> Result<(), ::diode::StdError> {
+                #(#provides)*
+                Ok(())
+            }
+
+            fn dependencies(&self) -> ::diode::Dependencies {
+                ::diode::Dependencies::new()
+                    #(.plugin::<#dependencies>())*
+            }
+        }
+    }
+    .into()
+}
+
+struct Factory<'a> {
+    method: &'a ImplItemFn,
+    when: Option<Expr>,
+}
+
+fn parse_factory_attribute(attr: &Attribute) -> Result<Option<Expr>, Error> {
+    if let Meta::Path(_) = attr.meta {
+        return Ok(None);
+    }
+    let mut when = None;
+    attr.parse_nested_meta(|meta| {
+        if meta.path.is_ident("when") {
+            when = Some(meta.value()?.parse::<Expr>()?);
+            Ok(())
+        } else {
+            Err(meta.error("Unsupported attribute format in #[factory]"))
+        }
+    })?;
+    Ok(when)
+}
+
+struct FactoryExpansion {
+    handle_type: Type,
+    build_body: proc_macro2::TokenStream,
+    dependency_stmts: Vec<proc_macro2::TokenStream>,
+    cleaned_inputs: Punctuated<FnArg, Token![,]>,
+}
+
 fn handle_service_impl(input: ItemImpl) -> TokenStream {
     if input.trait_.is_some() {
         return TokenStream::from(
@@ -155,46 +401,139 @@ fn handle_service_impl(input: ItemImpl) -> TokenStream {
     }
 
     let self_ty = &input.self_ty;
-    let mut new_method = None;
+    let mut factories = Vec::new();
+    let mut has_default = false;
 
     for item in &input.items {
         if let ImplItem::Fn(method) = item {
             for attr in &method.attrs {
                 if attr.path().is_ident(FACTORY_ATTR) {
-                    if new_method.is_some() {
-                        return TokenStream::from(
-                            Error::new(attr.span(), "Only one constructor method allowed")
+                    let when = match parse_factory_attribute(attr) {
+                        Ok(v) => v,
+                        Err(err) => return TokenStream::from(err.to_compile_error()),
+                    };
+                    if when.is_none() {
+                        if has_default {
+                            return TokenStream::from(
+                                Error::new(
+                                    attr.span(),
+                                    "Only one constructor method without `when` allowed",
+                                )
                                 .to_compile_error(),
-                        );
+                            );
+                        }
+                        has_default = true;
                     }
-                    new_method = Some(method);
+                    factories.push(Factory { method, when });
                 }
             }
         }
     }
 
-    let method = match new_method {
-        Some(m) => m,
-        None => {
-            return TokenStream::from(
-                Error::new(input.span(), "No factory method found").to_compile_error(),
-            );
+    if factories.is_empty() {
+        return TokenStream::from(
+            Error::new(input.span(), "No factory method found").to_compile_error(),
+        );
+    }
+
+    let mut expansions = Vec::with_capacity(factories.len());
+    for factory in &factories {
+        match expand_factory(factory.method) {
+            Ok(v) => expansions.push(v),
+            Err(err) => return TokenStream::from(err.to_compile_error()),
         }
-    };
+    }
+
+    // Every factory must produce the same handle; the first one defines it.
+    let handle_type = expansions[0].handle_type.clone();
+    let dependency_stmts: Vec<_> = expansions
+        .iter()
+        .flat_map(|v| v.dependency_stmts.clone())
+        .collect();
+
+    // Factories with `when` are tried in declaration order, then the default.
+    let mut branches = Vec::new();
+    let mut default_body = None;
+    for (factory, expansion) in factories.iter().zip(&expansions) {
+        let build_body = &expansion.build_body;
+        match &factory.when {
+            Some(when) => branches.push(quote! {
+                if (#when)(ctx) {
+                    return { #build_body };
+                }
+            }),
+            None => default_body = Some(build_body.clone()),
+        }
+    }
+    let default_body = default_body.unwrap_or_else(|| {
+        quote! {
+            Err(format!(
+                "No factory of {} matches the app context",
+                ::std::any::type_name::<Self>()
+            )
+            .into())
+        }
+    });
+
+    // Create cleaned input with extract and new attributes removed
+    let mut cleaned_input = input.clone();
+    let mut expansions = expansions.into_iter();
+    for item in &mut cleaned_input.items {
+        if let ImplItem::Fn(method) = item
+            && method
+                .attrs
+                .iter()
+                .any(|attr| attr.path().is_ident(FACTORY_ATTR))
+        {
+            // Remove extract attributes from method parameters
+            method.sig.inputs = expansions.next().unwrap().cleaned_inputs;
+            // Remove new attribute from method
+            method
+                .attrs
+                .retain(|attr| !attr.path().is_ident(FACTORY_ATTR));
+        }
+    }
+
+    quote! {
+        #cleaned_input
+
+        impl ::diode::Service for #self_ty {
+            type Handle = #handle_type;
+
+            async fn build(
+                ctx: &::diode::AppContext
+            ) -> Result<Self::Handle, ::diode::StdError> {
+                use ::std::ops::{Deref as _, DerefMut as _};
+                #(#branches)*
+                #default_body
+            }
 
+            fn dependencies() -> ::diode::Dependencies {
+                use ::diode::ServiceDependencyExt as _;
+                let mut deps = ::diode::Dependencies::new();
+                #(#dependency_stmts)*
+                deps
+            }
+        }
+    }
+    .into()
+}
+
+fn expand_factory(method: &ImplItemFn) -> Result<FactoryExpansion, Error> {
     let method_name = &method.sig.ident;
     let is_async = method.sig.asyncness.is_some();
     let mut dependency_stmts = Vec::new();
+    let mut async_arg_inits = Vec::new();
     let mut arg_inits = Vec::new();
     let mut arg_names = Vec::new();
 
     // Extract the actual return type to use as Handle
     let return_type = match &method.sig.output {
         syn::ReturnType::Default => {
-            return TokenStream::from(
-                Error::new(method.sig.span(), "Factory method must have a return type")
-                    .to_compile_error(),
-            );
+            return Err(Error::new(
+                method.sig.span(),
+                "Factory method must have a return type",
+            ));
         }
         syn::ReturnType::Type(_, ty) => ty.as_ref(),
     };
@@ -203,35 +542,49 @@ fn handle_service_impl(input: ItemImpl) -> TokenStream {
     let (handle_type, is_result) = extract_handle_type(return_type);
 
     // Create cleaned inputs without extract attributes
-    let mut cleaned_inputs = Vec::new();
+    let mut cleaned_inputs = Punctuated::new();
     let mut has_mut_ref = false;
     let mut ref_count: usize = 0;
 
     for fn_arg in &method.sig.inputs {
         match fn_arg {
             FnArg::Receiver(_) => {
-                return TokenStream::from(
-                    Error::new(
-                        fn_arg.span(),
-                        "Constructor method cannot have self parameter",
-                    )
-                    .to_compile_error(),
-                );
+                return Err(Error::new(
+                    fn_arg.span(),
+                    "Constructor method cannot have self parameter",
+                ));
             }
             FnArg::Typed(pat_type) => {
                 let arg_ty = &pat_type.ty;
 
                 // Create cleaned parameter without extract attributes
                 let mut cleaned_pat_type = pat_type.clone();
-                cleaned_pat_type
-                    .attrs
-                    .retain(|attr| !attr.path().is_ident(EXTRACT_ATTR));
+                cleaned_pat_type.attrs.retain(|attr| {
+                    !attr.path().is_ident(EXTRACT_ATTR) && !attr.path().is_ident(EXTRACT_ASYNC_ATTR)
+                });
                 cleaned_inputs.push(FnArg::Typed(cleaned_pat_type));
 
                 if let Pat::Ident(pat_ident) = pat_type.pat.as_ref() {
                     let arg_name = &pat_ident.ident;
 
-                    if let Some(extract_type) = extract_extract_type(&pat_type.attrs) {
+                    let extract_async_type = extract_attr_type(&pat_type.attrs, EXTRACT_ASYNC_ATTR);
+                    if let Some(extract_type) = extract_async_type {
+                        if extract_extract_type(&pat_type.attrs).is_some() {
+                            return Err(Error::new(
+                                arg_ty.span(),
+                                format!(
+                                    "#[{EXTRACT_ATTR}] and #[{EXTRACT_ASYNC_ATTR}] cannot be combined"
+                                ),
+                            ));
+                        }
+                        arg_names.push(quote! { #arg_name });
+                        async_arg_inits.push(quote! {
+                            let #arg_name = <#extract_type as ::diode::AsyncExtract<#arg_ty>>::extract(ctx).await?;
+                        });
+                        dependency_stmts.push(quote! {
+                            deps = deps.merge(<#extract_type as ::diode::AsyncExtract<#arg_ty>>::dependencies());
+                        });
+                    } else if let Some(extract_type) = extract_extract_type(&pat_type.attrs) {
                         match arg_ty.as_ref() {
                             Type::Reference(ref_ty) if ref_ty.mutability.is_some() => {
                                 has_mut_ref = true;
@@ -283,56 +636,31 @@ fn handle_service_impl(input: ItemImpl) -> TokenStream {
                                 })?;
                         });
                     } else {
-                        return TokenStream::from(
-                            Error::new(
-                                arg_ty.span(),
-                                format!(
-                                    "Arguments must be of type Arc<T> or use #[{EXTRACT_ATTR}]",
-                                ),
-                            )
-                            .to_compile_error(),
-                        );
+                        return Err(Error::new(
+                            arg_ty.span(),
+                            format!(
+                                "Arguments must be of type Arc<T> or use #[{EXTRACT_ATTR}] or #[{EXTRACT_ASYNC_ATTR}]",
+                            ),
+                        ));
                     }
                 } else {
-                    return TokenStream::from(
-                        Error::new(pat_type.pat.span(), "Only simple bindings supported")
-                            .to_compile_error(),
-                    );
+                    return Err(Error::new(
+                        pat_type.pat.span(),
+                        "Only simple bindings supported",
+                    ));
                 }
             }
         }
     }
 
     if has_mut_ref && ref_count > 1 {
-        return TokenStream::from(
-            Error::new(
-                method.sig.span(),
-                "Combining a `&mut` inject parameter with other `&` or `&mut` inject parameters \
-                 may cause a deadlock. Use `#[inject(AppContext)] ctx: &AppContext` and call \
-                 `get_component_ref`/`get_component_mut` manually, ensuring that guards do not \
-                 overlap.",
-            )
-            .to_compile_error(),
-        );
-    }
-
-    // Create cleaned input with extract and new attributes removed
-    let mut cleaned_input = input.clone();
-    for item in &mut cleaned_input.items {
-        if let ImplItem::Fn(method) = item
-            && method
-                .attrs
-                .iter()
-                .any(|attr| attr.path().is_ident(FACTORY_ATTR))
-        {
-            // Remove extract attributes from method parameters
-            method.sig.inputs = cleaned_inputs.into_iter().collect();
-            // Remove new attribute from method
-            method
-                .attrs
-                .retain(|attr| !attr.path().is_ident(FACTORY_ATTR));
-            break;
-        }
+        return Err(Error::new(
+            method.sig.span(),
+            "Combining a `&mut` inject parameter with other `&` or `&mut` inject parameters \
+             may cause a deadlock. Use `#[inject(AppContext)] ctx: &AppContext` and call \
+             `get_component_ref`/`get_component_mut` manually, ensuring that guards do not \
+             overlap.",
+        ));
     }
 
     // Generate the method call based on whether it's async and returns Result
@@ -345,38 +673,24 @@ fn handle_service_impl(input: ItemImpl) -> TokenStream {
     // Wrap the call based on whether the original method returns Result
     let build_body = if is_result {
         quote! {
+            #(#async_arg_inits)*
             #(#arg_inits)*
             #method_call.map_err(|e| e.into())
         }
     } else {
         quote! {
+            #(#async_arg_inits)*
             #(#arg_inits)*
             Ok(#method_call)
         }
     };
 
-    quote! {
-        #cleaned_input
-
-        impl ::diode::Service for #self_ty {
-            type Handle = #handle_type;
-
-            async fn build(
-                ctx: &::diode::AppContext
-            ) -> Result<Self::Handle, ::diode::StdError> {
-                use ::std::ops::{Deref as _, DerefMut as _};
-                #build_body
-            }
-
-            fn dependencies() -> ::diode::Dependencies {
-                use ::diode::ServiceDependencyExt as _;
-                let mut deps = ::diode::Dependencies::new();
-                #(#dependency_stmts)*
-                deps
-            }
-        }
-    }
-    .into()
+    Ok(FactoryExpansion {
+        handle_type,
+        build_body,
+        dependency_stmts,
+        cleaned_inputs,
+    })
 }
 
 fn extract_handle_type(ty: &Type) -> (Type, bool) {
@@ -1,10 +1,9 @@
-use std::any::{Any, TypeId};
+use std::any::TypeId;
 use std::collections::HashMap;
-use std::sync::Mutex;
+use std::sync::{Arc, OnceLock, Weak};
 
-use dashmap::DashMap;
-
-use crate::{AppBuilder, AppContext, StdError};
+use crate::context::{ComponentBox, ComponentInfo};
+use crate::{AppBuilder, AppContext, PluginError, Scope, StdError};
 
 /// Main application container that holds all registered components and services.
 ///
@@ -37,21 +36,130 @@ use crate::{AppBuilder, AppContext, StdError};
 /// # }
 /// ```
 pub struct App {
-    pub(crate) components: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+    pub(crate) components: HashMap<TypeId, ComponentBox>,
+    pub(crate) component_info: HashMap<TypeId, ComponentInfo>,
+    pub(crate) init_order: Vec<&'static str>,
+    pub(crate) handle: Weak<App>,
 }
 
 impl App {
     /// Creates a new [`AppBuilder`] for configuring and building an application.
     pub fn builder() -> AppBuilder {
         AppBuilder {
-            context: AppContext {
-                components: DashMap::new(),
-                plugins: DashMap::new(),
-                pending_plugins: Mutex::new(Vec::new()),
-            },
+            context: AppContext::new(),
+            built: false,
         }
     }
 
+    /// Converts the application into a shared handle.
+    ///
+    /// Unlike `Arc::new`, the returned application keeps a weak reference to
+    /// itself, so code that only has `&App` (a daemon, a router) can obtain an
+    /// owned handle with [`handle`](App::handle), for example to move into a
+    /// spawned task.
+    ///
+    /// This also binds the [`AppHandle`] component, so services that captured
+    /// it during the build can upgrade it from now on.
+    pub fn into_handle(self) -> Arc<App> {
+        Arc::new_cyclic(|handle| {
+            if let Some(app_handle) = self.get_component_ref::<AppHandle>() {
+                let _ = app_handle.0.set(handle.clone());
+            }
+            App {
+                components: self.components,
+                component_info: self.component_info,
+                init_order: self.init_order,
+                handle: handle.clone(),
+            }
+        })
+    }
+
+    /// Returns a shared handle to this application.
+    ///
+    /// Returns `None` unless the application was converted with
+    /// [`into_handle`](App::into_handle) and that handle is still alive.
+    pub fn handle(&self) -> Option<Arc<App>> {
+        self.handle.upgrade()
+    }
+
+    /// Returns the names of the plugins in the order they were built.
+    ///
+    /// A plugin always comes after the plugins it depends on, so shutting
+    /// down in reverse order releases dependents first. After
+    /// [`merge`](App::merge), the plugins of the merged application follow
+    /// those of this one.
+    pub fn init_order(&self) -> &[&'static str] {
+        &self.init_order
+    }
+
+    /// Creates a [`Scope`] for components that live shorter than the
+    /// application, such as per-request state.
+    pub fn scope(&self) -> Scope<'_> {
+        Scope::new(self)
+    }
+
+    /// Moves the components of `other`, an independently built application,
+    /// into this one.
+    ///
+    /// Components added with [`AppContext::add_merge_component`] (such as the
+    /// daemon and command registries) are merged with [`MergeComponent::merge`]
+    /// when both applications have them. Any other component present in both
+    /// is a conflict; use [`merge_overwrite`](App::merge_overwrite) to replace
+    /// it with the one from `other` instead. The [`AppHandle`] of this
+    /// application is kept, so services of `other` that captured its handle
+    /// are never bound.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppError::ComponentConflict`] before moving anything if a
+    /// component conflicts, or [`AppError::PluginError`] if merging a
+    /// component fails, in which case the components merged so far are kept.
+    pub fn merge(&mut self, other: App) -> Result<(), AppError> {
+        self.merge_components(other, false)
+    }
+
+    /// Same as [`merge`](App::merge), but a conflicting component is replaced
+    /// with the one from `other`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppError::PluginError`] if merging a component fails.
+    pub fn merge_overwrite(&mut self, other: App) -> Result<(), AppError> {
+        self.merge_components(other, true)
+    }
+
+    fn merge_components(&mut self, mut other: App, overwrite: bool) -> Result<(), AppError> {
+        other.components.remove(&TypeId::of::<AppHandle>());
+        if !overwrite {
+            for type_id in other.components.keys() {
+                if let Some(info) = self.component_info.get(type_id)
+                    && info.merge.is_none()
+                {
+                    return Err(AppError::ComponentConflict(info.name));
+                }
+            }
+        }
+        for (type_id, component) in other.components {
+            let info = other.component_info.remove(&type_id).unwrap();
+            let merge = self.component_info.get(&type_id).and_then(|v| v.merge);
+            match (self.components.get_mut(&type_id), merge) {
+                (Some(current), Some(merge)) => {
+                    merge(current, component).map_err(|err| {
+                        AppError::PluginError(
+                            format!("Component {} merge failed: {err}", info.name).into(),
+                        )
+                    })?;
+                }
+                _ => {
+                    self.components.insert(type_id, component);
+                    self.component_info.insert(type_id, info);
+                }
+            }
+        }
+        self.init_order.extend(other.init_order);
+        Ok(())
+    }
+
     /// Retrieves a component by type, returning a clone.
     pub fn get_component<T>(&self) -> Option<T>
     where
@@ -60,6 +168,26 @@ impl App {
         self.get_component_ref().cloned()
     }
 
+    /// Retrieves a component by type, returning a clone, or the value of `f`
+    /// if it is missing.
+    ///
+    /// ```rust
+    /// use diode::App;
+    ///
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let app = App::builder().build().await?;
+    /// let retries = app.get_component_or_else(|| 3_usize);
+    /// assert_eq!(retries, 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_component_or_else<T>(&self, f: impl FnOnce() -> T) -> T
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        self.get_component().unwrap_or_else(f)
+    }
+
     /// Checks if a component of the specified type exists.
     pub fn has_component<T>(&self) -> bool
     where
@@ -79,6 +207,72 @@ impl App {
     }
 }
 
+/// A component that is combined, rather than replaced, by [`App::merge`].
+///
+/// Registries that collect items from many plugins implement this, so that
+/// merging two applications keeps the items of both. Add such a component
+/// with [`AppContext::add_merge_component`].
+pub trait MergeComponent: Send + Sync + 'static {
+    /// Moves the items of `other` into `self`.
+    fn merge(&mut self, other: Self) -> Result<(), StdError>;
+}
+
+/// A late-bound, weak handle to the finished [`App`].
+///
+/// Every application has an `AppHandle` component, so services can extract
+/// it during [`Service::build`](crate::Service::build) with
+/// `#[inject(Component)]` or [`AppContext::get_component`] and keep it for
+/// later, for example to resolve components lazily from a spawned task.
+///
+/// The handle is empty until the built application is converted with
+/// [`App::into_handle`]; [`get`](AppHandle::get) returns `None` before that.
+/// It only holds a [`Weak`] reference, so a service storing it does not keep
+/// the application alive: once the last `Arc<App>` is dropped, `get` returns
+/// `None` again. Tasks that need the application for their whole lifetime
+/// should upgrade the handle once and hold the resulting `Arc<App>`, bearing
+/// in mind that this keeps every component alive until the task finishes.
+///
+/// # Examples
+///
+/// ```rust
+/// use diode::{AddServiceExt as _, App, AppHandle, Component, Service};
+/// use std::sync::Arc;
+///
+/// #[derive(Service)]
+/// struct Worker {
+///     #[inject(Component)]
+///     app: AppHandle,
+/// }
+///
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let app = App::builder().add_service::<Worker>().build().await?;
+/// let worker = app.get_component::<Arc<Worker>>().unwrap();
+/// assert!(worker.app.get().is_none());
+///
+/// let app = app.into_handle();
+/// assert!(Arc::ptr_eq(&worker.app.get().unwrap(), &app));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct AppHandle(Arc<OnceLock<Weak<App>>>);
+
+impl AppHandle {
+    /// Returns the application, if it was converted with
+    /// [`App::into_handle`] and is still alive.
+    pub fn get(&self) -> Option<Arc<App>> {
+        self.0.get().and_then(Weak::upgrade)
+    }
+}
+
+impl std::fmt::Debug for AppHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AppHandle")
+            .field("bound", &self.0.get().is_some())
+            .finish()
+    }
+}
+
 /// Errors that can occur during application building.
 #[derive(Debug)]
 pub enum AppError {
@@ -96,8 +290,28 @@ pub enum AppError {
     },
     /// A component was not found during extraction.
     MissingComponent(&'static str),
+    /// Both applications passed to [`App::merge`] have a component of this
+    /// type, and it is not a [`MergeComponent`].
+    ComponentConflict(&'static str),
+    /// Several services store their handle as the same component type, such
+    /// as two database services with `type Handle = Arc<Pool>`.
+    ///
+    /// `services` names the services involved; a single name means the
+    /// component was added by something other than a service.
+    DuplicateComponent {
+        type_name: &'static str,
+        services: Vec<&'static str>,
+    },
+    /// [`AppBuilder::build`] was called on a builder that was already built.
+    AlreadyBuilt,
     /// An error occurred within a plugin during initialization.
     PluginError(StdError),
+    /// A plugin failed with a structured [`PluginError`], telling apart
+    /// misconfiguration, unavailable dependencies and bugs.
+    PluginFailed {
+        plugin: &'static str,
+        error: PluginError,
+    },
 }
 
 impl std::fmt::Display for AppError {
@@ -116,7 +330,28 @@ impl std::fmt::Display for AppError {
             AppError::MissingComponent(name) => {
                 write!(f, "Missing component: {name}")
             }
+            AppError::ComponentConflict(name) => {
+                write!(f, "Component conflict: {name}")
+            }
+            AppError::DuplicateComponent {
+                type_name,
+                services,
+            } => match services.as_slice() {
+                [service] => write!(
+                    f,
+                    "Component {type_name} is already added, cannot add the handle of {service}"
+                ),
+                services => write!(
+                    f,
+                    "Component {type_name} is the handle of several services: {}",
+                    services.join(", ")
+                ),
+            },
+            AppError::AlreadyBuilt => write!(f, "Application builder already built"),
             AppError::PluginError(e) => write!(f, "Plugin error: {e}"),
+            AppError::PluginFailed { plugin, error } => {
+                write!(f, "Plugin {plugin} failed ({}): {error}", error.kind())
+            }
         }
     }
 }
@@ -125,6 +360,7 @@ impl std::error::Error for AppError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             AppError::PluginError(e) => Some(e.as_ref()),
+            AppError::PluginFailed { error, .. } => Some(error),
             _ => None,
         }
     }
@@ -1,9 +1,6 @@
 use std::ops::Deref;
-use std::sync::Mutex;
 
-use dashmap::DashMap;
-
-use crate::{App, AppContext, AppError, Plugin};
+use crate::{App, AppContext, AppError, MergeComponent, Plugin};
 
 /// Builder for constructing an [`App`] with a fluent API.
 ///
@@ -37,6 +34,7 @@ use crate::{App, AppContext, AppError, Plugin};
 /// ```
 pub struct AppBuilder {
     pub(crate) context: AppContext,
+    pub(crate) built: bool,
 }
 
 impl Deref for AppBuilder {
@@ -61,6 +59,19 @@ impl AppBuilder {
         self
     }
 
+    /// Adds a default plugin of type `T` unless one has already been added.
+    ///
+    /// Safe to call from several bundles that all need the same plugin, e.g.
+    /// `add_plugin_once::<HttpServerPlugin>()`. See
+    /// [`AppContext::ensure_plugin`] for plugins without a [`Default`].
+    pub fn add_plugin_once<T>(&mut self) -> &mut Self
+    where
+        T: Plugin + Default + 'static,
+    {
+        self.context.ensure_plugin(T::default());
+        self
+    }
+
     /// Adds a component to the application.
     ///
     /// # Panics
@@ -74,19 +85,47 @@ impl AppBuilder {
         self
     }
 
+    /// Adds a component that is merged, rather than replaced, by
+    /// [`App::merge`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if a component of the same type has already been added.
+    pub fn add_merge_component<T>(&mut self, component: T) -> &mut Self
+    where
+        T: MergeComponent,
+    {
+        self.context.add_merge_component(component);
+        self
+    }
+
     /// Builds all plugins in dependency order and returns the final [`App`].
     ///
-    /// This drains the builder's internal state. The builder should not be
-    /// used after calling `build`.
+    /// This drains the builder's internal state, so a builder builds at most
+    /// one app; create a new builder (for example from a shared setup
+    /// function) for each variant.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppError::AlreadyBuilt`] if `build` was already called on
+    /// this builder, whether or not that build succeeded.
     pub async fn build(&mut self) -> Result<App, AppError> {
-        let context = std::mem::replace(
-            &mut self.context,
-            AppContext {
-                components: DashMap::new(),
-                plugins: DashMap::new(),
-                pending_plugins: Mutex::new(Vec::new()),
-            },
-        );
+        if std::mem::replace(&mut self.built, true) {
+            return Err(AppError::AlreadyBuilt);
+        }
+        let context = std::mem::replace(&mut self.context, AppContext::new());
         context.build_app().await
     }
+
+    /// Logs a warning for every service that nothing depends on once the app is
+    /// built.
+    ///
+    /// Services that are built only for their side effects, or that are
+    /// resolved at runtime instead of through declared dependencies, are
+    /// reported too; see [`AppContext::unused_services`]. Warnings are emitted
+    /// with [`tracing`].
+    pub fn warn_unused_services(&mut self) -> &mut Self {
+        self.context.warn_unused_services = true;
+        self
+    }
 }
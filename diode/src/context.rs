@@ -2,14 +2,55 @@ use std::any::{Any, TypeId, type_name};
 use std::collections::{HashMap, HashSet};
 use std::mem::take;
 use std::ops::{Deref, DerefMut};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Instant;
 
 use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
 use dashmap::mapref::one::{MappedRef, MappedRefMut};
+use tokio::sync::OnceCell;
+use tracing::Instrument as _;
 
-use crate::{AppError, DynPlugin, Plugin};
+use crate::service::{FnReady, ServiceProvider};
+use crate::{
+    AppError, AppHandle, DynPlugin, DynReady, MergeComponent, Plugin, PluginError, Service,
+    StdError,
+};
 
-type ComponentBox = Box<dyn Any + Send + Sync>;
+pub(crate) type ComponentBox = Box<dyn Any + Send + Sync>;
+
+/// Type-erased [`MergeComponent::merge`].
+pub(crate) type MergeFn = fn(&mut ComponentBox, ComponentBox) -> Result<(), StdError>;
+
+/// Metadata kept for every component, used by [`App::merge`](crate::App::merge).
+pub(crate) struct ComponentInfo {
+    pub(crate) name: &'static str,
+    pub(crate) merge: Option<MergeFn>,
+}
+
+impl ComponentInfo {
+    fn of<T: 'static>() -> Self {
+        Self {
+            name: type_name::<T>(),
+            merge: None,
+        }
+    }
+
+    fn mergeable<T: MergeComponent>() -> Self {
+        Self {
+            name: type_name::<T>(),
+            merge: Some(merge_component::<T>),
+        }
+    }
+}
+
+fn merge_component<T>(lhs: &mut ComponentBox, rhs: ComponentBox) -> Result<(), StdError>
+where
+    T: MergeComponent,
+{
+    let rhs = rhs.downcast::<T>().unwrap();
+    lhs.downcast_mut::<T>().unwrap().merge(*rhs)
+}
 
 /// A smart pointer providing read access to a component stored in the application.
 ///
@@ -58,11 +99,43 @@ impl<T> DerefMut for ComponentMut<'_, T> {
 /// [`AppBuilder::build`]: crate::AppBuilder::build
 pub struct AppContext {
     pub(crate) components: DashMap<TypeId, ComponentBox>,
-    pub(crate) plugins: DashMap<TypeId, Box<dyn DynPlugin>>,
+    pub(crate) component_info: DashMap<TypeId, ComponentInfo>,
+    pub(crate) plugins: DashMap<TypeId, Arc<dyn DynPlugin>>,
     pub(crate) pending_plugins: Mutex<Vec<TypeId>>,
+    pub(crate) ready_hooks: Mutex<Vec<Box<dyn DynReady>>>,
+    /// Names of registered services, keyed by their provider plugin type.
+    pub(crate) services: DashMap<TypeId, &'static str>,
+    /// Names of registered services, keyed by the type of their handle.
+    pub(crate) service_handles: DashMap<TypeId, &'static str>,
+    /// First handle type shared by two registered services, reported by the
+    /// build as [`AppError::DuplicateComponent`].
+    pub(crate) duplicate_handle: Mutex<Option<AppError>>,
+    /// Handles of services built by [`AppContext::get_or_build_service`],
+    /// keyed by the service type.
+    pub(crate) inline_services: DashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+    pub(crate) warn_unused_services: bool,
 }
 
 impl AppContext {
+    pub(crate) fn new() -> Self {
+        let components: DashMap<TypeId, ComponentBox> = DashMap::new();
+        components.insert(TypeId::of::<AppHandle>(), Box::new(AppHandle::default()));
+        let component_info = DashMap::new();
+        component_info.insert(TypeId::of::<AppHandle>(), ComponentInfo::of::<AppHandle>());
+        Self {
+            components,
+            component_info,
+            plugins: DashMap::new(),
+            pending_plugins: Mutex::new(Vec::new()),
+            ready_hooks: Mutex::new(Vec::new()),
+            services: DashMap::new(),
+            service_handles: DashMap::new(),
+            duplicate_handle: Mutex::new(None),
+            inline_services: DashMap::new(),
+            warn_unused_services: false,
+        }
+    }
+
     /// Adds a component to the application.
     ///
     /// # Panics
@@ -83,6 +156,76 @@ impl AppContext {
             panic!("Component {} already added", type_name::<T>());
         }
         self.components.insert(type_id, Box::new(component));
+        self.component_info
+            .insert(type_id, ComponentInfo::of::<T>());
+    }
+
+    /// Adds a component that is merged, rather than replaced, by
+    /// [`App::merge`](crate::App::merge).
+    ///
+    /// # Panics
+    ///
+    /// Panics if a component of the same type has already been added.
+    ///
+    /// # Deadlock
+    ///
+    /// Same as [`add_component`](AppContext::add_component).
+    pub fn add_merge_component<T>(&self, component: T)
+    where
+        T: MergeComponent,
+    {
+        self.add_component(component);
+        self.component_info
+            .insert(TypeId::of::<T>(), ComponentInfo::mergeable::<T>());
+    }
+
+    /// Retrieves a mutable reference to a component, first adding the one
+    /// returned by `f` if there is none.
+    ///
+    /// Replaces the `has_component` / `add_component` / `get_component_mut`
+    /// sequence, e.g. for registries created by the first registration. `f`
+    /// is called without holding any lock.
+    ///
+    /// # Deadlock
+    ///
+    /// Same as [`get_component_mut`](AppContext::get_component_mut).
+    pub fn get_or_insert_component_with<T>(&self, f: impl FnOnce() -> T) -> ComponentMut<'_, T>
+    where
+        T: Send + Sync + 'static,
+    {
+        self.get_or_insert(ComponentInfo::of::<T>(), f)
+    }
+
+    /// Same as [`get_or_insert_component_with`](AppContext::get_or_insert_component_with),
+    /// adding `T::default()`.
+    pub fn get_or_insert_component<T>(&self) -> ComponentMut<'_, T>
+    where
+        T: Default + Send + Sync + 'static,
+    {
+        self.get_or_insert_component_with(T::default)
+    }
+
+    /// Same as [`get_or_insert_component`](AppContext::get_or_insert_component),
+    /// but a new component is added as with
+    /// [`add_merge_component`](AppContext::add_merge_component).
+    pub fn get_or_insert_merge_component<T>(&self) -> ComponentMut<'_, T>
+    where
+        T: MergeComponent + Default,
+    {
+        self.get_or_insert(ComponentInfo::mergeable::<T>(), T::default)
+    }
+
+    fn get_or_insert<T>(&self, info: ComponentInfo, f: impl FnOnce() -> T) -> ComponentMut<'_, T>
+    where
+        T: Send + Sync + 'static,
+    {
+        let type_id = TypeId::of::<T>();
+        let component = (!self.components.contains_key(&type_id)).then(f);
+        let entry = self.components.entry(type_id).or_insert_with(|| {
+            self.component_info.insert(type_id, info);
+            Box::new(component.unwrap())
+        });
+        ComponentMut(entry.map(|v| v.downcast_mut::<T>().unwrap()))
     }
 
     /// Retrieves a component by type, returning a clone.
@@ -152,10 +295,30 @@ impl AppContext {
         if self.plugins.contains_key(&type_id) {
             panic!("Plugin {} already added", plugin.name());
         }
-        self.plugins.insert(type_id, Box::new(plugin));
+        self.plugins.insert(type_id, Arc::new(plugin));
         self.pending_plugins.lock().unwrap().push(type_id);
     }
 
+    /// Adds a plugin unless one of the same type has already been added.
+    ///
+    /// Returns `true` if `plugin` was added. Lets independently written setup
+    /// helpers share a plugin without checking [`has_plugin`](Self::has_plugin)
+    /// first; a plugin added earlier is kept as is.
+    pub fn ensure_plugin<T>(&self, plugin: T) -> bool
+    where
+        T: Plugin + 'static,
+    {
+        let type_id = TypeId::of::<T>();
+        match self.plugins.entry(type_id) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(Arc::new(plugin));
+                self.pending_plugins.lock().unwrap().push(type_id);
+                true
+            }
+        }
+    }
+
     /// Checks if a plugin of the specified type has been added.
     pub fn has_plugin<T>(&self) -> bool
     where
@@ -164,15 +327,151 @@ impl AppContext {
         self.plugins.contains_key(&TypeId::of::<T>())
     }
 
+    /// Returns the handle of the service `T`, building it on first use.
+    ///
+    /// Meant for a [`Service::build`] that constructs a helper service inline
+    /// instead of declaring it as a dependency: every caller shares the handle
+    /// built by the first call, so an expensive resource is not constructed
+    /// once per consumer. Concurrent callers wait for the same build.
+    ///
+    /// ```rust
+    /// use diode::{AppContext, Service, StdError};
+    /// use std::sync::Arc;
+    ///
+    /// struct HttpClient;
+    ///
+    /// impl Service for HttpClient {
+    ///     type Handle = Arc<Self>;
+    ///
+    ///     async fn build(_ctx: &AppContext) -> Result<Self::Handle, StdError> {
+    ///         Ok(Arc::new(Self))
+    ///     }
+    /// }
+    ///
+    /// struct GitHubApi {
+    ///     client: Arc<HttpClient>,
+    /// }
+    ///
+    /// impl Service for GitHubApi {
+    ///     type Handle = Arc<Self>;
+    ///
+    ///     async fn build(ctx: &AppContext) -> Result<Self::Handle, StdError> {
+    ///         let client = ctx.get_or_build_service::<HttpClient>().await?;
+    ///         Ok(Arc::new(Self { client }))
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// A handle built this way is not added as a component and its
+    /// [`Service::ready`] is not called. If `T` is registered with
+    /// [`add_service`](crate::AddServiceExt::add_service), its component is
+    /// returned instead, and the call fails unless `T` has been built already:
+    /// declare it in [`Service::dependencies`] in that case. A failed build
+    /// is not cached, so the next call tries again.
+    ///
+    /// [`Service::build`]: crate::Service::build
+    /// [`Service::ready`]: crate::Service::ready
+    /// [`Service::dependencies`]: crate::Service::dependencies
+    pub async fn get_or_build_service<T>(&self) -> Result<T::Handle, StdError>
+    where
+        T: Service + 'static,
+        T::Handle: Clone,
+    {
+        if self.has_plugin::<ServiceProvider<T>>() {
+            return self.get_component::<T::Handle>().ok_or_else(|| {
+                format!(
+                    "Service {} is registered but not built yet, declare it as a dependency",
+                    type_name::<T>()
+                )
+                .into()
+            });
+        }
+        let cell = self
+            .inline_services
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Arc::new(OnceCell::<T::Handle>::new()))
+            .clone();
+        let cell = cell.downcast_ref::<OnceCell<T::Handle>>().unwrap();
+        cell.get_or_try_init(|| T::build(self)).await.cloned()
+    }
+
+    /// Runs `hook` once the whole application is built.
+    ///
+    /// The plugin counterpart of [`Service::ready`]: hooks run after every
+    /// plugin and service has been built, together with the `ready` of the
+    /// services, in the order they were added. Use it to check what only the
+    /// complete [`App`](crate::App) can tell, such as components registered by
+    /// plugins built later. Returning `Err` fails the build with
+    /// [`AppError::PluginError`].
+    ///
+    /// [`Service::ready`]: crate::Service::ready
+    pub fn add_ready_hook<F>(&self, hook: F)
+    where
+        F: FnOnce(&crate::App) -> Result<(), StdError> + Send + 'static,
+    {
+        self.ready_hooks
+            .lock()
+            .unwrap()
+            .push(Box::new(FnReady(Mutex::new(Some(hook)))));
+    }
+
+    /// Returns the type names of all added plugins, sorted by name.
+    pub fn plugin_names(&self) -> Vec<&'static str> {
+        let mut names: Vec<_> = self.plugins.iter().map(|v| v.name()).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Returns the number of added plugins that have not been built yet.
+    pub fn pending_plugin_count(&self) -> usize {
+        self.pending_plugins.lock().unwrap().len()
+    }
+
+    /// Returns the names of registered services that no plugin or service
+    /// declares as a dependency, sorted by name.
+    ///
+    /// Only declared [`Dependencies`](crate::Dependencies) are considered: a
+    /// service that is only resolved at runtime (for example with
+    /// [`App::get_component`](crate::App::get_component)) is reported as well.
+    /// Before the build this only sees the plugins added so far; see
+    /// [`AppBuilder::warn_unused_services`](crate::AppBuilder::warn_unused_services)
+    /// to check the complete application.
+    pub fn unused_services(&self) -> Vec<&'static str> {
+        let mut used = HashSet::new();
+        for plugin in self.plugins.iter() {
+            used.extend(plugin.dependencies().iter());
+        }
+        let mut unused: Vec<_> = self
+            .services
+            .iter()
+            .filter(|v| !used.contains(v.key()))
+            .map(|v| *v.value())
+            .collect();
+        unused.sort_unstable();
+        unused
+    }
+
     pub(crate) async fn build_app(self) -> Result<crate::App, AppError> {
+        if let Some(err) = self.duplicate_handle.lock().unwrap().take() {
+            return Err(err);
+        }
+        let build_start = Instant::now();
         let mut graph = HashMap::new();
         let mut names: HashMap<TypeId, &'static str> = HashMap::new();
         let mut used = HashMap::new();
+        let mut init_order = Vec::new();
+        let mut round = 0usize;
         loop {
             let pending_plugins = take(&mut *self.pending_plugins.lock().unwrap());
             if pending_plugins.is_empty() {
                 break;
             }
+            round += 1;
+            tracing::debug!(
+                round,
+                pending = pending_plugins.len(),
+                "Resolving plugin dependencies"
+            );
             let mut order = Vec::new();
             for type_id in &pending_plugins {
                 let plugin = self.plugins.get(type_id).unwrap();
@@ -212,16 +511,62 @@ impl AppContext {
                     .collect();
                 return Err(AppError::MissingDependency { blocked });
             }
+            tracing::debug!(
+                round,
+                order = ?order.iter().map(|v| names[v]).collect::<Vec<_>>(),
+                deferred = deferred.len(),
+                "Resolved plugin build order"
+            );
             for type_id in order {
                 assert!(ready_plugins.remove(&type_id));
-                let plugin = self.plugins.get(&type_id).unwrap();
-                plugin.build(&self).await.map_err(AppError::PluginError)?;
+                // Release the map guard before building: plugins may add plugins.
+                let plugin = self.plugins.get(&type_id).unwrap().clone();
+                let span = tracing::debug_span!("plugin_build", plugin = plugin.name());
+                let start = Instant::now();
+                plugin
+                    .build(&self)
+                    .instrument(span.clone())
+                    .await
+                    .map_err(|err| match err.downcast::<PluginError>() {
+                        Ok(error) => AppError::PluginFailed {
+                            plugin: plugin.name(),
+                            error: *error,
+                        },
+                        Err(err) => AppError::PluginError(err),
+                    })?;
+                tracing::debug!(parent: &span, elapsed = ?start.elapsed(), "Plugin built");
+                init_order.push(plugin.name());
             }
             assert!(ready_plugins.is_empty());
             self.pending_plugins.lock().unwrap().extend(deferred);
         }
+        if self.warn_unused_services {
+            for name in self.unused_services() {
+                tracing::warn!(
+                    service = name,
+                    "Service is not used by any plugin or service"
+                );
+            }
+        }
+        tracing::debug!(
+            rounds = round,
+            plugins = self.plugins.len(),
+            elapsed = ?build_start.elapsed(),
+            "Application plugins built"
+        );
         let components = self.components.into_iter().collect::<HashMap<_, _>>();
-        Ok(crate::App { components })
+        let component_info = self.component_info.into_iter().collect::<HashMap<_, _>>();
+        let app = crate::App {
+            components,
+            component_info,
+            init_order,
+            handle: Weak::new(),
+        };
+        let ready_hooks = take(&mut *self.ready_hooks.lock().unwrap());
+        for hook in ready_hooks {
+            hook.ready(&app).await.map_err(AppError::PluginError)?;
+        }
+        Ok(app)
     }
 }
 
@@ -1,4 +1,6 @@
+use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
+use std::str::FromStr;
 
 use crate::{
     AppContext, AppError, ComponentMut, ComponentRef, Dependencies, Service, ServiceDependencyExt,
@@ -60,6 +62,48 @@ pub trait ExtractMut<T>: ExtractRef<T> {
     fn extract_mut(ctx: &AppContext) -> Result<Self::RefMut<'_>, AppError>;
 }
 
+/// Trait for extracting owned values that take async work to produce, such as
+/// a connection checked out of a pool.
+///
+/// Used by `#[inject_async(Extractor)]` parameters of a `#[factory]` method,
+/// which are awaited before the other parameters are extracted, so no
+/// component guard is held across the `.await`.
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use diode::{AppContext, AppError, AsyncExtract, service};
+///
+/// struct Connection;
+///
+/// struct Connect;
+///
+/// impl AsyncExtract<Connection> for Connect {
+///     async fn extract(_ctx: &AppContext) -> Result<Connection, AppError> {
+///         Ok(Connection)
+///     }
+/// }
+///
+/// struct Repository {
+///     connection: Connection,
+/// }
+///
+/// #[service]
+/// impl Repository {
+///     #[factory]
+///     fn new(#[inject_async(Connect)] connection: Connection) -> Arc<Self> {
+///         Arc::new(Self { connection })
+///     }
+/// }
+/// ```
+pub trait AsyncExtract<T> {
+    fn extract(ctx: &AppContext) -> impl Future<Output = Result<T, AppError>> + Send;
+
+    fn dependencies() -> Dependencies {
+        Dependencies::new()
+    }
+}
+
 impl<T, S> Extract<T> for S
 where
     T: Clone + Send + Sync + 'static,
@@ -133,3 +177,52 @@ where
             .ok_or(AppError::MissingComponent(std::any::type_name::<T>()))
     }
 }
+
+/// Names an environment variable read by the [`Env`] extractor.
+pub trait EnvVar {
+    const NAME: &'static str;
+}
+
+/// Extractor for the environment variable named by `V`.
+///
+/// The value is parsed with [`FromStr`] when the service is built, so an unset
+/// or malformed variable fails the build with a message naming it.
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use diode::{Env, EnvVar, Service};
+///
+/// struct ApiToken;
+///
+/// impl EnvVar for ApiToken {
+///     const NAME: &'static str = "API_TOKEN";
+/// }
+///
+/// #[derive(Service)]
+/// struct ApiClient {
+///     #[inject(Env<ApiToken>)]
+///     token: String,
+/// }
+/// ```
+pub struct Env<V>(PhantomData<V>);
+
+impl<V, T> Extract<T> for Env<V>
+where
+    V: EnvVar,
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    fn extract(_ctx: &AppContext) -> Result<T, AppError> {
+        let value = std::env::var(V::NAME).map_err(|err| {
+            let reason = match err {
+                std::env::VarError::NotPresent => "is not set",
+                std::env::VarError::NotUnicode(_) => "is not valid unicode",
+            };
+            AppError::PluginError(format!("Environment variable {} {reason}", V::NAME).into())
+        })?;
+        value.parse().map_err(|err| {
+            AppError::PluginError(format!("Environment variable {} is invalid: {err}", V::NAME).into())
+        })
+    }
+}
@@ -10,6 +10,7 @@
 //! - **Plugin**: A trait for modular components that can register services and dependencies
 //! - **Components**: Raw objects stored in the app container
 //! - **Dependencies**: Type-safe dependency declarations between services and plugins
+//! - **Scope**: A short-lived overlay of components, e.g. per request, over the app
 //!
 //! ## Basic Usage
 //!
@@ -199,6 +200,7 @@ mod builder;
 mod context;
 mod inject;
 mod plugin;
+mod scope;
 mod service;
 
 pub use app::*;
@@ -206,6 +208,7 @@ pub use builder::*;
 pub use context::*;
 pub use inject::*;
 pub use plugin::*;
+pub use scope::*;
 pub use service::*;
 
 #[cfg(feature = "macros")]
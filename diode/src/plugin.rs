@@ -17,6 +17,75 @@ pub trait Plugin: Send + Sync {
     }
 }
 
+/// Structured error a [`Plugin`] can return from `build` to tell operators
+/// what kind of failure stopped the application.
+///
+/// Return it boxed like any other error, e.g.
+/// `Err(PluginError::misconfiguration("Section `db` is missing").into())`.
+/// [`AppBuilder::build`](crate::AppBuilder::build) then fails with
+/// [`AppError::PluginFailed`](crate::AppError::PluginFailed), naming the
+/// plugin. Other errors keep failing with
+/// [`AppError::PluginError`](crate::AppError::PluginError).
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PluginError {
+    /// The configuration is missing or invalid; the operator has to fix it.
+    Misconfiguration(StdError),
+    /// An external dependency, such as a database or a collector, is
+    /// unavailable; starting again later may succeed.
+    Unavailable(StdError),
+    /// An internal error that no configuration change fixes.
+    Bug(StdError),
+}
+
+impl PluginError {
+    /// Creates a [`PluginError::Misconfiguration`].
+    pub fn misconfiguration(err: impl Into<StdError>) -> Self {
+        Self::Misconfiguration(err.into())
+    }
+
+    /// Creates a [`PluginError::Unavailable`].
+    pub fn unavailable(err: impl Into<StdError>) -> Self {
+        Self::Unavailable(err.into())
+    }
+
+    /// Creates a [`PluginError::Bug`].
+    pub fn bug(err: impl Into<StdError>) -> Self {
+        Self::Bug(err.into())
+    }
+
+    /// Describes the kind of failure, for messages.
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            PluginError::Misconfiguration(_) => "misconfiguration",
+            PluginError::Unavailable(_) => "dependency unavailable",
+            PluginError::Bug(_) => "bug",
+        }
+    }
+
+    fn inner(&self) -> &StdError {
+        match self {
+            PluginError::Misconfiguration(err)
+            | PluginError::Unavailable(err)
+            | PluginError::Bug(err) => err,
+        }
+    }
+}
+
+/// Shows the wrapped error; [`AppError::PluginFailed`](crate::AppError::PluginFailed)
+/// adds the kind of failure.
+impl std::fmt::Display for PluginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.inner().fmt(f)
+    }
+}
+
+impl std::error::Error for PluginError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.inner().as_ref())
+    }
+}
+
 #[async_trait]
 pub(crate) trait DynPlugin: Send + Sync {
     async fn build(&self, ctx: &AppContext) -> Result<(), StdError>;
@@ -106,6 +175,26 @@ impl Dependencies {
         self.names.extend(other.names);
         self
     }
+
+    /// Returns an iterator over the [`TypeId`]s of the dependencies, in no
+    /// particular order.
+    ///
+    /// These are plugin types: a dependency declared with
+    /// [`service`](crate::ServiceDependencyExt::service) is recorded as the
+    /// plugin that provides the service.
+    pub fn iter(&self) -> impl Iterator<Item = TypeId> + '_ {
+        self.plugins.iter().copied()
+    }
+
+    /// Returns the number of dependencies.
+    pub fn len(&self) -> usize {
+        self.plugins.len()
+    }
+
+    /// Returns whether there are no dependencies.
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
 }
 
 impl Default for Dependencies {
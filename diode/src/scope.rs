@@ -0,0 +1,103 @@
+use std::any::{Any, TypeId, type_name};
+use std::collections::HashMap;
+
+use crate::App;
+
+/// A short-lived container layered over an [`App`].
+///
+/// Components in an [`App`] live as long as the application. A `Scope` adds
+/// a small set of components with a shorter lifetime, such as the current
+/// user or transaction of a request, on top of it. Lookups check the scope
+/// first and fall back to the parent application, so a scoped component
+/// shadows an application component of the same type.
+///
+/// A scope is cheap to create: typically one per request or job, created with
+/// [`App::scope`] and dropped together with its components when done.
+///
+/// # Examples
+///
+/// ```rust
+/// use diode::App;
+///
+/// #[derive(Clone)]
+/// struct CurrentUser(String);
+///
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let app = App::builder().add_component(42i32).build().await?;
+///
+/// let mut scope = app.scope();
+/// scope.add_component(CurrentUser("alice".to_string()));
+///
+/// assert_eq!(scope.get_component::<CurrentUser>().unwrap().0, "alice");
+/// assert_eq!(scope.get_component::<i32>(), Some(42));
+/// assert!(!app.has_component::<CurrentUser>());
+/// # Ok(())
+/// # }
+/// ```
+pub struct Scope<'a> {
+    app: &'a App,
+    components: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl<'a> Scope<'a> {
+    /// Creates an empty scope over `app`.
+    pub fn new(app: &'a App) -> Self {
+        Self {
+            app,
+            components: HashMap::new(),
+        }
+    }
+
+    /// Returns the parent application.
+    pub fn app(&self) -> &'a App {
+        self.app
+    }
+
+    /// Adds a component to this scope.
+    ///
+    /// The component shadows an application component of the same type for
+    /// lookups through this scope.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a component of the same type has already been added to this
+    /// scope.
+    pub fn add_component<T>(&mut self, component: T)
+    where
+        T: Send + Sync + 'static,
+    {
+        let type_id = TypeId::of::<T>();
+        if self.components.contains_key(&type_id) {
+            panic!("Component {} already added to scope", type_name::<T>());
+        }
+        self.components.insert(type_id, Box::new(component));
+    }
+
+    /// Retrieves a component by type, returning a clone.
+    pub fn get_component<T>(&self) -> Option<T>
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        self.get_component_ref().cloned()
+    }
+
+    /// Checks if a component of the specified type exists in this scope or
+    /// the parent application.
+    pub fn has_component<T>(&self) -> bool
+    where
+        T: Send + Sync + 'static,
+    {
+        self.components.contains_key(&TypeId::of::<T>()) || self.app.has_component::<T>()
+    }
+
+    /// Retrieves a reference to a component by type.
+    pub fn get_component_ref<T>(&self) -> Option<&T>
+    where
+        T: Send + Sync + 'static,
+    {
+        match self.components.get(&TypeId::of::<T>()) {
+            Some(v) => v.downcast_ref::<T>(),
+            None => self.app.get_component_ref::<T>(),
+        }
+    }
+}
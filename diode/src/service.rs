@@ -1,6 +1,10 @@
-use std::marker::PhantomData;
+use std::any::{TypeId, type_name};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use crate::{AppBuilder, AppContext, Dependencies, Plugin};
+use async_trait::async_trait;
+
+use crate::{App, AppBuilder, AppContext, AppError, Dependencies, Extract, Plugin};
 
 /// Type alias for boxed errors that can be sent across threads.
 pub type StdError = Box<dyn std::error::Error + Send + Sync>;
@@ -34,42 +38,415 @@ pub trait Service: Send + Sync {
     /// The handle type exposed when this service is injected.
     type Handle: Send + Sync + 'static;
 
+    /// Whether the built handle is stored as a component.
+    ///
+    /// Set it to `false` for a service that only exists for the side effects
+    /// of its [`build`](Service::build), such as registering routes or warming
+    /// a cache: the handle is dropped once [`ready`](Service::ready) has run,
+    /// and nothing can depend on the service. With `#[derive(Service)]`, use
+    /// `#[service(no_component)]`. Defaults to `true`.
+    const REGISTER_COMPONENT: bool = true;
+
     /// Builds an instance of this service asynchronously.
     fn build(
         ctx: &AppContext,
     ) -> impl std::future::Future<Output = Result<Self::Handle, StdError>> + Send;
 
     /// Declares the dependencies this service requires.
+    ///
+    /// Besides other services, a service can depend on a plain [`Plugin`]
+    /// with [`Dependencies::plugin`], to be built after it, for example
+    /// after a plugin that registers a component the service reads:
+    ///
+    /// ```rust
+    /// use diode::{AppContext, Dependencies, Plugin, Service, StdError};
+    /// use std::sync::Arc;
+    ///
+    /// struct RoutesPlugin;
+    ///
+    /// impl Plugin for RoutesPlugin {
+    ///     async fn build(&self, _ctx: &AppContext) -> Result<(), StdError> {
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// struct RouteAudit;
+    ///
+    /// impl Service for RouteAudit {
+    ///     type Handle = Arc<Self>;
+    ///
+    ///     async fn build(_ctx: &AppContext) -> Result<Self::Handle, StdError> {
+    ///         Ok(Arc::new(Self))
+    ///     }
+    ///
+    ///     fn dependencies() -> Dependencies {
+    ///         Dependencies::new().plugin::<RoutesPlugin>()
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// With `#[derive(Service)]`, use `#[service(after = RoutesPlugin)]`.
+    /// The plugin must be added to the builder, or the build fails with a
+    /// missing dependency.
     fn dependencies() -> Dependencies {
         Dependencies::new()
     }
+
+    /// Returns the policy for retrying a failed [`build`](Service::build).
+    ///
+    /// By default a failed build fails the whole application immediately.
+    /// Services that connect to external resources at build time can return a
+    /// [`RetryPolicy`] to ride out transient errors, such as a database that
+    /// comes up moments after the application.
+    fn retry_policy() -> Option<RetryPolicy> {
+        None
+    }
+
+    /// Finalizes the service once the whole application is built.
+    ///
+    /// Called after every plugin and service has been built, in build order,
+    /// with the handle returned by [`build`](Service::build) and the complete
+    /// [`App`]. Use it to resolve components that are registered by plugins the
+    /// service does not depend on. Returning `Err` fails the build with
+    /// [`AppError::PluginError`](crate::AppError::PluginError).
+    fn ready(
+        handle: &Self::Handle,
+        app: &App,
+    ) -> impl std::future::Future<Output = Result<(), StdError>> + Send {
+        let _ = (handle, app);
+        async { Ok(()) }
+    }
+}
+
+/// Policy for retrying a failed [`Service::build`] with exponential backoff.
+///
+/// The delay before the second attempt is the initial backoff (100 ms by
+/// default) and doubles with every further attempt, capped by the maximum
+/// backoff (10 s by default). Each failed attempt is logged with the service
+/// name; the error of the last attempt fails the build with
+/// [`AppError::PluginError`](crate::AppError::PluginError).
+///
+/// # Examples
+///
+/// ```rust
+/// use diode::{AppContext, RetryPolicy, Service, StdError};
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// struct Database;
+///
+/// impl Service for Database {
+///     type Handle = Arc<Self>;
+///
+///     async fn build(_ctx: &AppContext) -> Result<Self::Handle, StdError> {
+///         Ok(Arc::new(Self))
+///     }
+///
+///     fn retry_policy() -> Option<RetryPolicy> {
+///         Some(RetryPolicy::new(5).with_initial_backoff(Duration::from_millis(500)))
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Creates a policy that makes at most `max_attempts` build attempts.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_attempts` is zero.
+    pub fn new(max_attempts: u32) -> Self {
+        assert!(max_attempts > 0, "Retry policy needs at least one attempt");
+        Self {
+            max_attempts,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+
+    /// Sets the delay before the second attempt.
+    pub fn with_initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Sets the upper bound of the delay between attempts.
+    pub fn with_max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Returns the maximum number of build attempts.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Returns the delay after the given failed attempt, counted from 1.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt - 1).unwrap_or(u32::MAX);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+#[async_trait]
+pub(crate) trait DynReady: Send + Sync {
+    async fn ready(&self, app: &App) -> Result<(), StdError>;
+}
+
+/// Runs [`Service::ready`], holding the handle of a service that is not
+/// stored as a component.
+struct ServiceReady<T>
+where
+    T: Service,
+{
+    handle: Option<T::Handle>,
+}
+
+#[async_trait]
+impl<T> DynReady for ServiceReady<T>
+where
+    T: Service,
+{
+    async fn ready(&self, app: &App) -> Result<(), StdError> {
+        match &self.handle {
+            Some(handle) => T::ready(handle, app).await,
+            None => {
+                let handle = app
+                    .get_component_ref::<T::Handle>()
+                    .ok_or(AppError::MissingComponent(type_name::<T::Handle>()))?;
+                T::ready(handle, app).await
+            }
+        }
+    }
+}
+
+/// Runs a hook added with [`AppContext::add_ready_hook`].
+pub(crate) struct FnReady<F>(pub(crate) Mutex<Option<F>>);
+
+#[async_trait]
+impl<F> DynReady for FnReady<F>
+where
+    F: FnOnce(&App) -> Result<(), StdError> + Send,
+{
+    async fn ready(&self, app: &App) -> Result<(), StdError> {
+        match self.0.lock().unwrap().take() {
+            Some(hook) => hook(app),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Records the service `T`, noting the first handle type that another service
+/// already stores.
+fn register_service<T>(ctx: &AppContext)
+where
+    T: Service + 'static,
+{
+    ctx.services
+        .insert(TypeId::of::<ServiceProvider<T>>(), type_name::<T>());
+    if !T::REGISTER_COMPONENT {
+        return;
+    }
+    if let Some(first) = ctx
+        .service_handles
+        .insert(TypeId::of::<T::Handle>(), type_name::<T>())
+    {
+        ctx.duplicate_handle
+            .lock()
+            .unwrap()
+            .get_or_insert(AppError::DuplicateComponent {
+                type_name: type_name::<T::Handle>(),
+                services: vec![first, type_name::<T>()],
+            });
+    }
+}
+
+/// Adds the handle of the service `T`, failing rather than panicking if a
+/// plugin already added a component of the same type.
+fn add_handle<T>(ctx: &AppContext, handle: T::Handle) -> Result<(), AppError>
+where
+    T: Service + 'static,
+{
+    if ctx.has_component::<T::Handle>() {
+        return Err(AppError::DuplicateComponent {
+            type_name: type_name::<T::Handle>(),
+            services: vec![type_name::<T>()],
+        });
+    }
+    ctx.add_component(handle);
+    Ok(())
 }
 
 /// Internal plugin that wraps a service into the plugin system.
-struct ServiceProvider<T>(PhantomData<T>)
+///
+/// Holds the handle of a service registered with
+/// [`add_service_instance`](AddServiceExt::add_service_instance) until the
+/// build, in which case [`Service::build`] is not called.
+pub(crate) struct ServiceProvider<T>
 where
-    T: Service;
+    T: Service,
+{
+    instance: Mutex<Option<T::Handle>>,
+}
 
-impl<T> Plugin for ServiceProvider<T>
+impl<T> ServiceProvider<T>
 where
     T: Service,
+{
+    fn new(instance: Option<T::Handle>) -> Self {
+        Self {
+            instance: Mutex::new(instance),
+        }
+    }
+}
+
+impl<T> Plugin for ServiceProvider<T>
+where
+    T: Service + 'static,
 {
     async fn build(&self, ctx: &AppContext) -> Result<(), StdError> {
-        ctx.add_component(T::build(ctx).await?);
+        let instance = self.instance.lock().unwrap().take();
+        if let Some(handle) = instance {
+            if T::REGISTER_COMPONENT {
+                add_handle::<T>(ctx, handle)?;
+            }
+            return Ok(());
+        }
+        let handle = match T::retry_policy() {
+            Some(policy) => build_with_retry::<T>(ctx, &policy).await?,
+            None => T::build(ctx).await?,
+        };
+        let handle = if T::REGISTER_COMPONENT {
+            add_handle::<T>(ctx, handle)?;
+            None
+        } else {
+            Some(handle)
+        };
+        ctx.ready_hooks
+            .lock()
+            .unwrap()
+            .push(Box::new(ServiceReady::<T> { handle }));
         Ok(())
     }
 
     fn dependencies(&self) -> Dependencies {
+        if self.instance.lock().unwrap().is_some() {
+            return Dependencies::new();
+        }
         T::dependencies()
     }
 }
 
+async fn build_with_retry<T>(ctx: &AppContext, policy: &RetryPolicy) -> Result<T::Handle, StdError>
+where
+    T: Service,
+{
+    let mut attempt = 1;
+    loop {
+        match T::build(ctx).await {
+            Ok(handle) => return Ok(handle),
+            Err(err) if attempt < policy.max_attempts => {
+                let backoff = policy.backoff(attempt);
+                tracing::warn!(
+                    service = type_name::<T>(),
+                    attempt,
+                    max_attempts = policy.max_attempts,
+                    ?backoff,
+                    error = %err,
+                    "Service build failed, retrying"
+                );
+                tokio::time::sleep(backoff).await;
+                attempt += 1;
+            }
+            Err(err) => {
+                tracing::error!(
+                    service = type_name::<T>(),
+                    attempt,
+                    error = %err,
+                    "Service build failed, giving up"
+                );
+                return Err(err);
+            }
+        }
+    }
+}
+
 /// Extension trait for registering services on [`AppBuilder`].
 pub trait AddServiceExt {
     fn add_service<T>(&mut self) -> &mut Self
     where
         T: Service + 'static;
 
+    /// Registers an already built service handle.
+    ///
+    /// The handle is added as a component and the service counts as
+    /// registered: [`has_service`](AddServiceExt::has_service) returns `true`
+    /// and services depending on `T` are built after it as usual. Neither
+    /// [`Service::build`] nor [`Service::ready`] is called, and the
+    /// dependencies of `T` are not required. This is handy for mocks in tests
+    /// and for services constructed in `main`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the service has already been registered.
+    fn add_service_instance<T>(&mut self, handle: T::Handle) -> &mut Self
+    where
+        T: Service + 'static;
+
+    /// Registers the service `T` and exposes its handle as `Arc<I>`.
+    ///
+    /// `I` is usually a trait object, so that consumers depend on the
+    /// interface rather than on the implementation: they inject it with
+    /// [`#[inject(Interface)]`](Interface) or declare it with
+    /// [`Dependencies::interface`](ServiceDependencyExt::interface).
+    /// `coerce` performs the unsizing coercion, which stable Rust cannot
+    /// express as a bound; pass `|service| service`. The service `T` is added
+    /// if it is not already present.
+    ///
+    /// ```rust
+    /// use std::sync::Arc;
+    ///
+    /// use diode::{AddServiceExt as _, App, AppContext, Service, StdError};
+    ///
+    /// trait Store: Send + Sync {}
+    ///
+    /// struct MemoryStore;
+    ///
+    /// impl Store for MemoryStore {}
+    ///
+    /// impl Service for MemoryStore {
+    ///     type Handle = Arc<Self>;
+    ///
+    ///     async fn build(_ctx: &AppContext) -> Result<Self::Handle, StdError> {
+    ///         Ok(Arc::new(Self))
+    ///     }
+    /// }
+    ///
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let app = App::builder()
+    ///     .add_service_as::<MemoryStore, dyn Store>(|service| service)
+    ///     .build()
+    ///     .await?;
+    /// let _store = app.get_component::<Arc<dyn Store>>().unwrap();
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if an implementation of `I` has already been registered.
+    fn add_service_as<T, I>(&mut self, coerce: fn(Arc<T>) -> Arc<I>) -> &mut Self
+    where
+        T: Service<Handle = Arc<T>> + 'static,
+        I: ?Sized + Send + Sync + 'static;
+
     fn has_service<T>(&self) -> bool
     where
         T: Service + 'static;
@@ -80,7 +457,32 @@ impl AddServiceExt for AppBuilder {
     where
         T: Service + 'static,
     {
-        self.add_plugin(ServiceProvider::<T>(PhantomData));
+        self.add_plugin(ServiceProvider::<T>::new(None));
+        register_service::<T>(&self.context);
+        self
+    }
+
+    fn add_service_instance<T>(&mut self, handle: T::Handle) -> &mut Self
+    where
+        T: Service + 'static,
+    {
+        self.add_plugin(ServiceProvider::<T>::new(Some(handle)));
+        register_service::<T>(&self.context);
+        self
+    }
+
+    fn add_service_as<T, I>(&mut self, coerce: fn(Arc<T>) -> Arc<I>) -> &mut Self
+    where
+        T: Service<Handle = Arc<T>> + 'static,
+        I: ?Sized + Send + Sync + 'static,
+    {
+        if !self.has_service::<T>() {
+            self.add_service::<T>();
+        }
+        self.add_plugin(InterfaceProvider::<I> {
+            build: Box::new(move |ctx| ctx.get_component::<Arc<T>>().map(coerce)),
+            dependencies: Dependencies::new().service::<T>(),
+        });
         self
     }
 
@@ -92,11 +494,65 @@ impl AddServiceExt for AppBuilder {
     }
 }
 
+type InterfaceFn<I> = Box<dyn Fn(&AppContext) -> Option<Arc<I>> + Send + Sync>;
+
+/// Internal plugin that exposes the handle of the service registered with
+/// [`add_service_as`](AddServiceExt::add_service_as) as `Arc<I>`.
+struct InterfaceProvider<I>
+where
+    I: ?Sized,
+{
+    build: InterfaceFn<I>,
+    dependencies: Dependencies,
+}
+
+impl<I> Plugin for InterfaceProvider<I>
+where
+    I: ?Sized + Send + Sync + 'static,
+{
+    async fn build(&self, ctx: &AppContext) -> Result<(), StdError> {
+        let handle = (self.build)(ctx).ok_or(AppError::MissingComponent(type_name::<Arc<I>>()))?;
+        ctx.add_component(handle);
+        Ok(())
+    }
+
+    fn dependencies(&self) -> Dependencies {
+        self.dependencies.clone()
+    }
+}
+
+/// Extractor for an interface registered with
+/// [`add_service_as`](AddServiceExt::add_service_as).
+///
+/// Use it as `#[inject(Interface)] store: Arc<dyn Store>`: the service then
+/// depends on whichever implementation provides `dyn Store`.
+pub struct Interface;
+
+impl<I> Extract<Arc<I>> for Interface
+where
+    I: ?Sized + Send + Sync + 'static,
+{
+    fn extract(ctx: &AppContext) -> Result<Arc<I>, AppError> {
+        ctx.get_component::<Arc<I>>()
+            .ok_or(AppError::MissingComponent(type_name::<Arc<I>>()))
+    }
+
+    fn dependencies() -> Dependencies {
+        Dependencies::new().interface::<I>()
+    }
+}
+
 /// Extension trait for declaring service dependencies.
 pub trait ServiceDependencyExt {
     fn service<T>(self) -> Self
     where
         T: Service + 'static;
+
+    /// Depends on the implementation registered for the interface `I` with
+    /// [`add_service_as`](AddServiceExt::add_service_as).
+    fn interface<I>(self) -> Self
+    where
+        I: ?Sized + Send + Sync + 'static;
 }
 
 impl ServiceDependencyExt for Dependencies {
@@ -106,4 +562,11 @@ impl ServiceDependencyExt for Dependencies {
     {
         self.plugin::<ServiceProvider<T>>()
     }
+
+    fn interface<I>(self) -> Self
+    where
+        I: ?Sized + Send + Sync + 'static,
+    {
+        self.plugin::<InterfaceProvider<I>>()
+    }
 }
@@ -1,9 +1,15 @@
 use std::error::Error as _;
-use std::{any::type_name, ops::DerefMut, sync::Arc};
+use std::sync::OnceLock;
+use std::{
+    any::{TypeId, type_name},
+    ops::DerefMut,
+    sync::Arc,
+};
 
 use diode::{
-    AddServiceExt as _, App, AppContext, AppError, Component, Dependencies, Extract, ExtractMut,
-    ExtractRef, Plugin, Service, ServiceDependencyExt as _, StdError,
+    AddServiceExt as _, App, AppContext, AppError, AppHandle, Component, Dependencies, Extract,
+    ExtractMut, ExtractRef, MergeComponent, Plugin, PluginError, RetryPolicy, Service,
+    ServiceDependencyExt as _, StdError,
 };
 
 struct PluginA;
@@ -45,6 +51,133 @@ async fn test_plugins() {
         .unwrap();
 }
 
+#[tokio::test]
+async fn test_init_order() {
+    let app = App::builder()
+        .add_plugin(PluginB)
+        .add_plugin(PluginC)
+        .build()
+        .await
+        .unwrap();
+    // PluginB waits for PluginA, which PluginC adds while building.
+    assert_eq!(
+        app.init_order(),
+        [
+            type_name::<PluginC>(),
+            type_name::<PluginA>(),
+            type_name::<PluginB>()
+        ]
+    );
+    assert_eq!(app.into_handle().init_order().len(), 3);
+}
+
+struct NestingPlugin;
+
+impl Plugin for NestingPlugin {
+    async fn build(&self, ctx: &AppContext) -> Result<(), StdError> {
+        ctx.add_plugin(PluginA);
+        // Writes to the shard holding this plugin, so it would deadlock if the
+        // plugin map stayed locked while building.
+        assert!(!ctx.ensure_plugin(NestingPlugin));
+        Ok(())
+    }
+}
+
+#[test]
+fn test_plugin_adds_plugin_from_build() {
+    // Build on another thread, so a deadlock fails the test instead of hanging.
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let app = runtime.block_on(App::builder().add_plugin(NestingPlugin).build());
+        tx.send(app.map(|v| v.init_order().to_vec())).unwrap();
+    });
+    let init_order = rx
+        .recv_timeout(std::time::Duration::from_secs(5))
+        .expect("Plugin build deadlocked")
+        .unwrap();
+    assert_eq!(
+        init_order,
+        [type_name::<NestingPlugin>(), type_name::<PluginA>()]
+    );
+}
+
+struct Pool;
+
+struct PrimaryDatabase;
+
+impl Service for PrimaryDatabase {
+    type Handle = Arc<Pool>;
+
+    async fn build(_ctx: &AppContext) -> Result<Self::Handle, StdError> {
+        Ok(Arc::new(Pool))
+    }
+}
+
+struct ReplicaDatabase;
+
+impl Service for ReplicaDatabase {
+    type Handle = Arc<Pool>;
+
+    async fn build(_ctx: &AppContext) -> Result<Self::Handle, StdError> {
+        Ok(Arc::new(Pool))
+    }
+}
+
+struct PoolPlugin;
+
+impl Plugin for PoolPlugin {
+    async fn build(&self, ctx: &AppContext) -> Result<(), StdError> {
+        ctx.add_component(Arc::new(Pool));
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_duplicate_service_handle() {
+    let result = App::builder()
+        .add_service::<PrimaryDatabase>()
+        .add_service::<ReplicaDatabase>()
+        .build()
+        .await;
+    let Err(AppError::DuplicateComponent {
+        type_name: name,
+        services,
+    }) = result
+    else {
+        panic!("expected a duplicate component error");
+    };
+    assert_eq!(name, type_name::<Arc<Pool>>());
+    assert_eq!(
+        services,
+        [type_name::<PrimaryDatabase>(), type_name::<ReplicaDatabase>()]
+    );
+
+    // A component added by a plugin fails the service build instead of
+    // panicking.
+    let mut builder = App::builder();
+    builder.add_plugin(PoolPlugin).add_service::<PrimaryDatabase>();
+    let err = builder.build().await.err().unwrap();
+    assert!(
+        err.to_string().ends_with(&format!(
+            "cannot add the handle of {}",
+            type_name::<PrimaryDatabase>()
+        )),
+        "{err}"
+    );
+}
+
+#[tokio::test]
+async fn test_build_twice() {
+    let mut builder = App::builder();
+    builder.add_plugin(PluginA);
+    builder.build().await.unwrap();
+    assert!(matches!(builder.build().await, Err(AppError::AlreadyBuilt)));
+}
+
 #[tokio::test]
 #[should_panic]
 async fn test_plugins_duplicates() {
@@ -160,6 +293,44 @@ impl Service for ServiceB {
     }
 }
 
+struct StandaloneService;
+
+impl Service for StandaloneService {
+    type Handle = Arc<Self>;
+
+    async fn build(_ctx: &AppContext) -> Result<Arc<Self>, StdError> {
+        Ok(Arc::new(Self))
+    }
+}
+
+#[tokio::test]
+async fn test_plugin_names() {
+    let mut builder = App::builder();
+    assert!(builder.plugin_names().is_empty());
+    builder.add_plugin(PluginC).add_plugin(PluginB);
+    assert_eq!(
+        builder.plugin_names(),
+        [type_name::<PluginB>(), type_name::<PluginC>()]
+    );
+    assert_eq!(builder.pending_plugin_count(), 2);
+    builder.build().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_unused_services() {
+    let mut builder = App::builder();
+    builder.add_service::<ServiceB>().add_service::<ServiceA>();
+    assert_eq!(builder.unused_services(), [type_name::<ServiceB>()]);
+
+    builder.add_service::<StandaloneService>();
+    assert_eq!(
+        builder.unused_services(),
+        [type_name::<ServiceB>(), type_name::<StandaloneService>()]
+    );
+
+    builder.warn_unused_services().build().await.unwrap();
+}
+
 #[tokio::test]
 async fn test_services() {
     App::builder()
@@ -178,6 +349,98 @@ async fn test_services_bad() {
     ));
 }
 
+struct LateComponent(&'static str);
+
+struct LatePlugin;
+
+impl Plugin for LatePlugin {
+    async fn build(&self, ctx: &AppContext) -> Result<(), StdError> {
+        ctx.add_component(LateComponent("late"));
+        Ok(())
+    }
+
+    fn dependencies(&self) -> Dependencies {
+        Dependencies::new().service::<ReadyService>()
+    }
+}
+
+struct ReadyService {
+    late: OnceLock<&'static str>,
+}
+
+impl Service for ReadyService {
+    type Handle = Arc<Self>;
+
+    async fn build(ctx: &AppContext) -> Result<Arc<Self>, StdError> {
+        assert!(!ctx.has_component::<LateComponent>());
+        Ok(Arc::new(Self {
+            late: OnceLock::new(),
+        }))
+    }
+
+    async fn ready(handle: &Arc<Self>, app: &App) -> Result<(), StdError> {
+        let late = app
+            .get_component_ref::<LateComponent>()
+            .ok_or("LateComponent is missing")?;
+        handle.late.set(late.0).unwrap();
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_service_ready() {
+    let app = App::builder()
+        .add_service::<ReadyService>()
+        .add_plugin(LatePlugin)
+        .build()
+        .await
+        .unwrap();
+    let service = app.get_component::<Arc<ReadyService>>().unwrap();
+    assert_eq!(service.late.get(), Some(&"late"));
+}
+
+#[tokio::test]
+async fn test_service_ready_error() {
+    let result = App::builder().add_service::<ReadyService>().build().await;
+    let Err(AppError::PluginError(err)) = result else {
+        panic!("expected plugin error")
+    };
+    assert_eq!(err.to_string(), "LateComponent is missing");
+}
+
+struct HookPlugin;
+
+impl Plugin for HookPlugin {
+    async fn build(&self, ctx: &AppContext) -> Result<(), StdError> {
+        ctx.add_ready_hook(|app| {
+            app.get_component_ref::<LateComponent>()
+                .ok_or("LateComponent is missing in hook")?;
+            Ok(())
+        });
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_ready_hook() {
+    App::builder()
+        .add_plugin(HookPlugin)
+        .add_service::<ReadyService>()
+        .add_plugin(LatePlugin)
+        .build()
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_ready_hook_error() {
+    let result = App::builder().add_plugin(HookPlugin).build().await;
+    let Err(AppError::PluginError(err)) = result else {
+        panic!("expected plugin error")
+    };
+    assert_eq!(err.to_string(), "LateComponent is missing in hook");
+}
+
 #[tokio::test]
 async fn test_error_circular_dependency_message() {
     let result = App::builder()
@@ -228,6 +491,26 @@ fn test_dependencies_default_and_merge() {
     let _ = deps_a.merge(deps_b);
 }
 
+#[test]
+fn test_dependencies_iter() {
+    let deps = Dependencies::new();
+    assert!(deps.is_empty());
+    assert_eq!(deps.len(), 0);
+    assert_eq!(deps.iter().count(), 0);
+
+    let deps = deps
+        .plugin::<PluginA>()
+        .plugin::<PluginB>()
+        .plugin::<PluginA>();
+    assert!(!deps.is_empty());
+    assert_eq!(deps.len(), 2);
+    let mut type_ids: Vec<_> = deps.iter().collect();
+    type_ids.sort();
+    let mut expected = vec![TypeId::of::<PluginA>(), TypeId::of::<PluginB>()];
+    expected.sort();
+    assert_eq!(type_ids, expected);
+}
+
 #[tokio::test]
 async fn test_component_extract() {
     let mut builder = App::builder();
@@ -310,6 +593,60 @@ async fn test_app_has_component() {
     assert!(!app.has_component::<String>());
 }
 
+#[tokio::test]
+async fn test_app_into_handle() {
+    let app = App::builder()
+        .add_component(42i32)
+        .build()
+        .await
+        .unwrap();
+    assert!(app.handle().is_none());
+
+    let app = app.into_handle();
+    let handle = app.handle().unwrap();
+    assert!(Arc::ptr_eq(&app, &handle));
+    assert_eq!(handle.get_component::<i32>(), Some(42));
+
+    let weak = Arc::downgrade(&app);
+    drop((app, handle));
+    assert!(weak.upgrade().is_none());
+}
+
+#[tokio::test]
+async fn test_app_handle_component() {
+    struct LateService {
+        app: AppHandle,
+    }
+
+    impl Service for LateService {
+        type Handle = Arc<Self>;
+
+        async fn build(ctx: &AppContext) -> Result<Self::Handle, StdError> {
+            let app = ctx.get_component::<AppHandle>().ok_or("AppHandle is missing")?;
+            assert!(app.get().is_none());
+            Ok(Arc::new(Self { app }))
+        }
+    }
+
+    let app = App::builder()
+        .add_component(42i32)
+        .add_service::<LateService>()
+        .build()
+        .await
+        .unwrap();
+    let service = app.get_component::<Arc<LateService>>().unwrap();
+    assert!(service.app.get().is_none());
+
+    let app = app.into_handle();
+    let handle = service.app.get().unwrap();
+    assert!(Arc::ptr_eq(&app, &handle));
+    assert_eq!(handle.get_component::<i32>(), Some(42));
+
+    // The handle is weak: it does not keep the app alive.
+    drop((app, handle));
+    assert!(service.app.get().is_none());
+}
+
 #[tokio::test]
 async fn test_app_get_component_ref() {
     let app = App::builder()
@@ -320,3 +657,350 @@ async fn test_app_get_component_ref() {
     let r = app.get_component_ref::<String>().unwrap();
     assert_eq!(r, "hello");
 }
+
+#[tokio::test]
+async fn test_app_get_component_or_else() {
+    let app = App::builder().add_component(42i32).build().await.unwrap();
+    assert_eq!(app.get_component_or_else(|| 0i32), 42);
+    assert_eq!(app.get_component_or_else(|| "fallback".to_string()), "fallback");
+}
+
+#[tokio::test]
+async fn test_service_retry_policy() {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+
+    static FLAKY_ATTEMPTS: AtomicU32 = AtomicU32::new(0);
+    static BROKEN_ATTEMPTS: AtomicU32 = AtomicU32::new(0);
+
+    struct FlakyService;
+
+    impl Service for FlakyService {
+        type Handle = Arc<Self>;
+
+        async fn build(_ctx: &AppContext) -> Result<Self::Handle, StdError> {
+            if FLAKY_ATTEMPTS.fetch_add(1, Ordering::SeqCst) < 2 {
+                return Err("database is not reachable".into());
+            }
+            Ok(Arc::new(Self))
+        }
+
+        fn retry_policy() -> Option<RetryPolicy> {
+            Some(RetryPolicy::new(3).with_initial_backoff(Duration::from_millis(1)))
+        }
+    }
+
+    struct BrokenService;
+
+    impl Service for BrokenService {
+        type Handle = Arc<Self>;
+
+        async fn build(_ctx: &AppContext) -> Result<Self::Handle, StdError> {
+            BROKEN_ATTEMPTS.fetch_add(1, Ordering::SeqCst);
+            Err("database is not reachable".into())
+        }
+
+        fn retry_policy() -> Option<RetryPolicy> {
+            Some(RetryPolicy::new(2).with_initial_backoff(Duration::from_millis(1)))
+        }
+    }
+
+    let app = App::builder()
+        .add_service::<FlakyService>()
+        .build()
+        .await
+        .unwrap();
+    assert!(app.has_component::<Arc<FlakyService>>());
+    assert_eq!(FLAKY_ATTEMPTS.load(Ordering::SeqCst), 3);
+
+    let result = App::builder().add_service::<BrokenService>().build().await;
+    let Err(AppError::PluginError(err)) = result else {
+        panic!("expected plugin error");
+    };
+    assert_eq!(err.to_string(), "database is not reachable");
+    assert_eq!(BROKEN_ATTEMPTS.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_app_scope() {
+    #[derive(Clone, Debug, PartialEq)]
+    struct CurrentUser(&'static str);
+
+    let app = App::builder()
+        .add_component(42i32)
+        .add_component("app".to_string())
+        .build()
+        .await
+        .unwrap();
+
+    let mut scope = app.scope();
+    scope.add_component(CurrentUser("alice"));
+    scope.add_component("scope".to_string());
+
+    assert_eq!(scope.get_component::<CurrentUser>(), Some(CurrentUser("alice")));
+    assert_eq!(scope.get_component::<i32>(), Some(42));
+    assert!(scope.has_component::<i32>());
+    assert!(!scope.has_component::<u64>());
+    assert_eq!(scope.get_component_ref::<String>().unwrap(), "scope");
+    assert_eq!(scope.app().get_component::<String>().unwrap(), "app");
+
+    let other = app.scope();
+    assert!(!other.has_component::<CurrentUser>());
+    assert!(!app.has_component::<CurrentUser>());
+}
+
+#[tokio::test]
+async fn test_add_service_instance() {
+    struct ExternalService(&'static str);
+
+    impl Service for ExternalService {
+        type Handle = Arc<Self>;
+
+        async fn build(_ctx: &AppContext) -> Result<Self::Handle, StdError> {
+            Err("must not be built".into())
+        }
+
+        fn dependencies() -> Dependencies {
+            Dependencies::new().service::<ServiceA>()
+        }
+    }
+
+    struct DependentService(Arc<ExternalService>);
+
+    impl Service for DependentService {
+        type Handle = Arc<Self>;
+
+        async fn build(ctx: &AppContext) -> Result<Self::Handle, StdError> {
+            let external = ctx
+                .get_component::<Arc<ExternalService>>()
+                .ok_or("ExternalService is missing")?;
+            Ok(Arc::new(Self(external)))
+        }
+
+        fn dependencies() -> Dependencies {
+            Dependencies::new().service::<ExternalService>()
+        }
+    }
+
+    let mut builder = App::builder();
+    builder
+        .add_service::<DependentService>()
+        .add_service_instance::<ExternalService>(Arc::new(ExternalService("mock")));
+    assert!(builder.has_service::<ExternalService>());
+    let app = builder.build().await.unwrap();
+
+    let dependent = app.get_component::<Arc<DependentService>>().unwrap();
+    assert_eq!(dependent.0.0, "mock");
+}
+
+#[tokio::test]
+async fn test_app_merge() {
+    #[derive(Default)]
+    struct Registry(Vec<&'static str>);
+
+    impl MergeComponent for Registry {
+        fn merge(&mut self, other: Self) -> Result<(), StdError> {
+            self.0.extend(other.0);
+            Ok(())
+        }
+    }
+
+    async fn build_app(name: &'static str, value: u32) -> App {
+        App::builder()
+            .add_merge_component(Registry(vec![name]))
+            .add_component(value)
+            .add_component(name)
+            .build()
+            .await
+            .unwrap()
+    }
+
+    let mut app = build_app("core", 1).await;
+    let extension = App::builder()
+        .add_merge_component(Registry(vec!["extension"]))
+        .add_component(String::from("extension"))
+        .build()
+        .await
+        .unwrap();
+    app.merge(extension).unwrap();
+    assert_eq!(app.get_component_ref::<Registry>().unwrap().0, ["core", "extension"]);
+    assert_eq!(app.get_component::<String>().unwrap(), "extension");
+
+    // Conflicts are reported before anything is moved.
+    let err = app.merge(build_app("other", 2).await).unwrap_err();
+    assert!(matches!(err, AppError::ComponentConflict(_)));
+    assert_eq!(app.get_component_ref::<Registry>().unwrap().0, ["core", "extension"]);
+    assert_eq!(app.get_component::<u32>().unwrap(), 1);
+
+    app.merge_overwrite(build_app("other", 2).await).unwrap();
+    assert_eq!(
+        app.get_component_ref::<Registry>().unwrap().0,
+        ["core", "extension", "other"]
+    );
+    assert_eq!(app.get_component::<u32>().unwrap(), 2);
+    assert_eq!(app.get_component::<&'static str>().unwrap(), "other");
+}
+
+#[tokio::test]
+async fn test_get_or_insert_component() {
+    #[derive(Default)]
+    struct Registry(Vec<&'static str>);
+
+    let mut builder = App::builder();
+    builder.get_or_insert_component::<Registry>().0.push("first");
+    builder
+        .get_or_insert_component_with(|| Registry(vec!["unused"]))
+        .0
+        .push("second");
+    builder.get_or_insert_component_with(|| 42u32);
+    let app = builder.build().await.unwrap();
+
+    assert_eq!(app.get_component_ref::<Registry>().unwrap().0, ["first", "second"]);
+    assert_eq!(app.get_component::<u32>(), Some(42));
+}
+
+#[tokio::test]
+async fn test_add_plugin_once() {
+    #[derive(Default)]
+    struct CounterPlugin;
+
+    impl Plugin for CounterPlugin {
+        async fn build(&self, ctx: &AppContext) -> Result<(), StdError> {
+            *ctx.get_or_insert_component::<u32>() += 1;
+            Ok(())
+        }
+    }
+
+    fn add_bundle(builder: &mut diode::AppBuilder) {
+        builder.add_plugin_once::<CounterPlugin>();
+    }
+
+    let mut builder = App::builder();
+    add_bundle(&mut builder);
+    add_bundle(&mut builder);
+    assert!(!builder.ensure_plugin(CounterPlugin));
+    assert!(builder.ensure_plugin(PluginA));
+    assert!(builder.has_plugin::<PluginA>());
+    let app = builder.build().await.unwrap();
+    assert_eq!(app.get_component::<u32>(), Some(1));
+}
+
+#[tokio::test]
+async fn test_plugin_error_kind() {
+    struct CachePlugin;
+
+    impl Plugin for CachePlugin {
+        async fn build(&self, _ctx: &AppContext) -> Result<(), StdError> {
+            Err(PluginError::unavailable("Connection refused").into())
+        }
+    }
+
+    let err = App::builder()
+        .add_plugin(CachePlugin)
+        .build()
+        .await
+        .err()
+        .unwrap();
+    match &err {
+        AppError::PluginFailed {
+            plugin,
+            error: PluginError::Unavailable(source),
+        } => {
+            assert_eq!(*plugin, type_name::<CachePlugin>());
+            assert_eq!(source.to_string(), "Connection refused");
+        }
+        _ => panic!("Unexpected error: {err:?}"),
+    }
+    assert!(err.to_string().contains("(dependency unavailable): Connection refused"));
+    assert!(err.source().is_some());
+
+    // Plain errors are not classified.
+    let err = App::builder()
+        .add_plugin(BadPlugin)
+        .build()
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(err, AppError::PluginError(_)), "{err:?}");
+}
+
+#[tokio::test]
+async fn test_get_or_build_service() {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static POOL_BUILDS: AtomicU32 = AtomicU32::new(0);
+
+    struct Pool;
+
+    impl Service for Pool {
+        type Handle = Arc<Self>;
+
+        async fn build(_ctx: &AppContext) -> Result<Self::Handle, StdError> {
+            POOL_BUILDS.fetch_add(1, Ordering::SeqCst);
+            Ok(Arc::new(Self))
+        }
+    }
+
+    struct Users(Arc<Pool>);
+
+    impl Service for Users {
+        type Handle = Arc<Self>;
+
+        async fn build(ctx: &AppContext) -> Result<Self::Handle, StdError> {
+            Ok(Arc::new(Self(ctx.get_or_build_service::<Pool>().await?)))
+        }
+    }
+
+    struct Orders(Arc<Pool>);
+
+    impl Service for Orders {
+        type Handle = Arc<Self>;
+
+        async fn build(ctx: &AppContext) -> Result<Self::Handle, StdError> {
+            Ok(Arc::new(Self(ctx.get_or_build_service::<Pool>().await?)))
+        }
+    }
+
+    let app = App::builder()
+        .add_service::<Users>()
+        .add_service::<Orders>()
+        .build()
+        .await
+        .unwrap();
+    let users = app.get_component::<Arc<Users>>().unwrap();
+    let orders = app.get_component::<Arc<Orders>>().unwrap();
+    assert!(Arc::ptr_eq(&users.0, &orders.0));
+    assert_eq!(POOL_BUILDS.load(Ordering::SeqCst), 1);
+    // Inline services are not components.
+    assert!(!app.has_component::<Arc<Pool>>());
+
+    // A registered service is never built inline: its component is returned
+    // once it has been built.
+    struct Audit(Arc<Users>);
+
+    impl Service for Audit {
+        type Handle = Arc<Self>;
+
+        async fn build(ctx: &AppContext) -> Result<Self::Handle, StdError> {
+            Ok(Arc::new(Self(ctx.get_or_build_service::<Users>().await?)))
+        }
+
+        fn dependencies() -> Dependencies {
+            Dependencies::new().service::<Users>()
+        }
+    }
+
+    let app = App::builder()
+        .add_service::<Users>()
+        .add_service::<Audit>()
+        .build()
+        .await
+        .unwrap();
+    let users = app.get_component::<Arc<Users>>().unwrap();
+    assert!(Arc::ptr_eq(&users, &app.get_component::<Arc<Audit>>().unwrap().0));
+
+    let mut builder = App::builder();
+    builder.add_service::<Users>();
+    let err = builder.get_or_build_service::<Users>().await.err().unwrap();
+    assert!(err.to_string().contains("declare it as a dependency"), "{err}");
+}
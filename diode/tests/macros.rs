@@ -1,4 +1,7 @@
-use diode::{AddServiceExt as _, App, AppContext, Component, Service, StdError, service};
+use diode::{
+    AddServiceExt as _, App, AppContext, AppError, AsyncExtract, Component, Dependencies, Env,
+    EnvVar, Interface, Plugin, Service, ServiceDependencyExt as _, StdError, service,
+};
 use std::sync::Arc;
 
 #[derive(Clone, Default)]
@@ -277,3 +280,408 @@ async fn test_factory_with_custom_error() {
     let service = app.get_component::<Arc<ServiceWithCustomError>>().unwrap();
     assert!(Arc::strong_count(&service) >= 1);
 }
+
+#[derive(Clone)]
+struct StorageMode(&'static str);
+
+enum Storage {
+    Memory,
+    Disk(&'static str),
+}
+
+#[service]
+impl Storage {
+    fn is_memory(ctx: &AppContext) -> bool {
+        ctx.get_component_ref::<StorageMode>()
+            .is_some_and(|v| v.0 == "memory")
+    }
+
+    #[factory(when = Self::is_memory)]
+    fn memory() -> Arc<Self> {
+        Arc::new(Self::Memory)
+    }
+
+    #[factory]
+    fn disk(#[inject(Component)] mode: StorageMode) -> Arc<Self> {
+        Arc::new(Self::Disk(mode.0))
+    }
+}
+
+#[tokio::test]
+async fn test_factory_variants() {
+    let app = App::builder()
+        .add_component(StorageMode("memory"))
+        .add_service::<Storage>()
+        .build()
+        .await
+        .unwrap();
+    let storage = app.get_component::<Arc<Storage>>().unwrap();
+    assert!(matches!(*storage, Storage::Memory));
+
+    let app = App::builder()
+        .add_component(StorageMode("/var/lib/data"))
+        .add_service::<Storage>()
+        .build()
+        .await
+        .unwrap();
+    let storage = app.get_component::<Arc<Storage>>().unwrap();
+    assert!(matches!(*storage, Storage::Disk("/var/lib/data")));
+}
+
+struct OnlyConditional;
+
+#[service]
+impl OnlyConditional {
+    #[factory(when = |_: &AppContext| false)]
+    fn new() -> Arc<Self> {
+        Arc::new(Self)
+    }
+}
+
+#[tokio::test]
+async fn test_factory_variants_no_match() {
+    let result = App::builder()
+        .add_service::<OnlyConditional>()
+        .build()
+        .await;
+    let err = result.err().unwrap().to_string();
+    assert!(err.contains("No factory of"), "{err}");
+}
+
+#[derive(Service)]
+#[service(handle = CounterHandle)]
+struct Counter {
+    #[inject(Component)]
+    config: Config,
+}
+
+#[derive(Clone)]
+struct CounterHandle(Arc<Counter>);
+
+impl From<Counter> for CounterHandle {
+    fn from(value: Counter) -> Self {
+        Self(Arc::new(value))
+    }
+}
+
+#[derive(Service)]
+#[service(handle = "Arc<dyn Named>", wrap = named_greeter)]
+struct Greeter {
+    #[allow(unused)]
+    factory: Arc<ServiceWithFactory>,
+}
+
+trait Named: Send + Sync {
+    fn name(&self) -> &'static str;
+}
+
+impl Named for Greeter {
+    fn name(&self) -> &'static str {
+        "greeter"
+    }
+}
+
+fn named_greeter(greeter: Greeter) -> Arc<dyn Named> {
+    Arc::new(greeter)
+}
+
+#[tokio::test]
+async fn test_derive_custom_handle() {
+    let app = App::builder()
+        .add_component(Config { valid: true })
+        .add_service::<Counter>()
+        .add_service::<SimpleService>()
+        .add_service::<ServiceWithFactory>()
+        .add_service::<Greeter>()
+        .build()
+        .await
+        .unwrap();
+    let counter = app.get_component::<CounterHandle>().unwrap();
+    assert!(counter.0.config.valid);
+    let greeter = app.get_component::<Arc<dyn Named>>().unwrap();
+    assert_eq!(greeter.name(), "greeter");
+}
+
+struct WorkerCount;
+
+impl EnvVar for WorkerCount {
+    const NAME: &'static str = "DIODE_TEST_WORKER_COUNT";
+}
+
+struct MissingToken;
+
+impl EnvVar for MissingToken {
+    const NAME: &'static str = "DIODE_TEST_MISSING_TOKEN";
+}
+
+#[derive(Service)]
+struct Workers {
+    #[inject(Env<WorkerCount>)]
+    count: usize,
+}
+
+#[derive(Service)]
+struct TokenClient {
+    #[allow(unused)]
+    #[inject(Env<MissingToken>)]
+    token: String,
+}
+
+#[tokio::test]
+async fn test_inject_env() {
+    // SAFETY: no other test reads or writes this variable.
+    unsafe { std::env::set_var(WorkerCount::NAME, "4") };
+    let app = App::builder()
+        .add_service::<Workers>()
+        .build()
+        .await
+        .unwrap();
+    assert_eq!(app.get_component::<Arc<Workers>>().unwrap().count, 4);
+
+    // SAFETY: as above.
+    unsafe { std::env::set_var(WorkerCount::NAME, "four") };
+    let result = App::builder().add_service::<Workers>().build().await;
+    let err = result.err().unwrap().to_string();
+    assert!(
+        err.contains("Environment variable DIODE_TEST_WORKER_COUNT is invalid"),
+        "{err}"
+    );
+
+    let result = App::builder().add_service::<TokenClient>().build().await;
+    let err = result.err().unwrap().to_string();
+    assert!(
+        err.contains("Environment variable DIODE_TEST_MISSING_TOKEN is not set"),
+        "{err}"
+    );
+}
+
+trait Store: Send + Sync {
+    fn name(&self) -> &'static str;
+}
+
+#[derive(Service)]
+struct MemoryStore;
+
+impl Store for MemoryStore {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+}
+
+#[derive(Service)]
+struct MockStore;
+
+impl Store for MockStore {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+}
+
+#[derive(Service)]
+struct Repository {
+    #[inject(Interface)]
+    store: Arc<dyn Store>,
+}
+
+#[tokio::test]
+async fn test_service_as_interface() {
+    // The consumer is built after the implementation it does not name.
+    let app = App::builder()
+        .add_service::<Repository>()
+        .add_service_as::<MemoryStore, dyn Store>(|service| service)
+        .build()
+        .await
+        .unwrap();
+    let repository = app.get_component::<Arc<Repository>>().unwrap();
+    assert_eq!(repository.store.name(), "memory");
+    assert!(app.has_component::<Arc<MemoryStore>>());
+
+    let app = App::builder()
+        .add_service::<Repository>()
+        .add_service_as::<MockStore, dyn Store>(|service| service)
+        .build()
+        .await
+        .unwrap();
+    let repository = app.get_component::<Arc<Repository>>().unwrap();
+    assert_eq!(repository.store.name(), "mock");
+
+    let result = App::builder().add_service::<Repository>().build().await;
+    let err = result.err().unwrap().to_string();
+    assert!(err.contains("Missing dependencies"), "{err}");
+}
+
+#[derive(Service)]
+#[service(no_component)]
+struct Warmup {
+    #[inject(Component)]
+    warmed: Arc<std::sync::atomic::AtomicBool>,
+}
+
+impl Drop for Warmup {
+    fn drop(&mut self) {
+        self.warmed.store(true, std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+#[tokio::test]
+async fn test_derive_no_component() {
+    let warmed = Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let app = App::builder()
+        .add_component(warmed.clone())
+        .add_service::<Warmup>()
+        .build()
+        .await
+        .unwrap();
+    assert!(!app.has_component::<Arc<Warmup>>());
+    // The handle is dropped once the build is over.
+    assert!(warmed.load(std::sync::atomic::Ordering::SeqCst));
+}
+
+#[derive(Clone)]
+struct Routes(Vec<&'static str>);
+
+struct RoutesPlugin;
+
+impl Plugin for RoutesPlugin {
+    async fn build(&self, ctx: &AppContext) -> Result<(), StdError> {
+        ctx.add_component(Routes(vec!["/health"]));
+        Ok(())
+    }
+}
+
+#[derive(Service)]
+#[service(after = RoutesPlugin)]
+struct RouteAudit {
+    #[inject(Component)]
+    routes: Routes,
+}
+
+#[tokio::test]
+async fn test_derive_after_plugin() {
+    // The service is added first, but still builds after the plugin.
+    let app = App::builder()
+        .add_service::<RouteAudit>()
+        .add_plugin(RoutesPlugin)
+        .build()
+        .await
+        .unwrap();
+    let audit = app.get_component::<Arc<RouteAudit>>().unwrap();
+    assert_eq!(audit.routes.0, ["/health"]);
+}
+
+#[derive(Service)]
+struct RefInjected {
+    #[inject_ref(Component)]
+    config: Config,
+}
+
+#[tokio::test]
+async fn test_derive_inject_ref() {
+    let app = App::builder()
+        .add_component(Config { valid: true })
+        .add_service::<RefInjected>()
+        .build()
+        .await
+        .unwrap();
+    let service = app.get_component::<Arc<RefInjected>>().unwrap();
+    assert!(service.config.valid);
+
+    let err = App::builder()
+        .add_service::<RefInjected>()
+        .build()
+        .await
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("Config"), "{err}");
+}
+
+#[derive(Clone)]
+struct ApiUrl(&'static str);
+
+#[derive(Plugin)]
+struct ApiPlugin {
+    #[provide]
+    url: ApiUrl,
+    #[provide]
+    timeout: std::time::Duration,
+    #[allow(unused)]
+    retries: usize,
+}
+
+#[derive(Plugin)]
+#[depends(ApiPlugin, RoutesPlugin)]
+struct ClientPlugin;
+
+#[tokio::test]
+async fn test_derive_plugin() {
+    let app = App::builder()
+        .add_plugin(ClientPlugin)
+        .add_plugin(ApiPlugin {
+            url: ApiUrl("http://localhost"),
+            timeout: std::time::Duration::from_secs(5),
+            retries: 3,
+        })
+        .add_plugin(RoutesPlugin)
+        .build()
+        .await
+        .unwrap();
+    assert_eq!(app.get_component::<ApiUrl>().unwrap().0, "http://localhost");
+    assert_eq!(
+        app.get_component::<std::time::Duration>(),
+        Some(std::time::Duration::from_secs(5))
+    );
+    assert!(!app.has_component::<usize>());
+    // The dependencies are built first.
+    assert_eq!(
+        app.init_order().last(),
+        Some(&std::any::type_name::<ClientPlugin>())
+    );
+}
+
+struct Connection(String);
+
+struct Connect;
+
+impl AsyncExtract<Connection> for Connect {
+    async fn extract(ctx: &AppContext) -> Result<Connection, AppError> {
+        tokio::task::yield_now().await;
+        let custom = ctx
+            .get_component::<Box<str>>()
+            .ok_or(AppError::MissingComponent("Box<str>"))?;
+        Ok(Connection(format!("connected to {custom}")))
+    }
+
+    fn dependencies() -> Dependencies {
+        Dependencies::new().service::<CustomService>()
+    }
+}
+
+struct PooledRepository {
+    connection: Connection,
+}
+
+#[service]
+impl PooledRepository {
+    #[factory]
+    fn new(
+        #[inject(Component)] config: &Config,
+        #[inject_async(Connect)] connection: Connection,
+    ) -> Arc<Self> {
+        assert!(config.valid);
+        Arc::new(Self { connection })
+    }
+}
+
+#[tokio::test]
+async fn test_factory_inject_async() {
+    // The extractor's dependencies are built first.
+    let app = App::builder()
+        .add_component(Config { valid: true })
+        .add_service::<PooledRepository>()
+        .add_service::<CustomService>()
+        .build()
+        .await
+        .unwrap();
+    let repository = app.get_component::<Arc<PooledRepository>>().unwrap();
+    assert_eq!(repository.connection.0, "connected to CustomService");
+}